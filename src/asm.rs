@@ -0,0 +1,924 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+use system::{OpCode, Address, Byte, Word, RegisterIndex, PROGRAM_ADDRESS};
+
+/// How deeply `INCLUDE` directives may nest before the assembler gives up.
+const MAX_INCLUDE_DEPTH: usize = 16;
+/// How deeply constants may refer to other constants, which catches definitions that loop.
+const MAX_SYMBOL_DEPTH: usize = 64;
+/// One past the last address a program can occupy.
+const MEMORY_END: i64 = 0x10000;
+
+/// Operands that are written as bare words rather than values.
+const KEYWORDS: [&str; 9] = ["I", "[I]", "DT", "ST", "K", "F", "HF", "B", "R"];
+
+/// Every instruction mnemonic, to tell an unknown instruction from a misused one.
+const MNEMONICS: [&str; 33] = [
+    "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+    "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW",
+    "HIGH", "SAVE", "LOAD", "PLANE", "AUDIO", "PITCH", "LONG"
+];
+
+/// An error in assembly source, along with where it was found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// The file holding the error, or `<source>` for source given as a string.
+    pub file: String,
+    /// Line number counting from 1, or 0 if the file could not be read at all.
+    pub line: usize,
+    /// Column number counting from 1.
+    pub column: usize,
+    /// What went wrong.
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+/// An assembled program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    /// The address the first byte of the ROM belongs at.
+    pub origin: Address,
+    /// The ROM image, with any gaps left by `ORG` filled with zeroes.
+    pub rom: Vec<Byte>,
+    /// The address of every label.
+    pub labels: BTreeMap<String, Address>
+}
+
+/// Assemble source held in a string. Included files are found relative to the working
+/// directory.
+///
+/// The syntax is the one `OpCode` is displayed in, so `LD V0, #64` and `DRW V0, V1, #5`, with
+/// `label:` definitions, `NAME EQU expr` (or `NAME = expr`) constants and the `ORG`, `DB`, `DW`
+/// and `INCLUDE "file"` directives. Operands can be expressions over numbers, labels, constants
+/// and `$`, the address of the current line, using `+ - * / % & | ^ ~ << >>` and parentheses.
+/// Numbers are decimal, or hex after `#`, `$` or `0x`, or binary after `0b`. Comments start
+/// with `;`.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut assembler = Assembler::default();
+    assembler.parse("<source>".to_string(), source, Path::new(""), 0)
+        .and_then(|_| assembler.build())
+        .map_err(|fault| assembler.error(fault))
+}
+
+/// Assemble a source file, as `assemble` does. Included files are found relative to the file
+/// including them.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Program, AsmError> {
+    let path = path.as_ref();
+    let source = read_source(path).map_err(|error| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        column: 0,
+        message: error.to_string()
+    })?;
+
+    let mut assembler = Assembler::default();
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    assembler.parse(path.display().to_string(), &source, directory, 0)
+        .and_then(|_| assembler.build())
+        .map_err(|fault| assembler.error(fault))
+}
+
+fn read_source(path: &Path) -> io::Result<String> {
+    let mut source = String::new();
+    File::open(path)?.read_to_string(&mut source)?;
+    Ok(source)
+}
+
+/// A line of one of the files being assembled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Location {
+    file: usize,
+    line: usize
+}
+
+/// An error found while assembling, before the file it was found in is named.
+#[derive(Clone, Debug)]
+struct Fault {
+    location: Location,
+    column: usize,
+    message: String
+}
+
+/// A lexical token, each kept alongside the column it starts at.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Text(String),
+    /// `$`, the address of the current line.
+    Here,
+    Symbol(&'static str)
+}
+
+/// Punctuation and operators, longest first so `<<` is not read as two `<`.
+const SYMBOLS: [&str; 18] = [
+    "<<", ">>", ",", ":", "(", ")", "[", "]", "+", "-", "*", "/", "%", "&", "|", "^", "~", "="
+];
+
+/// Split a line into tokens, stopping at a comment. Errors give the column at fault.
+fn lex(line: &str) -> Result<Vec<(Token, usize)>, (usize, String)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    // the characters from `start` that could belong to a number or name
+    let word_end = |start: usize| {
+        let mut end = start;
+        while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_'
+                                    || chars[end] == '.') {
+            end += 1;
+        }
+        end
+    };
+
+    while i < chars.len() {
+        let column = i + 1;
+        let c = chars[i];
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err((column, "unterminated string".to_string())),
+                    Some(&'"') => break,
+                    Some(&'\\') => {
+                        match chars.get(i + 1) {
+                            Some(&'n') => text.push('\n'),
+                            Some(&'0') => text.push('\0'),
+                            Some(&escaped) if escaped == '"' || escaped == '\\' => {
+                                text.push(escaped)
+                            },
+                            _ => return Err((i + 1, "invalid escape in string".to_string()))
+                        }
+                        i += 1;
+                    },
+                    Some(&c) => text.push(c)
+                }
+                i += 1;
+            }
+            if !text.is_ascii() {
+                return Err((column, "strings may only hold ASCII characters".to_string()));
+            }
+            tokens.push((Token::Text(text), column));
+            i += 1;
+        } else if c.is_ascii_digit() || c == '#'
+                || (c == '$' && chars.get(i + 1).is_some_and(|c| c.is_ascii_hexdigit())) {
+            let start = if c.is_ascii_digit() { i } else { i + 1 };
+            let end = word_end(start);
+            let word: String = chars[start..end].iter().filter(|&&c| c != '_').collect();
+            let lower = word.to_lowercase();
+            let number = if !c.is_ascii_digit() {
+                i64::from_str_radix(&lower, 16)
+            } else if let Some(digits) = lower.strip_prefix("0x") {
+                i64::from_str_radix(digits, 16)
+            } else if let Some(digits) = lower.strip_prefix("0b") {
+                i64::from_str_radix(digits, 2)
+            } else {
+                lower.parse()
+            };
+            match number {
+                Ok(number) => tokens.push((Token::Number(number), column)),
+                Err(_) => {
+                    let text: String = chars[i..end].iter().collect();
+                    return Err((column, format!("invalid number `{}`", text)));
+                }
+            }
+            i = end;
+        } else if c == '$' {
+            tokens.push((Token::Here, column));
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let end = word_end(i);
+            tokens.push((Token::Ident(chars[i..end].iter().collect()), column));
+            i = end;
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+                Some(symbol) => {
+                    tokens.push((Token::Symbol(symbol), column));
+                    i += symbol.len();
+                },
+                None => return Err((column, format!("unexpected character `{}`", c)))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A value to be worked out once every label is known.
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(i64),
+    /// A label or constant, with the column it was named at.
+    Symbol(String, usize),
+    Here,
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    /// An operator, the column it was written at, and its operands.
+    Binary(&'static str, usize, Box<Expr>, Box<Expr>)
+}
+
+/// How tightly a binary operator binds, or None if the symbol is not one.
+fn precedence(symbol: &str) -> Option<u8> {
+    match symbol {
+        "|"         => Some(1),
+        "^"         => Some(2),
+        "&"         => Some(3),
+        "<<" | ">>" => Some(4),
+        "+" | "-"   => Some(5),
+        "*" | "/" | "%" => Some(6),
+        _ => None
+    }
+}
+
+/// Reads an expression from a run of tokens.
+struct ExprParser<'a> {
+    tokens: &'a [(Token, usize)],
+    position: usize,
+    /// Where to report a missing value at the end of the tokens.
+    end: usize
+}
+
+impl<'a> ExprParser<'a> {
+    /// Parse all of `tokens` as a single expression.
+    fn parse(tokens: &'a [(Token, usize)], end: usize) -> Result<Expr, (usize, String)> {
+        let mut parser = ExprParser { tokens, position: 0, end };
+        let expr = parser.binary(1)?;
+        match parser.tokens.get(parser.position) {
+            Some(&(_, column)) => Err((column, "expected the end of the operand".to_string())),
+            None => Ok(expr)
+        }
+    }
+
+    fn binary(&mut self, min: u8) -> Result<Expr, (usize, String)> {
+        let mut left = self.unary()?;
+        while let Some(&(Token::Symbol(symbol), column)) = self.tokens.get(self.position) {
+            let binding = match precedence(symbol) {
+                Some(binding) if binding >= min => binding,
+                _ => break
+            };
+            self.position += 1;
+            let right = self.binary(binding + 1)?;
+            left = Expr::Binary(symbol, column, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, (usize, String)> {
+        let (token, column) = match self.tokens.get(self.position) {
+            Some(&(ref token, column)) => (token.clone(), column),
+            None => return Err((self.end, "expected a value".to_string()))
+        };
+        self.position += 1;
+
+        match token {
+            Token::Number(number) => Ok(Expr::Number(number)),
+            Token::Ident(name) => Ok(Expr::Symbol(name, column)),
+            Token::Here => Ok(Expr::Here),
+            Token::Symbol("-") => Ok(Expr::Negate(Box::new(self.unary()?))),
+            Token::Symbol("~") => Ok(Expr::Not(Box::new(self.unary()?))),
+            Token::Symbol("+") => self.unary(),
+            Token::Symbol("(") => {
+                let expr = self.binary(1)?;
+                match self.tokens.get(self.position) {
+                    Some(&(Token::Symbol(")"), _)) => {
+                        self.position += 1;
+                        Ok(expr)
+                    },
+                    Some(&(_, column)) => Err((column, "expected `)`".to_string())),
+                    None => Err((self.end, "expected `)`".to_string()))
+                }
+            },
+            _ => Err((column, "expected a value".to_string()))
+        }
+    }
+}
+
+/// How many bits a value must fit in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Width {
+    Nibble,
+    Byte,
+    Address,
+    Word
+}
+
+impl Width {
+    /// The smallest and largest values allowed, and a name for error messages. Bytes and words
+    /// may be negative, and are stored in two's complement.
+    pub(crate) fn range(self) -> (i64, i64, &'static str) {
+        match self {
+            Width::Nibble   => (0, 0xF, "a nibble"),
+            Width::Byte     => (-0x80, 0xFF, "a byte"),
+            Width::Address  => (0, 0xFFF, "an address"),
+            Width::Word     => (-0x8000, 0xFFFF, "a word")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum OperandKind {
+    Register(RegisterIndex),
+    Keyword(&'static str),
+    Value(Expr),
+    /// The address following `LONG` in XO-CHIP's `LD I, LONG NNNN`.
+    Long(Expr),
+    Text(String)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Operand {
+    kind: OperandKind,
+    column: usize
+}
+
+/// What an operand looks like, for picking the instruction it belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Shape {
+    Register(RegisterIndex),
+    Keyword(&'static str),
+    Value,
+    Long,
+    Text
+}
+
+impl Operand {
+    fn parse(tokens: &[(Token, usize)], end: usize) -> Result<Operand, (usize, String)> {
+        let column = tokens[0].1;
+        let name = name_of(&tokens[0].0).to_uppercase();
+
+        let kind = if tokens.len() == 1 && register(&name).is_some() {
+            OperandKind::Register(register(&name).unwrap())
+        } else if tokens.len() == 1 && KEYWORDS.contains(&name.as_str()) {
+            OperandKind::Keyword(KEYWORDS.iter().find(|&&keyword| keyword == name).unwrap())
+        } else if tokens.len() == 3 && tokens[0].0 == Token::Symbol("[")
+                && name_of(&tokens[1].0).eq_ignore_ascii_case("I")
+                && tokens[2].0 == Token::Symbol("]") {
+            OperandKind::Keyword("[I]")
+        } else if name == "LONG" && tokens.len() > 1 {
+            OperandKind::Long(ExprParser::parse(&tokens[1..], end)?)
+        } else if let (1, Token::Text(ref text)) = (tokens.len(), &tokens[0].0) {
+            OperandKind::Text(text.clone())
+        } else {
+            OperandKind::Value(ExprParser::parse(tokens, end)?)
+        };
+
+        Ok(Operand { kind, column })
+    }
+
+    fn shape(&self) -> Shape {
+        match self.kind {
+            OperandKind::Register(x)        => Shape::Register(x),
+            OperandKind::Keyword(keyword)   => Shape::Keyword(keyword),
+            OperandKind::Value(_)           => Shape::Value,
+            OperandKind::Long(_)            => Shape::Long,
+            OperandKind::Text(_)            => Shape::Text
+        }
+    }
+}
+
+/// The text of an identifier, or nothing for any other token.
+fn name_of(token: &Token) -> &str {
+    match *token {
+        Token::Ident(ref name) => name,
+        _ => ""
+    }
+}
+
+/// The register named by `V0` to `VF`, in upper case.
+fn register(name: &str) -> Option<RegisterIndex> {
+    if name.len() == 2 && name.starts_with('V') {
+        RegisterIndex::from_str_radix(&name[1..], 16).ok()
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Statement {
+    Label(String),
+    Constant(String, Expr),
+    Org(Expr),
+    Include(String),
+    Data(Width, Vec<Operand>),
+    /// An upper case mnemonic and its operands.
+    Instruction(String, Vec<Operand>)
+}
+
+/// Parse one line of source into the statements on it, each with the column it starts at.
+fn parse_line(line: &str) -> Result<Vec<(Statement, usize)>, (usize, String)> {
+    let tokens = lex(line)?;
+    let end = line.chars().count() + 1;
+    let mut statements = Vec::new();
+    let mut rest = &tokens[..];
+
+    if let [(Token::Ident(ref name), column), (Token::Symbol(":"), _), ..] = *rest {
+        statements.push((Statement::Label(name.clone()), column));
+        rest = &rest[2..];
+    }
+
+    let (name, column) = match rest.first() {
+        Some(&(Token::Ident(ref name), column)) => (name.clone(), column),
+        Some(&(_, column)) => return Err((column, "expected an instruction".to_string())),
+        None => return Ok(statements)
+    };
+    let constant = match rest.get(1) {
+        Some(&(Token::Ident(ref equ), _)) => equ.eq_ignore_ascii_case("EQU"),
+        Some(&(Token::Symbol("="), _)) => true,
+        _ => false
+    };
+    if constant {
+        statements.push((Statement::Constant(name, ExprParser::parse(&rest[2..], end)?), column));
+        return Ok(statements);
+    }
+
+    let mut operands = Vec::new();
+    if rest.len() > 1 {
+        for tokens in rest[1..].split(|(token, _)| *token == Token::Symbol(",")) {
+            if tokens.is_empty() {
+                return Err((column, "missing operand".to_string()));
+            }
+            operands.push(Operand::parse(tokens, end)?);
+        }
+    }
+
+    let mnemonic = name.to_uppercase();
+    let statement = match (mnemonic.as_str(), operands.len()) {
+        ("ORG", 1) => match operands.pop().unwrap().kind {
+            OperandKind::Value(expr) => Statement::Org(expr),
+            _ => return Err((column, "`ORG` takes an address".to_string()))
+        },
+        ("INCLUDE", 1) => match operands.pop().unwrap().kind {
+            OperandKind::Text(path) => Statement::Include(path),
+            _ => return Err((column, "`INCLUDE` takes a quoted file name".to_string()))
+        },
+        ("ORG", _) | ("INCLUDE", _) =>
+            return Err((column, format!("`{}` takes one operand", mnemonic))),
+        ("DB", _) => Statement::Data(Width::Byte, operands),
+        ("DW", _) => Statement::Data(Width::Word, operands),
+        _ => Statement::Instruction(mnemonic, operands)
+    };
+    statements.push((statement, column));
+
+    Ok(statements)
+}
+
+/// A constant's definition, kept to be worked out when it is used.
+#[derive(Clone, Debug)]
+struct Constant {
+    expr: Expr,
+    location: Location,
+    /// The address of the line defining the constant, for any `$` in it.
+    here: i64
+}
+
+/// Collects the statements of every file, then lays them out and encodes them.
+#[derive(Debug, Default)]
+struct Assembler {
+    files: Vec<String>,
+    statements: Vec<(Statement, Location, usize)>,
+    labels: HashMap<String, i64>,
+    constants: HashMap<String, Constant>
+}
+
+impl Assembler {
+    /// Attach a file name to an error.
+    fn error(&self, fault: Fault) -> AsmError {
+        AsmError {
+            file: self.files[fault.location.file].clone(),
+            line: fault.location.line,
+            column: fault.column,
+            message: fault.message
+        }
+    }
+
+    /// Read the statements of a file, and of any file it includes, into the assembler.
+    fn parse(&mut self, name: String, source: &str, directory: &Path, depth: usize)
+            -> Result<(), Fault> {
+        let file = self.files.len();
+        self.files.push(name);
+
+        for (index, line) in source.lines().enumerate() {
+            let location = Location { file, line: index + 1 };
+            let statements = parse_line(line).map_err(|(column, message)| {
+                Fault { location, column, message }
+            })?;
+
+            for (statement, column) in statements {
+                let path = match statement {
+                    Statement::Include(path) => path,
+                    statement => {
+                        self.statements.push((statement, location, column));
+                        continue;
+                    }
+                };
+
+                let fault = |message| Fault { location, column, message };
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(fault("includes are nested too deeply".to_string()));
+                }
+                let path = directory.join(path);
+                let source = read_source(&path).map_err(|error| {
+                    fault(format!("could not read {}: {}", path.display(), error))
+                })?;
+                let directory = path.parent().unwrap_or_else(|| Path::new(""));
+                self.parse(path.display().to_string(), &source, directory, depth + 1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Work out the value of an expression found at `location`.
+    fn eval(&self, expr: &Expr, here: i64, location: Location, depth: usize)
+            -> Result<i64, Fault> {
+        let fault = |column, message| Fault { location, column, message };
+        match *expr {
+            Expr::Number(number) => Ok(number),
+            Expr::Here => Ok(here),
+            Expr::Symbol(ref name, column) => {
+                if let Some(&address) = self.labels.get(name) {
+                    return Ok(address);
+                }
+                match self.constants.get(name) {
+                    Some(_) if depth >= MAX_SYMBOL_DEPTH =>
+                        Err(fault(column, format!("`{}` is defined in terms of itself", name))),
+                    Some(constant) =>
+                        self.eval(&constant.expr, constant.here, constant.location, depth + 1),
+                    None => Err(fault(column, format!("`{}` is not defined", name)))
+                }
+            },
+            Expr::Negate(ref operand) =>
+                Ok(self.eval(operand, here, location, depth)?.wrapping_neg()),
+            Expr::Not(ref operand) => Ok(!self.eval(operand, here, location, depth)?),
+            Expr::Binary(operator, column, ref left, ref right) => {
+                let left = self.eval(left, here, location, depth)?;
+                let right = self.eval(right, here, location, depth)?;
+                match operator {
+                    "/" | "%" if right == 0 => Err(fault(column, "division by zero".to_string())),
+                    "<<" | ">>" if !(0..64).contains(&right) =>
+                        Err(fault(column, format!("cannot shift by {}", right))),
+                    "+"     => Ok(left.wrapping_add(right)),
+                    "-"     => Ok(left.wrapping_sub(right)),
+                    "*"     => Ok(left.wrapping_mul(right)),
+                    "/"     => Ok(left.wrapping_div(right)),
+                    "%"     => Ok(left.wrapping_rem(right)),
+                    "&"     => Ok(left & right),
+                    "|"     => Ok(left | right),
+                    "^"     => Ok(left ^ right),
+                    "<<"    => Ok(left << right),
+                    ">>"    => Ok(left >> right),
+                    _ => unreachable!("unknown operator {}", operator)
+                }
+            }
+        }
+    }
+
+    /// Work out the value of an operand, which must fit in `width`.
+    fn value(&self, operand: &Operand, width: Width, here: i64, location: Location)
+            -> Result<i64, Fault> {
+        let fault = |message| Fault { location, column: operand.column, message };
+        let value = match operand.kind {
+            OperandKind::Value(ref expr) | OperandKind::Long(ref expr) =>
+                self.eval(expr, here, location, 0)?,
+            _ => return Err(fault("expected a value".to_string()))
+        };
+
+        let (min, max, name) = width.range();
+        if value < min || value > max {
+            return Err(fault(format!("{} does not fit in {}", value, name)));
+        }
+        Ok(value)
+    }
+
+    /// How many bytes a statement assembles to.
+    fn size(statement: &Statement) -> i64 {
+        match *statement {
+            Statement::Data(Width::Word, ref operands) => 2 * operands.len() as i64,
+            Statement::Data(_, ref operands) => operands.iter().map(|operand| {
+                match operand.kind {
+                    OperandKind::Text(ref text) => text.len() as i64,
+                    _ => 1
+                }
+            }).sum(),
+            Statement::Instruction(_, ref operands) => {
+                if operands.iter().any(|operand| operand.shape() == Shape::Long) { 4 } else { 2 }
+            },
+            _ => 0
+        }
+    }
+
+    /// Lay out every statement, define its labels and constants, then encode it.
+    fn build(&mut self) -> Result<Program, Fault> {
+        let statements = ::std::mem::take(&mut self.statements);
+        let mut address = PROGRAM_ADDRESS as i64;
+        let mut origin = None;
+        let mut placed = Vec::new();
+
+        for &(ref statement, location, column) in statements.iter() {
+            let fault = |message| Fault { location, column, message };
+            match *statement {
+                Statement::Label(ref name) | Statement::Constant(ref name, _)
+                        if self.labels.contains_key(name) || self.constants.contains_key(name) => {
+                    return Err(fault(format!("`{}` is already defined", name)));
+                },
+                Statement::Label(ref name) => {
+                    self.labels.insert(name.clone(), address);
+                },
+                Statement::Constant(ref name, ref expr) => {
+                    let constant = Constant { expr: expr.clone(), location, here: address };
+                    self.constants.insert(name.clone(), constant);
+                },
+                Statement::Org(ref expr) => {
+                    let target = self.eval(expr, address, location, 0)?;
+                    if !(0..MEMORY_END).contains(&target) {
+                        return Err(fault(format!("{:#X} is outside of memory", target)));
+                    }
+                    if origin.is_some_and(|origin| target < origin) {
+                        let message = format!("{:#X} is before the start of the program", target);
+                        return Err(fault(message));
+                    }
+                    address = target;
+                },
+                _ => {
+                    origin = origin.or(Some(address));
+                    placed.push((statement, location, column, address));
+                    address += Assembler::size(statement);
+                }
+            }
+        }
+
+        let origin = origin.unwrap_or(address);
+        let mut rom = Vec::new();
+        for (statement, location, column, address) in placed {
+            let bytes = match *statement {
+                Statement::Data(width, ref operands) =>
+                    self.data(width, operands, address, location)?,
+                Statement::Instruction(ref mnemonic, ref operands) =>
+                    self.instruction(mnemonic, operands, address, location, column)?,
+                _ => unreachable!("only data and instructions are placed")
+            };
+
+            if address + bytes.len() as i64 > MEMORY_END {
+                return Err(Fault {
+                    location,
+                    column,
+                    message: "program runs past the end of memory".to_string()
+                });
+            }
+            let offset = (address - origin) as usize;
+            if rom.len() < offset + bytes.len() {
+                rom.resize(offset + bytes.len(), 0);
+            }
+            rom[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        let labels = self.labels.iter()
+            .map(|(name, &address)| (name.clone(), address as Address))
+            .collect();
+        Ok(Program { origin: origin as Address, rom, labels })
+    }
+
+    /// Encode the operands of a `DB` or `DW` directive.
+    fn data(&self, width: Width, operands: &[Operand], here: i64, location: Location)
+            -> Result<Vec<Byte>, Fault> {
+        let mut bytes = Vec::new();
+        for operand in operands {
+            match (width, &operand.kind) {
+                (Width::Byte, OperandKind::Text(ref text)) => bytes.extend(text.bytes()),
+                (Width::Word, _) => {
+                    let value = self.value(operand, width, here, location)? as Word;
+                    bytes.push((value >> 8) as Byte);
+                    bytes.push(value as Byte);
+                },
+                _ => bytes.push(self.value(operand, width, here, location)? as Byte)
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Encode an instruction, picking the opcode from the mnemonic and the shape of the operands.
+    fn instruction(&self, mnemonic: &str, operands: &[Operand], here: i64, location: Location,
+                   column: usize) -> Result<Vec<Byte>, Fault> {
+        use self::Shape::{Register, Keyword, Value, Long};
+
+        let shapes: Vec<Shape> = operands.iter().map(Operand::shape).collect();
+        let value = |index: usize, width| self.value(&operands[index], width, here, location);
+        let address = |index| value(index, Width::Address).map(|value| value as Address);
+        let byte = |index| value(index, Width::Byte).map(|value| value as Byte);
+        let nibble = |index| value(index, Width::Nibble).map(|value| value as Byte);
+
+        let opcode = match (mnemonic, &shapes[..]) {
+            ("CLS", &[]) => OpCode::ClearScreen,
+            ("RET", &[]) => OpCode::SubReturn,
+            ("SYS", &[Value]) => OpCode::MachineCall(address(0)?),
+            ("JP", &[Value]) => OpCode::JumpAddress(address(0)?),
+            ("JP", &[Register(0), Value]) => OpCode::JumpAddressOffset(address(1)?),
+            ("CALL", &[Value]) => OpCode::SubJump(address(0)?),
+            ("SE", &[Register(x), Register(y)]) => OpCode::SkipRegister(x, y),
+            ("SE", &[Register(x), Value]) => OpCode::SkipValue(x, byte(1)?),
+            ("SNE", &[Register(x), Register(y)]) => OpCode::SkipNotRegister(x, y),
+            ("SNE", &[Register(x), Value]) => OpCode::SkipNotValue(x, byte(1)?),
+            ("LD", &[Register(x), Register(y)]) => OpCode::SetRegister(x, y),
+            ("LD", &[Register(x), Keyword("DT")]) => OpCode::SetRegisterFromTimer(x),
+            ("LD", &[Register(x), Keyword("K")]) => OpCode::WaitKeyPress(x),
+            ("LD", &[Register(x), Keyword("[I]")]) => OpCode::LoadRegisters(x),
+            ("LD", &[Register(x), Keyword("R")]) => OpCode::LoadFlags(x),
+            ("LD", &[Register(x), Value]) => OpCode::SetValue(x, byte(1)?),
+            ("LD", &[Keyword("DT"), Register(x)]) => OpCode::SetDelayTimer(x),
+            ("LD", &[Keyword("ST"), Register(x)]) => OpCode::SetSoundTimer(x),
+            ("LD", &[Keyword("I"), Value]) => OpCode::SetIndex(address(1)?),
+            ("LD", &[Keyword("I"), Long]) => {
+                let target = value(1, Width::Word)? as Word;
+                return Ok(vec![0xF0, 0x00, (target >> 8) as Byte, target as Byte]);
+            },
+            ("LD", &[Keyword("F"), Register(x)]) => OpCode::SetIndexToSprite(x),
+            ("LD", &[Keyword("HF"), Register(x)]) => OpCode::SetIndexToLargeSprite(x),
+            ("LD", &[Keyword("B"), Register(x)]) => OpCode::StoreDecimal(x),
+            ("LD", &[Keyword("[I]"), Register(x)]) => OpCode::StoreRegisters(x),
+            ("LD", &[Keyword("R"), Register(x)]) => OpCode::SaveFlags(x),
+            ("ADD", &[Register(x), Register(y)]) => OpCode::AddRegister(x, y),
+            ("ADD", &[Register(x), Value]) => OpCode::AddValue(x, byte(1)?),
+            ("ADD", &[Keyword("I"), Register(x)]) => OpCode::AddIndex(x),
+            ("OR", &[Register(x), Register(y)]) => OpCode::OrRegister(x, y),
+            ("AND", &[Register(x), Register(y)]) => OpCode::AndRegister(x, y),
+            ("XOR", &[Register(x), Register(y)]) => OpCode::XorRegister(x, y),
+            ("SUB", &[Register(x), Register(y)]) => OpCode::SubRegisterRight(x, y),
+            ("SUBN", &[Register(x), Register(y)]) => OpCode::SubRegisterLeft(x, y),
+            ("SHR", &[Register(x), Register(y)]) => OpCode::RShiftRegister(x, y),
+            ("SHR", &[Register(x)]) => OpCode::RShiftRegister(x, x),
+            ("SHL", &[Register(x), Register(y)]) => OpCode::LShiftRegister(x, y),
+            ("SHL", &[Register(x)]) => OpCode::LShiftRegister(x, x),
+            ("RND", &[Register(x), Value]) => OpCode::RandomValue(x, byte(1)?),
+            ("DRW", &[Register(x), Register(y), Value]) => OpCode::Draw(x, y, nibble(2)?),
+            ("SKP", &[Register(x)]) => OpCode::SkipKeyPressed(x),
+            ("SKNP", &[Register(x)]) => OpCode::SkipKeyNotPressed(x),
+            ("SCD", &[Value]) => OpCode::ScrollDown(nibble(0)?),
+            ("SCU", &[Value]) => OpCode::ScrollUp(nibble(0)?),
+            ("SCR", &[]) => OpCode::ScrollRight,
+            ("SCL", &[]) => OpCode::ScrollLeft,
+            ("EXIT", &[]) => OpCode::Exit,
+            ("LOW", &[]) => OpCode::LowResolution,
+            ("HIGH", &[]) => OpCode::HighResolution,
+            ("SAVE", &[Register(x), Register(y)]) => OpCode::SaveRange(x, y),
+            ("LOAD", &[Register(x), Register(y)]) => OpCode::LoadRange(x, y),
+            ("PLANE", &[Value]) => OpCode::SelectPlanes(nibble(0)?),
+            ("AUDIO", &[]) => OpCode::LoadAudioPattern,
+            ("PITCH", &[Register(x)]) => OpCode::SetPitch(x),
+            _ => {
+                let message = if MNEMONICS.contains(&mnemonic) {
+                    format!("invalid operands for `{}`", mnemonic)
+                } else {
+                    format!("unknown instruction `{}`", mnemonic)
+                };
+                return Err(Fault { location, column, message });
+            }
+        };
+
+        let word = opcode.encode().expect("assembled instructions are always known");
+        Ok(vec![(word >> 8) as Byte, word as Byte])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use disasm::{Content, Disassembly, Syntax};
+    use system::{Platform, System};
+
+    /** Labels, constants, expressions and data should all assemble in place. */
+    #[test]
+    fn assemble_program() {
+        let program = assemble("
+            ROWS    EQU 5
+            start:  LD I, sprite        ; point at the sprite
+                    LD V0, ROWS * 2 + 1
+                    DRW V0, V1, ROWS
+            loop:   JP loop
+                    ORG $ + 2
+            sprite: DB #F0, (1 << 4) | $80, 0b1001_0000, \"ok\"
+                    DW start, -1
+        ").unwrap();
+
+        assert_eq!(0x200, program.origin);
+        assert_eq!(vec![
+            0xA2, 0x0A, 0x60, 0x0B, 0xD0, 0x15, 0x12, 0x06, 0x00, 0x00,
+            0xF0, 0x90, 0x90, b'o', b'k', 0x02, 0x00, 0xFF, 0xFF
+        ], program.rom);
+        assert_eq!(Some(&0x206), program.labels.get("loop"));
+    }
+
+    /** Every instruction the disassembler prints should assemble back to the same bytes. */
+    #[test]
+    fn round_trip() {
+        for word in 0x0000..0x10000u32 {
+            let opcode = OpCode::from(word as Word);
+            if opcode == OpCode::Unknown || opcode == OpCode::SetIndexLong {
+                continue;
+            }
+
+            let program = assemble(&opcode.to_string()).unwrap();
+            assert_eq!(vec![(word >> 8) as Byte, word as Byte], program.rom, "{}", opcode);
+        }
+
+        let rom = [0x22, 0x06, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xEE, 0xAB, 0xCD, 0xEF];
+        let disassembly = Disassembly::new(&rom, 0x200, Platform::XoChip);
+        let mut source = String::new();
+        for line in disassembly.lines() {
+            if disassembly.labels().contains(&line.address) {
+                source += &format!("{}:\n", Disassembly::label_name(line.address));
+            }
+            source += &disassembly.text(line, Syntax::Cowgod);
+            source += "\n";
+        }
+        assert_eq!(Content::Data, disassembly.lines().last().unwrap().content);
+        assert_eq!(rom.to_vec(), assemble(&source).unwrap().rom);
+    }
+
+    /** Errors should say where in the source they were found. */
+    #[test]
+    fn errors() {
+        let error = |source| assemble(source).unwrap_err();
+
+        assert_eq!(AsmError {
+            file: "<source>".to_string(),
+            line: 2,
+            column: 12,
+            message: "`missing` is not defined".to_string()
+        }, error("CLS\n    JP 2 + missing"));
+        assert_eq!("<source>:1:1: unknown instruction `FOO`", error("FOO V1").to_string());
+        assert_eq!((1, 5, "invalid operands for `LD`".to_string()),
+                   { let e = error("    LD DT, #12"); (e.line, e.column, e.message) });
+        assert_eq!((1, 8, "256 does not fit in a byte".to_string()),
+                   { let e = error("LD V0, #FF + 1"); (e.line, e.column, e.message) });
+        assert_eq!("`a` is already defined", error("a: CLS\na: CLS").message);
+        assert_eq!("`a` is defined in terms of itself", error("a EQU b\nb EQU a\nJP a").message);
+        assert_eq!((1, 10), { let e = error("DB 1, 2, \"open"); (e.line, e.column) });
+    }
+
+    /** Included files should be found next to the file including them. */
+    #[test]
+    fn include() {
+        let directory = env::temp_dir().join(format!("chip8-asm-test-{}", process::id()));
+        fs::create_dir_all(directory.join("lib")).unwrap();
+        let main = "CALL draw\nJP $\nINCLUDE \"lib/draw.asm\"\n";
+        fs::write(directory.join("main.asm"), main).unwrap();
+        fs::write(directory.join("lib/draw.asm"), "draw: CLS\nRET\nbad\n").unwrap();
+
+        let error = assemble_file(directory.join("main.asm")).unwrap_err();
+        assert!(error.file.ends_with("draw.asm"));
+        assert_eq!(3, error.line);
+
+        fs::write(directory.join("lib/draw.asm"), "draw: CLS\nRET\n").unwrap();
+        let program = assemble_file(directory.join("main.asm")).unwrap();
+        assert_eq!(vec![0x22, 0x04, 0x12, 0x02, 0x00, 0xE0, 0x00, 0xEE], program.rom);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    /** An assembled program should run, so tests can be written as assembly. */
+    #[test]
+    fn run_assembled() {
+        let program = assemble("
+                    LD V0, 0
+                    LD V1, 10
+            sum:    ADD V0, V1
+                    ADD V1, -1
+                    SE V1, 0
+                    JP sum
+            done:   JP done
+        ").unwrap();
+
+        let mut system = System::new();
+        system.load_rom(&program.rom[..]).unwrap();
+        system.run_cycles(100).unwrap();
+        assert_eq!(55, system.registers()[0]);
+        assert_eq!(program.labels["done"], system.pc());
+    }
+}
@@ -0,0 +1,44 @@
+extern crate chip8_interpreter;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process;
+
+use chip8_interpreter::{asm, octo};
+
+const USAGE: &str = "usage: chip8-asm [-o OUTPUT] SOURCE\n\
+                     SOURCE is assembly, or Octo if it ends in .8o";
+
+/// Print a message and give up.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let mut source = None;
+    let mut output = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().unwrap_or_else(|| fail(USAGE)))),
+            _ if arg.starts_with('-') || source.is_some() => fail(USAGE),
+            _ => source = Some(PathBuf::from(arg))
+        }
+    }
+    let source = source.unwrap_or_else(|| fail(USAGE));
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+
+    let program = if source.extension().is_some_and(|extension| extension == "8o") {
+        octo::compile_file(&source)
+    } else {
+        asm::assemble_file(&source)
+    };
+    let program = program.unwrap_or_else(|error| fail(&error.to_string()));
+    if let Err(error) = File::create(&output).and_then(|mut file| file.write_all(&program.rom)) {
+        fail(&format!("could not write {}: {}", output.display(), error));
+    }
+}
@@ -0,0 +1,60 @@
+extern crate chip8_interpreter;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::process;
+
+use chip8_interpreter::disasm::{Disassembly, Syntax};
+use chip8_interpreter::system::{Address, Platform, PROGRAM_ADDRESS};
+
+const USAGE: &str = "usage: chip8-disasm [--octo] [--platform chip8|schip|xochip] \
+                     [--origin ADDRESS] ROM";
+
+/// Print a message and give up.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+/// Parse an address written in decimal, or in hex after "0x", "#" or "$".
+fn parse_address(text: &str) -> Option<Address> {
+    for prefix in &["0x", "#", "$"] {
+        if let Some(digits) = text.strip_prefix(prefix) {
+            return Address::from_str_radix(digits, 16).ok();
+        }
+    }
+    text.parse().ok()
+}
+
+fn main() {
+    let mut syntax = Syntax::Cowgod;
+    let mut platform = Platform::Chip8;
+    let mut origin = PROGRAM_ADDRESS;
+    let mut path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--octo" => syntax = Syntax::Octo,
+            "--platform" => {
+                let name = args.next().unwrap_or_else(|| fail(USAGE));
+                platform = name.parse().unwrap_or_else(|error: String| fail(&error));
+            },
+            "--origin" => {
+                origin = args.next().as_ref().and_then(|text| parse_address(text))
+                    .unwrap_or_else(|| fail(USAGE));
+            },
+            _ if arg.starts_with("--") || path.is_some() => fail(USAGE),
+            _ => path = Some(arg)
+        }
+    }
+    let path = path.unwrap_or_else(|| fail(USAGE));
+
+    let mut rom = Vec::new();
+    if let Err(error) = File::open(&path).and_then(|mut file| file.read_to_end(&mut rom)) {
+        fail(&format!("could not read {}: {}", path, error));
+    }
+
+    print!("{}", Disassembly::new(&rom, origin, platform).render(syntax));
+}
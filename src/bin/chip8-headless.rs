@@ -0,0 +1,522 @@
+extern crate chip8_interpreter;
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::process;
+
+use chip8_interpreter::system::{System, Display, Platform, ErrorKind, StepResult, Address};
+use chip8_interpreter::system::{CpuState, VipRng, Word};
+
+const USAGE: &str = "\
+usage: chip8-headless [OPTIONS] ROM
+
+options:
+    --frames N          run N 60 Hz frames (default 600)
+    --cycles N          run N instructions instead of frames
+    --ipf N             instructions per frame (default 15)
+    --platform NAME     chip8, schip or xochip (default chip8)
+    --seed N            seed the random number generator
+    --rng NAME          xorshift (default), or vip:FILE for the COSMAC VIP generator, given
+                        a dump of the VIP interpreter or of its page at 0x100
+    --keys FILE         feed keys from FILE, with lines of `TIME KEY down|up`, where TIME
+                        counts frames, or instructions with --cycles, and KEY is 0 to F
+    --trap ADDRESS      stop when the program counter reaches ADDRESS; may be repeated
+    --dump FORMAT       write the framebuffer as ascii, pbm or png (default ascii)
+    --output FILE       write the framebuffer to FILE rather than standard output
+    --scale N           size of a pixel in pbm and png images (default 1)
+
+exit status:
+    0   ran to the end, or halted with 00FD
+    1   bad arguments, or a file could not be read or written
+    2   stopped on an unknown opcode
+    3   reached a trap address
+    4   stopped on any other error";
+
+const EXIT_FINISHED: i32 = 0;
+const EXIT_USAGE: i32 = 1;
+const EXIT_UNKNOWN_OPCODE: i32 = 2;
+const EXIT_TRAP: i32 = 3;
+const EXIT_ERROR: i32 = 4;
+
+/// Grey levels for the four XO-CHIP colours in images.
+const GREYS: [u8; 4] = [0x00, 0xFF, 0xAA, 0x55];
+/// Characters for the four XO-CHIP colours in ASCII art.
+const CHARACTERS: [char; 4] = ['.', '#', 'o', '@'];
+
+/// Print a message and give up.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(EXIT_USAGE);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    Pbm,
+    Png
+}
+
+/// Everything given on the command line.
+struct Options {
+    rom: String,
+    limit: u64,
+    cycles: bool,
+    instructions_per_frame: u64,
+    platform: Platform,
+    seed: Option<u64>,
+    vip_rng: Option<String>,
+    keys: Option<String>,
+    traps: Vec<Address>,
+    format: Format,
+    output: Option<String>,
+    scale: usize
+}
+
+/// Parse a number written in decimal, or in hex after "0x", "#" or "$".
+fn parse_number(text: &str) -> Option<u64> {
+    for prefix in &["0x", "#", "$"] {
+        if let Some(digits) = text.strip_prefix(prefix) {
+            return u64::from_str_radix(digits, 16).ok();
+        }
+    }
+    text.parse().ok()
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        rom: String::new(),
+        limit: 600,
+        cycles: false,
+        instructions_per_frame: 15,
+        platform: Platform::Chip8,
+        seed: None,
+        vip_rng: None,
+        keys: None,
+        traps: Vec::new(),
+        format: Format::Ascii,
+        output: None,
+        scale: 1
+    };
+    let mut rom = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            if rom.is_some() {
+                fail(USAGE);
+            }
+            rom = Some(arg);
+            continue;
+        }
+
+        let value = args.next().unwrap_or_else(|| fail(USAGE));
+        let number = || parse_number(&value)
+            .unwrap_or_else(|| fail(&format!("{} needs a number, not \"{}\"", arg, value)));
+        match arg.as_str() {
+            "--frames" => options.limit = number(),
+            "--cycles" => {
+                options.limit = number();
+                options.cycles = true;
+            },
+            "--ipf" => options.instructions_per_frame = number(),
+            "--platform" => {
+                options.platform = value.parse().unwrap_or_else(|error: String| fail(&error));
+            },
+            "--seed" => options.seed = Some(number()),
+            "--rng" => options.vip_rng = match value.strip_prefix("vip:") {
+                Some(path) => Some(path.to_string()),
+                None if value == "xorshift" => None,
+                None => fail(&format!("unknown random number generator \"{}\"", value))
+            },
+            "--keys" => options.keys = Some(value),
+            "--trap" => {
+                let address = number();
+                if address > 0xFFFF {
+                    fail(&format!("trap address {} is past the end of memory", value));
+                }
+                options.traps.push(address as Address);
+            },
+            "--dump" => options.format = match value.as_str() {
+                "ascii" => Format::Ascii,
+                "pbm" => Format::Pbm,
+                "png" => Format::Png,
+                _ => fail(&format!("unknown framebuffer format \"{}\"", value))
+            },
+            "--output" => options.output = Some(value),
+            "--scale" => options.scale = number() as usize,
+            _ => fail(USAGE)
+        }
+    }
+
+    options.rom = rom.unwrap_or_else(|| fail(USAGE));
+    if options.format == Format::Png && options.output.is_none() {
+        fail("--dump png needs --output");
+    }
+    if options.scale == 0 || options.instructions_per_frame == 0 {
+        fail("--scale and --ipf must be at least 1");
+    }
+    options
+}
+
+/// Read a key script into (time, key, pressed) events, in order of time.
+fn read_keys(path: &str) -> io::Result<Vec<(u64, usize, bool)>> {
+    let mut script = String::new();
+    File::open(path)?.read_to_string(&mut script)?;
+    parse_keys(path, &script)
+}
+
+/// Parse the key script read from `path`, which is only used in error messages.
+fn parse_keys(path: &str, script: &str) -> io::Result<Vec<(u64, usize, bool)>> {
+    let mut events = Vec::new();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+
+        let event = match fields[..] {
+            [time, key, action] => match (time.parse(), usize::from_str_radix(key, 16), action) {
+                (Ok(time), Ok(key), "down") if key < 16 => Some((time, key, true)),
+                (Ok(time), Ok(key), "up") if key < 16 => Some((time, key, false)),
+                _ => None
+            },
+            _ => None
+        };
+        match event {
+            Some(event) => events.push(event),
+            None => {
+                let message = format!("{}:{}: expected `TIME KEY down|up`", path, number + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+    }
+
+    events.sort_by_key(|&(time, _, _)| time);
+    Ok(events)
+}
+
+/// Why the run stopped.
+enum Outcome {
+    Finished,
+    Halted,
+    Trap(Address),
+    Failed(StepResult)
+}
+
+/// Run the system for the number of frames or cycles asked for, feeding it keys on the way.
+fn run(system: &mut System, options: &Options, keys: &[(u64, usize, bool)]) -> (Outcome, u64) {
+    let per_unit = if options.cycles { 1 } else { options.instructions_per_frame };
+    let mut keys = keys.iter().peekable();
+    let mut executed = 0;
+
+    for unit in 0..options.limit {
+        while let Some(&&(_, key, pressed)) = keys.peek().filter(|&&&(time, _, _)| time <= unit) {
+            system.set_key(key, pressed);
+            keys.next();
+        }
+
+        for _ in 0..per_unit {
+            if system.is_halted() {
+                return (Outcome::Halted, executed);
+            }
+            if options.traps.contains(&system.pc()) {
+                return (Outcome::Trap(system.pc()), executed);
+            }
+
+            // waiting on FX0A or for the display does not execute anything
+            let running = system.cpu_state() == CpuState::Running;
+            let result = system.step();
+            if !result.is_ok() {
+                return (Outcome::Failed(result), executed);
+            }
+            if running {
+                executed += 1;
+            }
+        }
+
+        if !options.cycles {
+            system.tick_timers();
+        }
+    }
+
+    (Outcome::Finished, executed)
+}
+
+fn ascii(display: &Display) -> Vec<u8> {
+    let mut art = String::new();
+    for y in 0..display.height() {
+        art.extend((0..display.width()).map(|x| CHARACTERS[display.colour(x, y) as usize]));
+        art.push('\n');
+    }
+    art.into_bytes()
+}
+
+/// A plain PBM bitmap, with lit pixels black.
+fn pbm(display: &Display, scale: usize) -> Vec<u8> {
+    let (width, height) = (display.width() * scale, display.height() * scale);
+    let mut image = format!("P1\n{} {}\n", width, height);
+    for y in 0..height {
+        let row: Vec<&str> = (0..width)
+            .map(|x| if display.pixel(x / scale, y / scale) { "1" } else { "0" })
+            .collect();
+        image += &row.join(" ");
+        image.push('\n');
+    }
+    image.into_bytes()
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+/// A greyscale PNG. The image data is stored uncompressed, which keeps the encoder small.
+fn png(display: &Display, scale: usize) -> Vec<u8> {
+    let (width, height) = (display.width() * scale, display.height() * scale);
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    for y in 0..height {
+        raw.push(0);    // no filter
+        raw.extend((0..width).map(|x| GREYS[display.colour(x / scale, y / scale) as usize]));
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(0xFFFF).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        let length = block.len() as u16;
+        zlib.push((index + 1 == blocks.len()) as u8);
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);    // 8 bit greyscale, not interlaced
+
+    let mut image = b"\x89PNG\r\n\x1a\n".to_vec();
+    for &(tag, ref data) in [(b"IHDR", header), (b"IDAT", zlib), (b"IEND", Vec::new())].iter() {
+        let mut chunk = tag.to_vec();
+        chunk.extend_from_slice(data);
+        image.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        image.extend_from_slice(&chunk);
+        image.extend_from_slice(&crc32(&chunk).to_be_bytes());
+    }
+    image
+}
+
+/// The registers, timers, stack and state hash, then how the run ended.
+fn report(system: &System, outcome: &Outcome, executed: u64) -> String {
+    let mut report = format!("PC {:04X}  I {:04X}  DT {:02X}  ST {:02X}  stack [", system.pc(),
+                             system.index(), system.delay_timer(), system.sound_timer());
+    let stack: Vec<String> = system.stack().iter()
+        .map(|address| format!("{:04X}", address))
+        .collect();
+    report += &stack.join(" ");
+    report += "]\n";
+
+    for (row, registers) in system.registers().chunks(8).enumerate() {
+        let registers: Vec<String> = registers.iter().enumerate()
+            .map(|(index, value)| format!("V{:X} {:02X}", row * 8 + index, value))
+            .collect();
+        report += &registers.join("  ");
+        report.push('\n');
+    }
+
+    report += &format!("state {:016x}\n", system.state_hash());
+    report += &format!("executed {} instructions, ", executed);
+    report += &match *outcome {
+        Outcome::Finished => "finished".to_string(),
+        Outcome::Halted => "halted".to_string(),
+        Outcome::Trap(address) => format!("reached trap at {:04X}", address),
+        Outcome::Failed(ref result) => match result.error {
+            Some(ref error) => error.to_string(),
+            None => "failed".to_string()
+        }
+    };
+    report.push('\n');
+    report
+}
+
+/// The exit status for how the run ended.
+fn exit_code(outcome: &Outcome) -> i32 {
+    match *outcome {
+        Outcome::Finished | Outcome::Halted => EXIT_FINISHED,
+        Outcome::Trap(_) => EXIT_TRAP,
+        Outcome::Failed(ref result) => match result.error {
+            Some(ref error) if error.kind == ErrorKind::UnknownOpcode => EXIT_UNKNOWN_OPCODE,
+            _ => EXIT_ERROR
+        }
+    }
+}
+
+fn main() {
+    let options = parse_options();
+    let keys = match options.keys {
+        Some(ref path) => read_keys(path).unwrap_or_else(|error| fail(&error.to_string())),
+        None => Vec::new()
+    };
+
+    let mut system = System::new();
+    system.set_platform(options.platform);
+    if let Some(seed) = options.seed {
+        system.seed_random(seed);
+    }
+    if let Some(ref path) = options.vip_rng {
+        let mut dump = Vec::new();
+        if let Err(error) = File::open(path).and_then(|mut file| file.read_to_end(&mut dump)) {
+            fail(&format!("could not read {}: {}", path, error));
+        }
+        let seed = options.seed.unwrap_or(0) as Word;
+        let rng = VipRng::from_dump(&dump, seed)
+            .unwrap_or_else(|| fail(&format!("{} is not a 512 or 256 byte dump", path)));
+        system.set_random_source(Box::new(rng));
+    }
+    if options.cycles {
+        let cycles_per_second = (options.instructions_per_frame * 60).min(u32::MAX as u64);
+        system.set_cycle_timing(Some(cycles_per_second as u32));
+    }
+    if let Err(error) = system.load_rom_file(&options.rom) {
+        fail(&format!("could not load {}: {}", options.rom, error));
+    }
+
+    let (outcome, executed) = run(&mut system, &options, &keys);
+
+    let framebuffer = match options.format {
+        Format::Ascii => ascii(system.display()),
+        Format::Pbm => pbm(system.display(), options.scale),
+        Format::Png => png(system.display(), options.scale)
+    };
+    let written = match options.output {
+        Some(ref path) => File::create(path).and_then(|mut file| file.write_all(&framebuffer)),
+        None => io::stdout().write_all(&framebuffer)
+    };
+    if let Err(error) = written {
+        fail(&format!("could not write the framebuffer: {}", error));
+    }
+    // a closed pipe should not turn a clean run into a crash
+    let _ = io::stdout().write_all(report(&system, &outcome, executed).as_bytes());
+
+    process::exit(exit_code(&outcome));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Key scripts should be read into events sorted by time, skipping blank lines and
+      * comments. */
+    #[test]
+    fn parse_key_script() {
+        let script = "# press A then B\n\n30 a down\n10 B down  # first\n32 A up\n";
+        let events = parse_keys("keys", script).unwrap();
+        assert_eq!(vec![(10, 0xB, true), (30, 0xA, true), (32, 0xA, false)], events);
+    }
+
+    /** Lines that are not `TIME KEY down|up`, or name a key off the keypad, should be rejected
+      * with the line they are on. */
+    #[test]
+    fn parse_bad_key_script() {
+        for &line in &["10 A", "10 A press", "ten A down", "10 10 down", "10 G up", "1 2 up 3"] {
+            let script = format!("0 1 down\n{}\n", line);
+            let error = parse_keys("keys", &script).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+            assert_eq!("keys:2: expected `TIME KEY down|up`", error.to_string());
+        }
+    }
+
+    /** The checksums should match the standard check values. */
+    #[test]
+    fn checksums() {
+        assert_eq!(0x0000_0000, crc32(b""));
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+        assert_eq!(0x0000_0001, adler32(b""));
+        assert_eq!(0x091E_01DE, adler32(b"123456789"));
+        assert_eq!(0x11E6_0398, adler32(b"Wikipedia"));
+    }
+
+    /** A PBM image should give its size in pixels after scaling, then one row per line. */
+    #[test]
+    fn pbm_header() {
+        let image = String::from_utf8(pbm(&Display::new(), 2)).unwrap();
+        let lines: Vec<&str> = image.lines().collect();
+        assert_eq!("P1", lines[0]);
+        assert_eq!("128 64", lines[1]);
+        assert_eq!(2 + 64, lines.len());
+        assert_eq!(128, lines[2].split(' ').count());
+    }
+
+    /** A PNG image should start with the signature and an IHDR chunk for an 8 bit greyscale
+      * image of the scaled size, and end with an empty IEND chunk. */
+    #[test]
+    fn png_header() {
+        let image = png(&Display::new(), 3);
+        assert_eq!(b"\x89PNG\r\n\x1a\n", &image[..8]);
+        assert_eq!(&[0, 0, 0, 13], &image[8..12]);
+        assert_eq!(b"IHDR", &image[12..16]);
+        assert_eq!(&[0, 0, 0, 192, 0, 0, 0, 96, 8, 0, 0, 0, 0], &image[16..29]);
+        assert_eq!(crc32(&image[12..29]).to_be_bytes(), image[29..33]);
+        assert_eq!(b"\0\0\0\0IEND\xAE\x42\x60\x82", &image[image.len() - 12..]);
+    }
+
+    /** Only instructions that ran should be counted, not the steps spent waiting for a key. */
+    #[test]
+    fn run_counts_executed() {
+        let options = Options {
+            rom: String::new(),
+            limit: 4,
+            cycles: false,
+            instructions_per_frame: 10,
+            platform: Platform::Chip8,
+            seed: None,
+            vip_rng: None,
+            keys: None,
+            traps: Vec::new(),
+            format: Format::Ascii,
+            output: None,
+            scale: 1
+        };
+        let mut system = System::new();
+        system.load_rom(&[0x60, 0x01, 0xF1, 0x0A, 0x12, 0x04][..]).unwrap();
+
+        // two instructions, nothing while FX0A waits through frame 1, then ten a frame
+        let (outcome, executed) = run(&mut system, &options, &[(2, 0x3, true)]);
+        assert_eq!(EXIT_FINISHED, exit_code(&outcome));
+        assert_eq!(2 + 2 * 10, executed);
+    }
+
+    /** Each way a run can end should map to its documented exit status. */
+    #[test]
+    fn exit_codes() {
+        let failure = |rom: &[u8]| {
+            let mut system = System::new();
+            system.load_rom(rom).unwrap();
+            Outcome::Failed(system.step())
+        };
+
+        assert_eq!(EXIT_FINISHED, exit_code(&Outcome::Finished));
+        assert_eq!(EXIT_FINISHED, exit_code(&Outcome::Halted));
+        assert_eq!(EXIT_TRAP, exit_code(&Outcome::Trap(0x200)));
+        assert_eq!(EXIT_UNKNOWN_OPCODE, exit_code(&failure(&[0xFF, 0xFF])));
+        assert_eq!(EXIT_ERROR, exit_code(&failure(&[0x00, 0xEE])));
+    }
+}
@@ -0,0 +1,335 @@
+extern crate chip8_interpreter;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::io;
+use std::io::prelude::*;
+use std::process::{self, Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chip8_interpreter::system::{System, Display, Platform, Key, KeyEventType, Rewind};
+use chip8_interpreter::system::FileFlagStorage;
+
+const USAGE: &str = "\
+usage: chip8-term [OPTIONS] ROM
+
+options:
+    --platform NAME     chip8, schip or xochip (default chip8)
+    --ipf N             instructions per frame (default 15)
+    --seed N            seed the random number generator
+    --braille           draw 2x4 pixels per character with braille patterns, rather than 1x2
+                        with half blocks
+    --status            show the registers and timers below the display
+    --hold MS           how long a key stays down after the terminal last sent it, which should
+                        be longer than the keyboard's repeat delay (default 250)
+    --flags DIR         keep the SUPER-CHIP user flags saved by each ROM in DIR (default
+                        chip8-interpreter/flags in the user's data directory)
+
+keys:
+    1 2 3 4             1 2 3 C
+    q w e r     map     4 5 6 D
+    a s d f     to      7 8 9 E
+    z x c v             A 0 B F
+
+    Hold Backspace to run time backwards. Ctrl-C quits.";
+
+/// Ctrl-C, which arrives as an ordinary byte in raw mode.
+const INTERRUPT: u8 = 0x03;
+/// Backspace, which rewinds a frame each frame it is held.
+const REWIND_KEY: Key = 0x7F;
+/// Bytes of history kept for rewinding, which is a few minutes for most ROMs.
+const REWIND_BUDGET: usize = 32 << 20;
+const FRAME: Duration = Duration::from_micros(16_667);
+
+/// Print a message and give up.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+/// Everything given on the command line.
+struct Options {
+    rom: String,
+    platform: Platform,
+    instructions_per_frame: usize,
+    seed: Option<u64>,
+    braille: bool,
+    status: bool,
+    hold: Duration,
+    flags: Option<String>
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        rom: String::new(),
+        platform: Platform::Chip8,
+        instructions_per_frame: 15,
+        seed: None,
+        braille: false,
+        status: false,
+        hold: Duration::from_millis(250),
+        flags: None
+    };
+    let mut rom = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--braille" => options.braille = true,
+            "--status" => options.status = true,
+            "--flags" => options.flags = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--platform" | "--ipf" | "--seed" | "--hold" => {
+                let value = args.next().unwrap_or_else(|| fail(USAGE));
+                let number = || value.parse::<u64>().unwrap_or_else(|_| {
+                    fail(&format!("{} needs a number, not \"{}\"", arg, value))
+                });
+                match arg.as_str() {
+                    "--platform" => {
+                        options.platform = value.parse()
+                            .unwrap_or_else(|error: String| fail(&error));
+                    },
+                    "--ipf" => options.instructions_per_frame = number() as usize,
+                    "--seed" => options.seed = Some(number()),
+                    _ => options.hold = Duration::from_millis(number())
+                }
+            },
+            _ if arg.starts_with("--") || rom.is_some() => fail(USAGE),
+            _ => rom = Some(arg)
+        }
+    }
+
+    options.rom = rom.unwrap_or_else(|| fail(USAGE));
+    if options.instructions_per_frame == 0 {
+        fail("--ipf must be at least 1");
+    }
+    options
+}
+
+/// Puts the terminal into raw mode with `stty`, hiding the cursor, and puts it back as it was
+/// when dropped.
+struct RawMode {
+    saved: String
+}
+
+impl RawMode {
+    fn enable() -> Result<RawMode, String> {
+        let output = Command::new("stty").arg("-g").stdin(Stdio::inherit()).output()
+            .map_err(|error| format!("could not run stty: {}", error))?;
+        if !output.status.success() {
+            return Err("standard input is not a terminal".to_string());
+        }
+
+        let saved = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        stty(&["raw", "-echo"])?;
+        print!("\x1b[?25l\x1b[2J");
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+        print!("\x1b[?25h\r\n");
+        let _ = io::stdout().flush();
+    }
+}
+
+fn stty(args: &[&str]) -> Result<(), String> {
+    let status = Command::new("stty").args(args).status()
+        .map_err(|error| format!("could not run stty: {}", error))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("stty {} failed", args.join(" ")))
+    }
+}
+
+/// Read standard input on its own thread, so the emulator never waits for a key.
+fn spawn_input() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buffer = [0; 64];
+        while let Ok(count) = stdin.read(&mut buffer) {
+            if count == 0 || buffer[..count].iter().any(|&byte| sender.send(byte).is_err()) {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// The display with each character covering two pixels, one above the other.
+fn half_blocks(display: &Display) -> Vec<String> {
+    (0..display.height()).step_by(2).map(|y| {
+        (0..display.width()).map(|x| {
+            match (display.pixel(x, y), display.pixel(x, y + 1)) {
+                (false, false) => ' ',
+                (true, false) => '\u{2580}',
+                (false, true) => '\u{2584}',
+                (true, true) => '\u{2588}'
+            }
+        }).collect()
+    }).collect()
+}
+
+/// The display with each character covering a block of 2x4 pixels.
+fn braille(display: &Display) -> Vec<String> {
+    // the dot for each pixel of the block, by row and then column
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    (0..display.height()).step_by(4).map(|y| {
+        (0..display.width()).step_by(2).map(|x| {
+            let mut pattern = 0;
+            for (row, dots) in DOTS.iter().enumerate() {
+                for (column, dot) in dots.iter().enumerate() {
+                    if display.pixel(x + column, y + row) {
+                        pattern |= dot;
+                    }
+                }
+            }
+            std::char::from_u32(0x2800 + pattern).unwrap()
+        }).collect()
+    }).collect()
+}
+
+/// The program counter, index, timers and registers, in the layout of chip8-headless.
+fn status(system: &System) -> Vec<String> {
+    let mut lines = vec![format!("PC {:04X}  I {:04X}  DT {:02X}  ST {:02X}", system.pc(),
+                                 system.index(), system.delay_timer(), system.sound_timer())];
+    for (row, registers) in system.registers().chunks(8).enumerate() {
+        let registers: Vec<String> = registers.iter().enumerate()
+            .map(|(index, value)| format!("V{:X} {:02X}", row * 8 + index, value))
+            .collect();
+        lines.push(registers.join("  "));
+    }
+    lines
+}
+
+/// Redraw the whole screen from the top left corner, clearing whatever is left below.
+fn draw(system: &System, options: &Options) -> io::Result<()> {
+    let mut lines = if options.braille {
+        braille(system.display())
+    } else {
+        half_blocks(system.display())
+    };
+    if options.status {
+        lines.push(String::new());
+        lines.extend(status(system));
+    }
+
+    let mut screen = "\x1b[H".to_string();
+    for line in lines {
+        screen += &line;
+        screen += "\x1b[K\r\n";
+    }
+    screen += "\x1b[J";
+
+    let mut stdout = io::stdout();
+    stdout.write_all(screen.as_bytes())?;
+    stdout.flush()
+}
+
+/// Run the system at 60 frames a second until it halts, fails or Ctrl-C is pressed.
+fn run(system: &mut System, options: &Options, input: &Receiver<u8>) -> Result<(), String> {
+    // terminals only say when a key is typed, so each one is let go once the terminal has not
+    // sent it again for a while
+    let mut held: BTreeMap<Key, Instant> = BTreeMap::new();
+    let mut deadline = Instant::now();
+    let mut rewind = Rewind::new(REWIND_BUDGET);
+    rewind.push(system).map_err(|error| error.to_string())?;
+
+    while !system.is_halted() {
+        let now = Instant::now();
+        while let Ok(byte) = input.try_recv() {
+            if byte == INTERRUPT {
+                return Ok(());
+            }
+
+            let key = byte.to_ascii_lowercase() as Key;
+            if held.insert(key, now + options.hold).is_none() {
+                system.handle_input(key, KeyEventType::KeyPress);
+            }
+        }
+
+        let released: Vec<Key> = held.iter()
+            .filter(|&(_, &until)| until <= now)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in released {
+            held.remove(&key);
+            system.handle_input(key, KeyEventType::KeyRelease);
+        }
+
+        if held.contains_key(&REWIND_KEY) {
+            rewind.rewind(1, system).map_err(|error| error.to_string())?;
+            // the keys saved with that frame are not the ones held now
+            for index in 0..0x10 {
+                system.set_key(index, false);
+            }
+            for &key in held.keys() {
+                system.handle_input(key, KeyEventType::KeyPress);
+            }
+        } else {
+            let silent = system.sound_timer() == 0;
+            if let Err(result) = system.run_frame(options.instructions_per_frame) {
+                return Err(match result.error {
+                    Some(error) => error.to_string(),
+                    None => format!("stopped at {:04X}", result.address)
+                });
+            }
+            if silent && system.sound_timer() > 0 {
+                print!("\x07");
+            }
+            rewind.push(system).map_err(|error| error.to_string())?;
+        }
+
+        if system.take_display_dirty() || options.status {
+            draw(system, options).map_err(|error| error.to_string())?;
+        }
+
+        deadline += FRAME;
+        let now = Instant::now();
+        if deadline > now {
+            thread::sleep(deadline - now);
+        } else {
+            deadline = now;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let options = parse_options();
+
+    let mut system = System::new();
+    system.set_platform(options.platform);
+    if let Some(seed) = options.seed {
+        system.seed_random(seed);
+    }
+    let storage = match options.flags {
+        Some(ref directory) => Some(FileFlagStorage::new(directory)),
+        None => FileFlagStorage::for_user()
+    };
+    if let Some(storage) = storage {
+        system.set_flag_storage(Some(Rc::new(RefCell::new(storage))));
+    }
+    if let Err(error) = system.load_rom_file(&options.rom) {
+        fail(&format!("could not load {}: {}", options.rom, error));
+    }
+
+    let result = RawMode::enable().and_then(|raw_mode| {
+        let input = spawn_input();
+        let result = run(&mut system, &options, &input);
+        drop(raw_mode);
+        result
+    });
+    if let Err(message) = result {
+        fail(&message);
+    }
+}
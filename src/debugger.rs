@@ -0,0 +1,470 @@
+use std::collections::BTreeSet;
+use std::mem::{self, Discriminant};
+
+use system::{System, OpCode, StepResult, Chip8Error, ShiftSource, CpuState};
+use system::{Address, RegisterIndex};
+
+/// Something a watchpoint can watch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// A byte of memory.
+    Memory(Address),
+    /// One of the registers V0 to VF.
+    Register(RegisterIndex)
+}
+
+/// The kinds of access a watchpoint reacts to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// The value is read.
+    Read,
+    /// The value is written.
+    Write,
+    /// Either of the above.
+    ReadWrite
+}
+
+impl Access {
+    /// Whether a watchpoint for this kind of access is triggered by `access`.
+    fn covers(&self, access: Access) -> bool {
+        *self == Access::ReadWrite || *self == access
+    }
+}
+
+/// Why the debugger handed control back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The program counter reached a breakpoint.
+    Breakpoint(Address),
+    /// The next instruction would access a watched location.
+    Watchpoint { location: Location, access: Access },
+    /// The next instruction is of a class being broken on.
+    OpCode(OpCode),
+    /// A single step, step over or step out finished.
+    Stepped,
+    /// The condition given to `run_until` became true.
+    Condition,
+    /// The CPU was halted by 00FD.
+    Halted,
+    /// An instruction failed.
+    Error(Chip8Error),
+    /// The cycle budget ran out before anything else happened.
+    CycleLimit
+}
+
+/// Wraps a System to run it under control: stopping at breakpoints and watchpoints, and
+/// stepping by instruction or by subroutine.
+///
+/// Every way of running takes a cycle budget, so a frontend can drive the debugger a frame at
+/// a time and stay responsive. Breakpoints, watchpoints and opcode classes are checked before an
+/// instruction runs, except for the first instruction of each call, so that running again
+/// moves on from where the last stop was.
+#[derive(Clone, Debug)]
+pub struct Debugger {
+    system: System,
+    breakpoints: BTreeSet<Address>,
+    watchpoints: Vec<(Location, Access)>,
+    opcode_breaks: Vec<Discriminant<OpCode>>
+}
+
+impl Debugger {
+    /// Creates a new Debugger controlling `system`.
+    pub fn new(system: System) -> Self {
+        Debugger {
+            system,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            opcode_breaks: Vec::new()
+        }
+    }
+
+    /// The system being debugged.
+    pub fn system(&self) -> &System {
+        &self.system
+    }
+
+    /// The system being debugged, for feeding it input or changing its configuration.
+    pub fn system_mut(&mut self) -> &mut System {
+        &mut self.system
+    }
+
+    /// Stop debugging and take back the system.
+    pub fn into_system(self) -> System {
+        self.system
+    }
+
+    /// Stop before executing the instruction at `address`.
+    pub fn add_breakpoint(&mut self, address: Address) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Remove the breakpoint at `address`, returning whether there was one.
+    pub fn remove_breakpoint(&mut self, address: Address) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// The addresses with breakpoints, in order.
+    pub fn breakpoints(&self) -> Vec<Address> {
+        self.breakpoints.iter().cloned().collect()
+    }
+
+    /// Stop before any instruction that makes an `access` of `location`.
+    pub fn add_watchpoint(&mut self, location: Location, access: Access) {
+        self.watchpoints.push((location, access));
+    }
+
+    /// Remove every watchpoint on `location`.
+    pub fn remove_watchpoint(&mut self, location: Location) {
+        self.watchpoints.retain(|&(watched, _)| watched != location);
+    }
+
+    /// Stop before any instruction of the same kind as `example`, whatever its operands, e.g.
+    /// `OpCode::Draw(0, 0, 0)` for every DXYN.
+    pub fn break_on_opcode(&mut self, example: OpCode) {
+        self.opcode_breaks.push(mem::discriminant(&example));
+    }
+
+    /// Stop breaking on instructions of the same kind as `example`.
+    pub fn clear_opcode_break(&mut self, example: OpCode) {
+        let class = mem::discriminant(&example);
+        self.opcode_breaks.retain(|&kind| kind != class);
+    }
+
+    /// Remove all breakpoints, watchpoints and opcode class breaks.
+    pub fn clear_all(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.opcode_breaks.clear();
+    }
+
+    /// Execute exactly one instruction, regardless of breakpoints.
+    pub fn step(&mut self) -> StepResult {
+        self.system.step()
+    }
+
+    /// Execute one instruction, running any subroutine it calls through to its return.
+    pub fn step_over(&mut self, max_cycles: usize) -> StopReason {
+        let depth = self.system.stack().len();
+        let next = self.system.pc().wrapping_add(2);
+        match self.next_opcode() {
+            Some(OpCode::SubJump(_)) => self.run_while(max_cycles, |system, _| {
+                system.pc() == next && system.stack().len() == depth
+            }),
+            _ => self.run_while(max_cycles, |_, _| true)
+        }
+    }
+
+    /// Run until the current subroutine returns to its caller.
+    pub fn step_out(&mut self, max_cycles: usize) -> StopReason {
+        let depth = self.system.stack().len();
+        self.run_while(max_cycles, |system, result| {
+            result.opcode == OpCode::SubReturn && system.stack().len() < depth
+        })
+    }
+
+    /// Run until something stops the program or `max_cycles` cycles have passed.
+    pub fn run(&mut self, max_cycles: usize) -> StopReason {
+        self.run_while(max_cycles, |_, _| false)
+    }
+
+    /// Run until `condition` holds after an instruction, or something else stops the program.
+    pub fn run_until<F>(&mut self, max_cycles: usize, mut condition: F) -> StopReason
+        where F: FnMut(&System) -> bool {
+        match self.run_while(max_cycles, |system, _| condition(system)) {
+            StopReason::Stepped => StopReason::Condition,
+            reason => reason
+        }
+    }
+
+    /// Run cycles until `done` says the last one finished the job, which is reported as
+    /// `StopReason::Stepped`, or some other reason to stop comes up.
+    fn run_while<F>(&mut self, max_cycles: usize, mut done: F) -> StopReason
+        where F: FnMut(&System, &StepResult) -> bool {
+        for cycle in 0..max_cycles {
+            if self.system.is_halted() {
+                return StopReason::Halted;
+            }
+            if cycle > 0 {
+                if let Some(reason) = self.check_stop() {
+                    return reason;
+                }
+            }
+
+            let result = self.system.step();
+            if let Some(error) = result.error {
+                return StopReason::Error(error);
+            }
+            if done(&self.system, &result) {
+                return StopReason::Stepped;
+            }
+        }
+
+        if self.system.is_halted() {
+            return StopReason::Halted;
+        }
+        StopReason::CycleLimit
+    }
+
+    /// The instruction the CPU will execute next, if it is running.
+    fn next_opcode(&self) -> Option<OpCode> {
+        if self.system.cpu_state() != CpuState::Running {
+            return None;
+        }
+
+        self.system.read_word(self.system.pc() as usize).ok().map(OpCode::from)
+    }
+
+    /// Whether a breakpoint, opcode class break or watchpoint applies to the next instruction.
+    fn check_stop(&self) -> Option<StopReason> {
+        let opcode = self.next_opcode()?;
+
+        if self.breakpoints.contains(&self.system.pc()) {
+            return Some(StopReason::Breakpoint(self.system.pc()));
+        }
+        if self.opcode_breaks.contains(&mem::discriminant(&opcode)) {
+            return Some(StopReason::OpCode(opcode));
+        }
+
+        for (location, access) in accesses(&opcode, &self.system) {
+            for &(watched, kind) in self.watchpoints.iter() {
+                if watched == location && kind.covers(access) {
+                    return Some(StopReason::Watchpoint { location, access });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// The registers and memory `opcode` would read and write if it ran on `system` now.
+/// Instruction fetches and the stack are not included.
+pub fn accesses(opcode: &OpCode, system: &System) -> Vec<(Location, Access)> {
+    use self::Access::{Read, Write};
+
+    let mut accesses = Vec::new();
+    {
+        let mut register = |index: RegisterIndex, access| {
+            accesses.push((Location::Register(index), access));
+        };
+
+        match *opcode {
+            OpCode::SkipValue(left, _) | OpCode::SkipNotValue(left, _)
+                | OpCode::SetDelayTimer(left) | OpCode::SetSoundTimer(left)
+                | OpCode::SkipKeyPressed(left) | OpCode::SkipKeyNotPressed(left)
+                | OpCode::AddIndex(left) | OpCode::SetIndexToSprite(left)
+                | OpCode::SetIndexToLargeSprite(left) | OpCode::StoreDecimal(left)
+                | OpCode::SetPitch(left) => {
+                register(left, Read);
+            },
+
+            OpCode::SkipRegister(left, right) | OpCode::SkipNotRegister(left, right) => {
+                register(left, Read);
+                register(right, Read);
+            },
+
+            OpCode::SetRegisterFromTimer(left) | OpCode::SetValue(left, _)
+                | OpCode::RandomValue(left, _) | OpCode::WaitKeyPress(left) => {
+                register(left, Write);
+            },
+
+            OpCode::AddValue(left, _) => {
+                register(left, Read);
+                register(left, Write);
+            },
+
+            OpCode::SetRegister(left, right) => {
+                register(right, Read);
+                register(left, Write);
+            },
+
+            OpCode::OrRegister(left, right) | OpCode::AndRegister(left, right)
+                | OpCode::XorRegister(left, right) => {
+                register(left, Read);
+                register(right, Read);
+                register(left, Write);
+                if system.quirks().logic_resets_vf {
+                    register(0xF, Write);
+                }
+            },
+
+            OpCode::AddRegister(left, right) | OpCode::SubRegisterRight(left, right)
+                | OpCode::SubRegisterLeft(left, right) => {
+                register(left, Read);
+                register(right, Read);
+                register(left, Write);
+                register(0xF, Write);
+            },
+
+            OpCode::RShiftRegister(left, right) | OpCode::LShiftRegister(left, right) => {
+                let source = match system.quirks().shift_source {
+                    ShiftSource::Vy => right,
+                    ShiftSource::Vx => left
+                };
+                register(source, Read);
+                register(left, Write);
+                register(0xF, Write);
+            },
+
+            OpCode::JumpAddressOffset(address) => {
+                let offset = if system.quirks().jump_uses_vx { (address >> 8) & 0xF } else { 0 };
+                register(offset as RegisterIndex, Read);
+            },
+
+            OpCode::Draw(left, right, _) => {
+                register(left, Read);
+                register(right, Read);
+                register(0xF, Write);
+            },
+
+            OpCode::StoreRegisters(left) | OpCode::SaveFlags(left) => {
+                for index in 0..left + 1 {
+                    register(index, Read);
+                }
+            },
+
+            OpCode::LoadRegisters(left) | OpCode::LoadFlags(left) => {
+                for index in 0..left + 1 {
+                    register(index, Write);
+                }
+            },
+
+            OpCode::SaveRange(left, right) | OpCode::LoadRange(left, right) => {
+                let access = match *opcode { OpCode::SaveRange(..) => Read, _ => Write };
+                let (low, high) = (left.min(right), left.max(right));
+                for index in low..high + 1 {
+                    register(index, access);
+                }
+            },
+
+            _ => ()
+        }
+    }
+
+    let (length, access) = match *opcode {
+        OpCode::Draw(_, _, rows) => {
+            let large = rows == 0 && system.platform().has_super_chip();
+            let rows = if large { 32 } else { rows as usize };
+            (rows * system.display().plane_count(), Read)
+        },
+        OpCode::StoreDecimal(_) => (3, Write),
+        OpCode::StoreRegisters(left) => (left + 1, Write),
+        OpCode::LoadRegisters(left) => (left + 1, Read),
+        OpCode::SaveRange(left, right) => (left.max(right) - left.min(right) + 1, Write),
+        OpCode::LoadRange(left, right) => (left.max(right) - left.min(right) + 1, Read),
+        OpCode::LoadAudioPattern => (16, Read),
+        _ => (0, Read)
+    };
+    for offset in 0..length {
+        let address = system.index().wrapping_add(offset as Address);
+        accesses.push((Location::Memory(address), access));
+    }
+
+    accesses
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use system::Quirks;
+
+    /** A system running a small program: a loop calling a subroutine that draws. */
+    fn debugger() -> Debugger {
+        let mut system = System::new();
+        system.load_rom(&[
+            0x60, 0x05,     // 0x200: V0 = 5
+            0xA3, 0x00,     // 0x202: I = 0x300
+            0x22, 0x0A,     // 0x204: call 0x20A
+            0x12, 0x04,     // 0x206: jump 0x204
+            0x00, 0x00,
+            0x70, 0x01,     // 0x20A: V0 += 1
+            0xD0, 0x01,     // 0x20C: draw
+            0xF0, 0x55,     // 0x20E: save V0
+            0x00, 0xEE      // 0x210: return
+        ][..]).unwrap();
+        // no display wait, and I left alone so every save lands on 0x300
+        system.set_quirks(Quirks::super_chip());
+        Debugger::new(system)
+    }
+
+    /** Running should stop at a breakpoint, and running again should move past it. */
+    #[test]
+    fn breakpoint() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(0x20C);
+
+        assert_eq!(StopReason::Breakpoint(0x20C), debugger.run(100));
+        assert_eq!(0x06, debugger.system().registers()[0x0]);
+        assert_eq!(StopReason::Breakpoint(0x20C), debugger.run(100));
+        assert_eq!(0x07, debugger.system().registers()[0x0]);
+
+        assert!(debugger.remove_breakpoint(0x20C));
+        assert_eq!(StopReason::CycleLimit, debugger.run(100));
+    }
+
+    /** Watchpoints should stop before an instruction touching the watched register or memory. */
+    #[test]
+    fn watchpoints() {
+        let mut debugger = debugger();
+        debugger.add_watchpoint(Location::Register(0xF), Access::Write);
+        let reason = debugger.run(100);
+        assert_eq!(StopReason::Watchpoint {
+            location: Location::Register(0xF),
+            access: Access::Write
+        }, reason);
+        assert_eq!(0x20C, debugger.system().pc());
+
+        debugger.remove_watchpoint(Location::Register(0xF));
+        debugger.add_watchpoint(Location::Memory(0x300), Access::Write);
+        let reason = debugger.run(100);
+        assert_eq!(StopReason::Watchpoint {
+            location: Location::Memory(0x300),
+            access: Access::Write
+        }, reason);
+        assert_eq!(0x20E, debugger.system().pc());
+    }
+
+    /** Breaking on an opcode class should stop on any instruction of that kind. */
+    #[test]
+    fn opcode_class() {
+        let mut debugger = debugger();
+        debugger.break_on_opcode(OpCode::Draw(0, 0, 0));
+
+        assert_eq!(StopReason::OpCode(OpCode::Draw(0x0, 0x0, 0x1)), debugger.run(100));
+        debugger.clear_opcode_break(OpCode::Draw(0xF, 0xF, 0xF));
+        assert_eq!(StopReason::CycleLimit, debugger.run(100));
+    }
+
+    /** Stepping over a call should run the whole subroutine, and stepping out should run to the
+      *     end of the current one. */
+    #[test]
+    fn step_over_and_out() {
+        let mut debugger = debugger();
+        debugger.step();
+        debugger.step();
+
+        assert_eq!(StopReason::Stepped, debugger.step_over(100));
+        assert_eq!(0x206, debugger.system().pc());
+        assert_eq!(0x06, debugger.system().registers()[0x0]);
+
+        assert_eq!(StopReason::Stepped, debugger.step_over(100));
+        assert_eq!(0x204, debugger.system().pc());
+        debugger.step();
+        debugger.step();
+        assert_eq!(StopReason::Stepped, debugger.step_out(100));
+        assert_eq!(0x206, debugger.system().pc());
+        assert!(debugger.system().stack().is_empty());
+    }
+
+    /** Running until a condition should stop on the first instruction after which it holds. */
+    #[test]
+    fn run_until() {
+        let mut debugger = debugger();
+
+        let reason = debugger.run_until(1000, |system| system.registers()[0x0] == 0x09);
+        assert_eq!(StopReason::Condition, reason);
+        assert_eq!(0x20C, debugger.system().pc());
+        assert_eq!(StopReason::CycleLimit, debugger.run_until(10, |_| false));
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use system::{OpCode, Platform, Address, Byte, Word};
+
+/// Most data bytes shown on a single line of a listing.
+const DATA_PER_LINE: usize = 4;
+
+/// The assembly language a listing is written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// Classic mnemonics from Cowgod's CHIP-8 technical reference, e.g. `LD V0, #64`.
+    Cowgod,
+    /// Octo's high-level assembly, e.g. `v0 := 0x64`.
+    Octo
+}
+
+/// What a line of a listing holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Content {
+    /// An instruction reachable from the entry point.
+    Code(OpCode),
+    /// Bytes never reached as code, such as sprites.
+    Data
+}
+
+/// A single instruction or run of data bytes in a ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// Where the line starts in memory.
+    pub address: Address,
+    /// The bytes making up the line.
+    pub bytes: Vec<Byte>,
+    /// What the bytes are.
+    pub content: Content
+}
+
+/// A ROM split into code and data, found by following every jump, call and skip from the
+/// entry point, with labels for the targets of jumps and calls.
+#[derive(Clone, Debug)]
+pub struct Disassembly {
+    lines: Vec<Line>,
+    labels: BTreeSet<Address>
+}
+
+impl Disassembly {
+    /// Disassemble `rom`, loaded at and entered from `origin`. The platform decides whether
+    /// F000 NNNN is a four byte instruction.
+    pub fn new(rom: &[Byte], origin: Address, platform: Platform) -> Self {
+        let start = origin as usize;
+        let end = start + rom.len();
+        let word_at = |address: usize| -> Option<Word> {
+            if address < start || address + 2 > end {
+                return None;
+            }
+            Some((rom[address - start] as Word) << 8 | rom[address - start + 1] as Word)
+        };
+        let length_at = |address: usize| {
+            match word_at(address) {
+                Some(0xF000) if platform.has_xo_chip() => 4,
+                _ => 2
+            }
+        };
+
+        let mut code = BTreeMap::new();
+        let mut targets = BTreeSet::new();
+        let mut pending = vec![start];
+        while let Some(address) = pending.pop() {
+            if code.contains_key(&address) {
+                continue;
+            }
+            let opcode = match word_at(address).map(OpCode::from) {
+                Some(OpCode::Unknown) | None => continue,
+                Some(opcode) => opcode
+            };
+            let length = length_at(address);
+            if address + length > end {
+                continue;
+            }
+            code.insert(address, (opcode, length));
+
+            let next = address + length;
+            match opcode {
+                OpCode::JumpAddress(target) => {
+                    targets.insert(target);
+                    pending.push(target as usize);
+                },
+                OpCode::SubJump(target) => {
+                    targets.insert(target);
+                    pending.push(target as usize);
+                    pending.push(next);
+                },
+                // the target depends on a register, so only the base can be labelled
+                OpCode::JumpAddressOffset(target) => {
+                    targets.insert(target);
+                },
+                OpCode::SubReturn | OpCode::Exit => (),
+                OpCode::SkipValue(..) | OpCode::SkipNotValue(..) | OpCode::SkipRegister(..)
+                    | OpCode::SkipNotRegister(..) | OpCode::SkipKeyPressed(_)
+                    | OpCode::SkipKeyNotPressed(_) => {
+                    pending.push(next);
+                    pending.push(next + length_at(next));
+                },
+                _ => pending.push(next)
+            }
+        }
+
+        let mut lines: Vec<Line> = Vec::new();
+        let mut address = start;
+        while address < end {
+            match code.get(&address) {
+                Some(&(opcode, length)) => {
+                    lines.push(Line {
+                        address: address as Address,
+                        bytes: rom[address - start..address - start + length].to_vec(),
+                        content: Content::Code(opcode)
+                    });
+                    address += length;
+                },
+                None => {
+                    let continues = match lines.last() {
+                        Some(line) => line.content == Content::Data
+                            && line.bytes.len() < DATA_PER_LINE
+                            && !targets.contains(&(address as Address)),
+                        None => false
+                    };
+                    if continues {
+                        lines.last_mut().unwrap().bytes.push(rom[address - start]);
+                    } else {
+                        lines.push(Line {
+                            address: address as Address,
+                            bytes: vec![rom[address - start]],
+                            content: Content::Data
+                        });
+                    }
+                    address += 1;
+                }
+            }
+        }
+
+        let starts: BTreeSet<Address> = lines.iter().map(|line| line.address).collect();
+        let labels = targets.intersection(&starts).cloned().collect();
+        Disassembly { lines, labels }
+    }
+
+    /// The lines of the listing, in address order.
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// The addresses that have labels.
+    pub fn labels(&self) -> &BTreeSet<Address> {
+        &self.labels
+    }
+
+    /// The name of the label at `address`.
+    pub fn label_name(address: Address) -> String {
+        format!("L{:03X}", address)
+    }
+
+    /// A listing of the whole ROM: labels on lines of their own, then the address, bytes and
+    /// mnemonic of each line.
+    pub fn render(&self, syntax: Syntax) -> String {
+        let mut listing = String::new();
+        for line in self.lines.iter() {
+            if self.labels.contains(&line.address) {
+                let label = Disassembly::label_name(line.address);
+                match syntax {
+                    Syntax::Cowgod  => writeln!(listing, "{}:", label).unwrap(),
+                    Syntax::Octo    => writeln!(listing, ": {}", label).unwrap()
+                }
+            }
+
+            let bytes: Vec<String> = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text = self.text(line, syntax);
+            writeln!(listing, "{:04X}  {:<12}{}", line.address, bytes.join(" "), text).unwrap();
+        }
+
+        listing
+    }
+
+    /// The mnemonic for a line, with branch targets replaced by their labels.
+    pub fn text(&self, line: &Line, syntax: Syntax) -> String {
+        let opcode = match line.content {
+            Content::Code(opcode) => opcode,
+            Content::Data => {
+                let bytes: Vec<String> = line.bytes.iter().map(|&byte| match syntax {
+                    Syntax::Cowgod  => format!("#{:02X}", byte),
+                    Syntax::Octo    => format!("0x{:02X}", byte)
+                }).collect();
+                return match syntax {
+                    Syntax::Cowgod  => format!("DB {}", bytes.join(", ")),
+                    Syntax::Octo    => bytes.join(" ")
+                };
+            }
+        };
+
+        let text = match syntax {
+            Syntax::Cowgod  => opcode.to_string(),
+            Syntax::Octo    => opcode.to_octo()
+        };
+        let target = match opcode {
+            OpCode::JumpAddress(target) | OpCode::SubJump(target)
+                | OpCode::JumpAddressOffset(target) => target,
+            OpCode::SetIndexLong if line.bytes.len() == 4 => {
+                let address = (line.bytes[2] as Address) << 8 | line.bytes[3] as Address;
+                return match syntax {
+                    Syntax::Cowgod  => format!("{} #{:04X}", text, address),
+                    Syntax::Octo    => format!("{} 0x{:04X}", text, address)
+                };
+            },
+            _ => return text
+        };
+        if !self.labels.contains(&target) {
+            return text;
+        }
+
+        // the target is always the last operand
+        let operands = text.rfind(' ').unwrap();
+        format!("{} {}", &text[..operands], Disassembly::label_name(target))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** A small program, with a sprite after the code and a skip over a jump. */
+    static ROM: [Byte; 16] = [
+        0xA2, 0x0E,     // 0x200: I = sprite
+        0x22, 0x08,     // 0x202: call 0x208
+        0x12, 0x02,     // 0x204: jump 0x202
+        0xFF, 0xFF,     // 0x206: never reached
+        0x3F, 0x00,     // 0x208: skip if VF == 0
+        0x00, 0xE0,     // 0x20A: clear
+        0x00, 0xEE,     // 0x20C: return
+        0x80, 0xC0      // 0x20E: sprite
+    ];
+
+    /** Code reached through jumps, calls and skips should be found, and the rest left as data. */
+    #[test]
+    fn separates_code_and_data() {
+        let disassembly = Disassembly::new(&ROM, 0x200, Platform::Chip8);
+        let kinds: Vec<(Address, Content)> = disassembly.lines().iter()
+            .map(|line| (line.address, line.content)).collect();
+
+        assert_eq!(vec![
+            (0x200, Content::Code(OpCode::SetIndex(0x20E))),
+            (0x202, Content::Code(OpCode::SubJump(0x208))),
+            (0x204, Content::Code(OpCode::JumpAddress(0x202))),
+            (0x206, Content::Data),
+            (0x208, Content::Code(OpCode::SkipValue(0xF, 0x00))),
+            (0x20A, Content::Code(OpCode::ClearScreen)),
+            (0x20C, Content::Code(OpCode::SubReturn)),
+            (0x20E, Content::Data)
+        ], kinds);
+        assert_eq!(vec![0x202, 0x208], disassembly.labels().iter().cloned().collect::<Vec<_>>());
+    }
+
+    /** The listing should label branch targets and show addresses, bytes and mnemonics. */
+    #[test]
+    fn render() {
+        let disassembly = Disassembly::new(&ROM, 0x200, Platform::Chip8);
+
+        assert_eq!("\
+0200  A2 0E       LD I, #20E
+L202:
+0202  22 08       CALL L208
+0204  12 02       JP L202
+0206  FF FF       DB #FF, #FF
+L208:
+0208  3F 00       SE VF, #00
+020A  00 E0       CLS
+020C  00 EE       RET
+020E  80 C0       DB #80, #C0
+", disassembly.render(Syntax::Cowgod));
+
+        let octo = disassembly.render(Syntax::Octo);
+        assert!(octo.contains(": L208\n0208  3F 00       if vf != 0x00 then\n"));
+        assert!(octo.contains("0202  22 08       :call L208\n"));
+    }
+
+    /** On XO-CHIP, F000 NNNN should be one instruction, and skips should step over all of it. */
+    #[test]
+    fn long_instruction() {
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xFD];
+        let disassembly = Disassembly::new(&rom, 0x200, Platform::XoChip);
+
+        let addresses: Vec<Address> = disassembly.lines().iter().map(|line| line.address).collect();
+        assert_eq!(vec![0x200, 0x202, 0x206], addresses);
+        assert_eq!("LD I, LONG #1234", disassembly.text(&disassembly.lines()[1], Syntax::Cowgod));
+    }
+}
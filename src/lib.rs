@@ -0,0 +1,5 @@
+pub mod system;
+pub mod debugger;
+pub mod disasm;
+pub mod asm;
+pub mod octo;
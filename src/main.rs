@@ -1,26 +1,177 @@
-mod system;
-mod macros;
+extern crate chip8_interpreter;
 extern crate gtk;
 extern crate gdk;
+mod macros;
 
 use gtk::prelude::*;
-use gtk::{Window, WindowType};
+use gtk::{Continue, DrawingArea, Window, WindowType};
 
 use std::cell::{RefCell, RefMut};
-use system::{System, KeyEventType};
+use std::env;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use chip8_interpreter::system::{System, Platform, KeyEventType, FileFlagStorage};
+
+const USAGE: &str = "usage: chip8-interpreter [--platform chip8|schip|xochip] [--flags DIR] ROM";
+
+/// Instructions run in each 60 Hz frame.
+const INSTRUCTIONS_PER_FRAME: usize = 15;
+/// Length of a 60 Hz frame.
+const FRAME: Duration = Duration::from_micros(16_667);
+/// Milliseconds between checks for frames that are due. Glib timeouts run late and drift, so
+/// this is only how often to look; the frames themselves are counted from the clock.
+const POLL_INTERVAL: u32 = 4;
+/// Most frames run in one go after a stall, rather than racing to catch up.
+const MAX_FRAMES_BEHIND: u32 = 4;
+/// Red, green and blue for the four XO-CHIP colours, the same greys chip8-headless uses.
+const PALETTE: [(f64, f64, f64); 4] = [
+    (0.0, 0.0, 0.0),
+    (1.0, 1.0, 1.0),
+    (0.667, 0.667, 0.667),
+    (0.333, 0.333, 0.333)
+];
+/// Colour of the bars around the display when the window is not the same shape.
+const LETTERBOX: (f64, f64, f64) = (0.1, 0.1, 0.1);
+
+/// The platform, user flag directory and ROM given on the command line.
+fn parse_args() -> Option<(Platform, Option<String>, String)> {
+    let mut platform = Platform::Chip8;
+    let mut flags = None;
+    let mut rom = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--platform" {
+            platform = args.next()?.parse().ok()?;
+        } else if arg == "--flags" {
+            flags = Some(args.next()?);
+        } else if rom.is_none() && !arg.starts_with("--") {
+            rom = Some(arg);
+        } else {
+            return None;
+        }
+    }
+
+    rom.map(|rom| (platform, flags, rom))
+}
+
+/// The largest whole number of window pixels per display pixel that fits in the window, at
+/// least one, and the left and top offsets that centre the display.
+fn layout(width: i32, height: i32, display_width: usize, display_height: usize)
+          -> (i32, i32, i32) {
+    let (display_width, display_height) = (display_width as i32, display_height as i32);
+    let scale = (width / display_width).min(height / display_height).max(1);
+    let left = (width - display_width * scale) / 2;
+    let top = (height - display_height * scale) / 2;
+    (scale, left, top)
+}
 
 fn main() {
+    let (platform, flags, rom) = match parse_args() {
+        Some(args) => args,
+        None => {
+            println!("{}", USAGE);
+            return;
+        }
+    };
+
     if gtk::init().is_err() {
         println!("Failed to initialize GTK.");
         return;
     }
 
+    let mut system = System::new();
+    system.set_platform(platform);
+    // SUPER-CHIP games keep high scores in the user flags, so they should outlive the window
+    let storage = match flags {
+        Some(directory) => Some(FileFlagStorage::new(directory)),
+        None => FileFlagStorage::for_user()
+    };
+    if let Some(storage) = storage {
+        system.set_flag_storage(Some(Rc::new(RefCell::new(storage))));
+    }
+    if let Err(error) = system.load_rom_file(&rom) {
+        println!("Failed to load {}: {}", rom, error);
+        return;
+    }
+    // shared between the callbacks, which would each get their own copy of a bare RefCell
+    let system: Rc<RefCell<System>> = Rc::new(RefCell::new(system));
+
+    let name = Path::new(&rom).file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| rom.clone());
+
     let window = Window::new(WindowType::Toplevel);
-    window.set_title("Chip8 Interpreter");
+    window.set_title(&format!("{} - Chip8 Interpreter", name));
     window.set_default_size(800, 500);
+
+    let area = DrawingArea::new();
+    window.add(&area);
     window.show_all();
 
-    let system: RefCell<System> = RefCell::new(System::new());
+    // callback for draw event
+    area.connect_draw(clone!( system => move |area, context| {
+        let p_system: RefMut<System> = wait_for_borrow!(system);
+        let display = p_system.display();
+
+        let (scale, left, top) = layout(area.get_allocated_width(), area.get_allocated_height(),
+                                        display.width(), display.height());
+        let scale = scale as f64;
+
+        let (red, green, blue) = LETTERBOX;
+        context.set_source_rgb(red, green, blue);
+        context.paint();
+
+        let (red, green, blue) = PALETTE[0];
+        context.set_source_rgb(red, green, blue);
+        context.rectangle(left as f64, top as f64,
+                          display.width() as f64 * scale, display.height() as f64 * scale);
+        context.fill();
+
+        for y in 0..display.height() {
+            for x in 0..display.width() {
+                let colour = display.colour(x, y) as usize;
+                if colour == 0 {
+                    continue;
+                }
+
+                let (red, green, blue) = PALETTE[colour];
+                context.set_source_rgb(red, green, blue);
+                context.rectangle(left as f64 + x as f64 * scale, top as f64 + y as f64 * scale,
+                                  scale, scale);
+                context.fill();
+            }
+        }
+
+        Inhibit(false)
+    }));
+
+    // run however many frames are due by the clock, redrawing whenever the display changes
+    let mut last = Instant::now();
+    let mut behind = Duration::from_secs(0);
+    gtk::timeout_add(POLL_INTERVAL, clone!( system, area => move || {
+        let now = Instant::now();
+        behind = (behind + (now - last)).min(FRAME * MAX_FRAMES_BEHIND);
+        last = now;
+
+        let mut p_system: RefMut<System> = wait_for_borrow!(system);
+        while behind >= FRAME && !p_system.is_halted() {
+            behind -= FRAME;
+            if let Err(result) = p_system.run_frame(INSTRUCTIONS_PER_FRAME) {
+                match result.error {
+                    Some(error) => println!("Stopped: {}", error),
+                    None => println!("Stopped at {:04X}", result.address)
+                }
+                return Continue(false);
+            }
+        }
+        if p_system.take_display_dirty() {
+            area.queue_draw();
+        }
+
+        Continue(!p_system.is_halted())
+    }));
 
     // callback for key press event
     window.connect_key_press_event(clone!( system => move |_, key| {
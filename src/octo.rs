@@ -0,0 +1,1060 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use asm::{AsmError, Program, Width};
+use system::{Address, Byte, Word, RegisterIndex, PROGRAM_ADDRESS};
+
+/// How many macros a program may expand before the compiler assumes one expands itself forever.
+const MAX_EXPANSIONS: usize = 100_000;
+/// The register `if` uses as scratch space for `<`, `>`, `<=` and `>=`, as in Octo.
+const COMPARE_TEMP: Word = 0xF;
+/// One past the last address a program can occupy.
+const MEMORY_END: usize = 0x10000;
+
+/// Words that can never be a label, constant or macro name.
+const RESERVED: &[&str] = &[
+    ":=", "|=", "&=", "^=", "-=", "=-", "+=", ">>=", "<<=", "==", "!=", "<", ">", "<=", ">=",
+    "key", "-key", "hex", "bighex", "random", "delay", "buzzer", "pitch", "long", "i", "then",
+    "begin", "else", "end", "loop", "while", "again", "if", "jump", "jump0", "native", "return",
+    "clear", "bcd", "save", "load", "sprite", "scroll-up", "scroll-down", "scroll-left",
+    "scroll-right", "exit", "lores", "hires", "saveflags", "loadflags", "plane", "audio"
+];
+
+/// Compile Octo source held in a string into a ROM loaded at 0x200.
+///
+/// As in Octo, 0x200 holds a jump to `: main`, which must be defined, unless `: main` comes
+/// first and the jump would only skip itself. Supported are all of Octo's instructions and `if ... then`, `if ... begin ... else ... end` and `loop ... while ...
+/// again`, along with the `:`, `:next`, `:const`, `:alias`, `:calc`, `:byte`, `:org`,
+/// `:unpack`, `:call` and `:macro` directives and `{ ... }` expressions wherever a number can
+/// go. `:breakpoint` and `:monitor` are accepted and ignored.
+pub fn compile(source: &str) -> Result<Program, AsmError> {
+    Compiler::new("<source>".to_string(), source).compile()
+}
+
+/// Compile an Octo source file, as `compile` does.
+pub fn compile_file<P: AsRef<Path>>(path: P) -> Result<Program, AsmError> {
+    let path = path.as_ref();
+    let mut source = String::new();
+    if let Err(error) = File::open(path).and_then(|mut file| file.read_to_string(&mut source)) {
+        return Err(AsmError {
+            file: path.display().to_string(),
+            line: 0,
+            column: 0,
+            message: error.to_string()
+        });
+    }
+
+    Compiler::new(path.display().to_string(), &source).compile()
+}
+
+/// A word of source and where it starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize
+}
+
+/// Split source into whitespace separated tokens, dropping `#` comments.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut start = None;
+        for (column, c) in line.chars().chain(Some(' ')).enumerate() {
+            match (start, c.is_whitespace()) {
+                (None, false) if c == '#' => break,
+                (None, false) => start = Some(column),
+                (Some(first), true) => {
+                    let text = line.chars().skip(first).take(column - first).collect();
+                    tokens.push_back(Token { text, line: index + 1, column: first + 1 });
+                    start = None;
+                },
+                _ => ()
+            }
+        }
+    }
+    tokens
+}
+
+/// Read a number written in decimal, or in hex or binary after `0x` or `0b`, with an optional
+/// leading minus sign.
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text)
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }?;
+    Some(if negative { -value } else { value })
+}
+
+/// The register named by `v0` to `vf`.
+fn parse_register(text: &str) -> Option<RegisterIndex> {
+    match text.strip_prefix('v').or_else(|| text.strip_prefix('V')) {
+        Some(digit) if digit.len() == 1 => RegisterIndex::from_str_radix(digit, 16).ok(),
+        _ => None
+    }
+}
+
+/// How a reference to a label is written into the ROM once the label's address is known.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Patch {
+    /// The low 12 bits of the instruction word at the address.
+    Address,
+    /// The whole word at the address, for `i := long`.
+    Long,
+    /// The byte at the address gets the top of the label's address, below an optional nibble,
+    /// for the first half of `:unpack`.
+    High(Option<Byte>),
+    /// The byte at the address gets the bottom byte of the label's address.
+    Low
+}
+
+/// The comparison of an `if` or `while`: VX, the operator, and VY or a value to compare with.
+struct Comparison {
+    x: Word,
+    operator: Token,
+    y: Option<Word>,
+    value: Word
+}
+
+/// A macro's parameters and the tokens it expands to.
+#[derive(Clone, Debug)]
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>
+}
+
+/// Compiles a stream of tokens straight into a ROM image, patching references to labels that
+/// are only defined later.
+struct Compiler {
+    file: String,
+    tokens: VecDeque<Token>,
+    /// The last token read, for errors at the end of the source.
+    last: Token,
+    rom: Vec<Byte>,
+    here: usize,
+    labels: HashMap<String, Address>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, RegisterIndex>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    /// References to labels not yet defined: the name, where to patch and how.
+    unresolved: Vec<(Token, usize, Patch)>,
+    /// The jumps of each open `if ... begin` and `else`, waiting for their destination.
+    branches: Vec<(Token, usize)>,
+    /// Each open `loop`: its start, and the jumps out of it made by `while`.
+    loops: Vec<(Token, usize, Vec<usize>)>,
+    /// Whether 0x200 still holds the jump to `: main`.
+    main_jump: bool
+}
+
+impl Compiler {
+    fn new(file: String, source: &str) -> Self {
+        Compiler {
+            file,
+            tokens: tokenize(source),
+            last: Token { text: String::new(), line: 1, column: 1 },
+            rom: Vec::new(),
+            here: PROGRAM_ADDRESS as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            unresolved: Vec::new(),
+            branches: Vec::new(),
+            loops: Vec::new(),
+            main_jump: false
+        }
+    }
+
+    fn error(&self, token: &Token, message: String) -> AsmError {
+        AsmError { file: self.file.clone(), line: token.line, column: token.column, message }
+    }
+
+    fn compile(mut self) -> Result<Program, AsmError> {
+        self.placeholder_jump()?;
+        self.main_jump = true;
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.last = token.clone();
+            self.statement(token)?;
+        }
+
+        if let Some((token, _)) = self.branches.last() {
+            return Err(self.error(token, format!("`{}` is never closed with `end`", token.text)));
+        }
+        if let Some((token, _, _)) = self.loops.last() {
+            return Err(self.error(token, "`loop` is never closed with `again`".to_string()));
+        }
+        for (token, address, patch) in ::std::mem::take(&mut self.unresolved) {
+            match self.labels.get(&token.text) {
+                Some(&target) => self.patch(&token, address, patch, target as i64)?,
+                None => return Err(self.error(&token, format!("`{}` is not defined", token.text)))
+            }
+        }
+
+        if self.main_jump {
+            let main = match self.labels.get("main") {
+                Some(&main) => main as i64,
+                None => {
+                    let message = "the program has no `: main` to start at".to_string();
+                    return Err(self.error(&self.last, message));
+                }
+            };
+            let token = self.last.clone();
+            self.patch(&token, PROGRAM_ADDRESS as usize, Patch::Address, main)?;
+        }
+
+        let labels: BTreeMap<String, Address> = self.labels.into_iter().collect();
+        Ok(Program { origin: PROGRAM_ADDRESS, rom: self.rom, labels })
+    }
+
+    /// Take the next token, which must exist.
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last = token.clone();
+                Ok(token)
+            },
+            None => Err(self.error(&self.last, "unexpected end of source".to_string()))
+        }
+    }
+
+    /// Take the next token, which must be `text`.
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(self.error(&token, format!("expected `{}`, found `{}`", text, token.text)));
+        }
+        Ok(token)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    /// Whether a token could name a label, constant, alias or macro.
+    fn is_name(text: &str) -> bool {
+        !text.starts_with(':') && parse_number(text).is_none() && parse_register(text).is_none()
+            && !RESERVED.contains(&text) && text != "{" && text != "}" && text != ";"
+            && text != "-"
+    }
+
+    /// Take a token naming something new.
+    fn new_name(&mut self) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if !Compiler::is_name(&token.text) {
+            return Err(self.error(&token, format!("`{}` cannot be used as a name", token.text)));
+        }
+        if self.labels.contains_key(&token.text) || self.constants.contains_key(&token.text)
+                || self.aliases.contains_key(&token.text) || self.macros.contains_key(&token.text) {
+            return Err(self.error(&token, format!("`{}` is already defined", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn register_of(&self, token: &Token) -> Option<RegisterIndex> {
+        parse_register(&token.text).or_else(|| self.aliases.get(&token.text).cloned())
+    }
+
+    fn peek_register(&self) -> Option<RegisterIndex> {
+        self.tokens.front().and_then(|token| self.register_of(token))
+    }
+
+    /// Take a register, named directly or through an alias.
+    fn register(&mut self) -> Result<Word, AsmError> {
+        let token = self.next()?;
+        match self.register_of(&token) {
+            Some(register) => Ok(register as Word),
+            None => Err(self.error(&token, format!("expected a register, found `{}`", token.text)))
+        }
+    }
+
+    /// The value of a number, constant or defined label.
+    fn known_value(&self, text: &str) -> Option<f64> {
+        parse_number(text).map(|value| value as f64)
+            .or_else(|| self.constants.get(text).cloned())
+            .or_else(|| self.labels.get(text).map(|&address| address as f64))
+    }
+
+    /// Take a number, constant, defined label or `{ ... }` expression, which must fit in
+    /// `width`.
+    fn value(&mut self, width: Width) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        let value = if token.text == "{" {
+            self.calc()?
+        } else {
+            match self.known_value(&token.text) {
+                Some(value) => value,
+                None => return Err(self.error(&token, format!("`{}` is not defined", token.text)))
+            }
+        };
+
+        let value = value.floor() as i64;
+        let (min, max, name) = width.range();
+        if value < min || value > max {
+            return Err(self.error(&token, format!("{} does not fit in {}", value, name)));
+        }
+        Ok(value)
+    }
+
+    /// Write bytes at the current address.
+    fn emit(&mut self, bytes: &[Byte]) -> Result<(), AsmError> {
+        if self.here + bytes.len() > MEMORY_END {
+            return Err(self.error(&self.last, "program runs past the end of memory".to_string()));
+        }
+        let offset = self.here - PROGRAM_ADDRESS as usize;
+        if self.rom.len() < offset + bytes.len() {
+            self.rom.resize(offset + bytes.len(), 0);
+        }
+        self.rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len();
+        Ok(())
+    }
+
+    fn instruction(&mut self, word: Word) -> Result<(), AsmError> {
+        self.emit(&[(word >> 8) as Byte, word as Byte])
+    }
+
+    /// Fill in a reference at `address` to the label at `target`.
+    fn patch(&mut self, token: &Token, address: usize, patch: Patch, target: i64)
+            -> Result<(), AsmError> {
+        let max = match patch {
+            Patch::Address | Patch::High(Some(_)) => 0xFFF,
+            _ => 0xFFFF
+        };
+        if target < 0 || target > max {
+            let message = format!("{:#X} is too far away for this reference", target);
+            return Err(self.error(token, message));
+        }
+
+        let offset = address - PROGRAM_ADDRESS as usize;
+        match patch {
+            Patch::Address => {
+                self.rom[offset] = (self.rom[offset] & 0xF0) | (target >> 8) as Byte;
+                self.rom[offset + 1] = target as Byte;
+            },
+            Patch::Long => {
+                self.rom[offset] = (target >> 8) as Byte;
+                self.rom[offset + 1] = target as Byte;
+            },
+            Patch::High(nibble) => {
+                self.rom[offset] = nibble.unwrap_or(0) << 4 | (target >> 8) as Byte;
+            },
+            Patch::Low => {
+                self.rom[offset] = target as Byte;
+            }
+        }
+        Ok(())
+    }
+
+    /// Take the target of a reference, along with its value, or None if it names a label that
+    /// is not defined yet.
+    fn target(&mut self) -> Result<(Token, Option<i64>), AsmError> {
+        let token = self.tokens.front().cloned().unwrap_or_else(|| self.last.clone());
+        if Compiler::is_name(&token.text) && self.known_value(&token.text).is_none() {
+            self.next()?;
+            return Ok((token, None));
+        }
+        let target = self.value(Width::Word)?;
+        Ok((token, Some(target)))
+    }
+
+    /// Write a reference at `address`, now if its target is known or once it is defined.
+    fn reference(&mut self, token: Token, target: Option<i64>, address: usize, patch: Patch)
+            -> Result<(), AsmError> {
+        match target {
+            Some(target) => self.patch(&token, address, patch, target),
+            None => {
+                self.unresolved.push((token, address, patch));
+                Ok(())
+            }
+        }
+    }
+
+    /// An instruction whose low 12 bits are an address, such as `jump`.
+    fn address_instruction(&mut self, high: Word) -> Result<(), AsmError> {
+        let address = self.here;
+        let (token, target) = self.target()?;
+        self.instruction(high)?;
+        self.reference(token, target, address, Patch::Address)
+    }
+
+    /// A jump to be aimed later, for `if ... begin`, `else` and `while`.
+    fn placeholder_jump(&mut self) -> Result<usize, AsmError> {
+        let address = self.here;
+        self.instruction(0x1000)?;
+        Ok(address)
+    }
+
+    /// Aim a jump made by `placeholder_jump` at the current address.
+    fn land_jump(&mut self, token: &Token, address: usize) -> Result<(), AsmError> {
+        let here = self.here as i64;
+        self.patch(token, address, Patch::Address, here)
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.new_name()?;
+                // a jump from 0x200 to a `: main` straight after it would only skip itself
+                if name.text == "main" && self.main_jump && self.rom.len() == 2
+                        && self.here == PROGRAM_ADDRESS as usize + 2 && self.labels.is_empty() {
+                    self.rom.clear();
+                    self.here = PROGRAM_ADDRESS as usize;
+                    self.main_jump = false;
+                }
+                self.labels.insert(name.text, self.here as Address);
+            },
+            ":next" => {
+                let name = self.new_name()?;
+                self.labels.insert(name.text, self.here as Address + 1);
+            },
+            ":const" => {
+                let name = self.new_name()?;
+                let value = self.value(Width::Word)?;
+                self.constants.insert(name.text, value as f64);
+            },
+            ":calc" => {
+                let name = self.new_name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+            },
+            ":alias" => {
+                let name = self.new_name()?;
+                let register = if self.peek_is("{") {
+                    let open = self.next()?;
+                    match self.calc()?.floor() as i64 {
+                        register @ 0..=0xF => register as RegisterIndex,
+                        register => {
+                            let message = format!("v{:x} is not a register", register);
+                            return Err(self.error(&open, message));
+                        }
+                    }
+                } else {
+                    self.register()? as RegisterIndex
+                };
+                self.aliases.insert(name.text, register);
+            },
+            ":byte" => {
+                let value = self.value(Width::Byte)?;
+                self.emit(&[value as Byte])?;
+            },
+            ":org" => {
+                let target = self.value(Width::Word)? as usize;
+                if target < PROGRAM_ADDRESS as usize {
+                    let message = format!("{:#X} is before the start of the program", target);
+                    return Err(self.error(&token, message));
+                }
+                self.here = target;
+            },
+            ":unpack" => {
+                let nibble = if self.peek_is("long") {
+                    self.next()?;
+                    None
+                } else {
+                    Some(self.value(Width::Nibble)? as Byte)
+                };
+                let address = self.here;
+                let (token, target) = self.target()?;
+                self.emit(&[0x60, 0x00, 0x61, 0x00])?;
+                self.reference(token.clone(), target, address + 1, Patch::High(nibble))?;
+                self.reference(token, target, address + 3, Patch::Low)?;
+            },
+            ":breakpoint" => {
+                self.next()?;
+            },
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            },
+            ":macro" => self.define_macro()?,
+            ":call" => self.address_instruction(0x2000)?,
+            ";" | "return" => self.instruction(0x00EE)?,
+            "clear" => self.instruction(0x00E0)?,
+            "bcd" => {
+                let x = self.register()?;
+                self.instruction(0xF033 | x << 8)?;
+            },
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek_is("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    let low = if token.text == "save" { 0x2 } else { 0x3 };
+                    self.instruction(0x5000 | x << 8 | y << 4 | low)?;
+                } else {
+                    let low = if token.text == "save" { 0x55 } else { 0x65 };
+                    self.instruction(0xF000 | x << 8 | low)?;
+                }
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let rows = self.value(Width::Nibble)? as Word;
+                self.instruction(0xD000 | x << 8 | y << 4 | rows)?;
+            },
+            "jump" => self.address_instruction(0x1000)?,
+            "jump0" => self.address_instruction(0xB000)?,
+            "native" => self.address_instruction(0x0000)?,
+            "scroll-down" => {
+                let rows = self.value(Width::Nibble)? as Word;
+                self.instruction(0x00C0 | rows)?;
+            },
+            "scroll-up" => {
+                let rows = self.value(Width::Nibble)? as Word;
+                self.instruction(0x00D0 | rows)?;
+            },
+            "scroll-right" => self.instruction(0x00FB)?,
+            "scroll-left" => self.instruction(0x00FC)?,
+            "exit" => self.instruction(0x00FD)?,
+            "lores" => self.instruction(0x00FE)?,
+            "hires" => self.instruction(0x00FF)?,
+            "saveflags" => {
+                let x = self.register()?;
+                self.instruction(0xF075 | x << 8)?;
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.instruction(0xF085 | x << 8)?;
+            },
+            "plane" => {
+                let planes = self.value(Width::Nibble)? as Word;
+                self.instruction(0xF001 | planes << 8)?;
+            },
+            "audio" => self.instruction(0xF002)?,
+            "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.instruction(0xF03A | x << 8)?;
+            },
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let low = if token.text == "delay" { 0x15 } else { 0x18 };
+                self.instruction(0xF000 | x << 8 | low)?;
+            },
+            "i" => self.index_statement()?,
+            "if" => {
+                let comparison = self.comparison()?;
+                let begin = self.tokens.front().is_some_and(|next| next.text == "begin");
+                self.condition(&comparison, begin)?;
+                if begin {
+                    self.next()?;
+                    let jump = self.placeholder_jump()?;
+                    self.branches.push((token, jump));
+                } else {
+                    self.expect("then")?;
+                }
+            },
+            "else" => {
+                let (_, jump) = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => {
+                        return Err(self.error(&token, "`else` without `if ... begin`".to_string()));
+                    }
+                };
+                let skip = self.placeholder_jump()?;
+                self.land_jump(&token, jump)?;
+                self.branches.push((token, skip));
+            },
+            "end" => {
+                let (_, jump) = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => {
+                        return Err(self.error(&token, "`end` without `if ... begin`".to_string()));
+                    }
+                };
+                self.land_jump(&token, jump)?;
+            },
+            "loop" => {
+                let here = self.here;
+                self.loops.push((token, here, Vec::new()));
+            },
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error(&token, "`while` outside of a loop".to_string()));
+                }
+                let comparison = self.comparison()?;
+                self.condition(&comparison, true)?;
+                let jump = self.placeholder_jump()?;
+                self.loops.last_mut().unwrap().2.push(jump);
+            },
+            "again" => {
+                let (_, start, exits) = match self.loops.pop() {
+                    Some(open) => open,
+                    None => return Err(self.error(&token, "`again` without `loop`".to_string()))
+                };
+                self.instruction(0x1000 | start as Word)?;
+                for jump in exits {
+                    self.land_jump(&token, jump)?;
+                }
+            },
+            _ => {
+                if let Some(x) = self.register_of(&token) {
+                    return self.register_statement(x as Word);
+                }
+                if let Some(definition) = self.macros.get(&token.text).cloned() {
+                    return self.expand(&token, definition);
+                }
+                let is_number = self.known_value(&token.text).is_some()
+                    && !self.labels.contains_key(&token.text);
+                if is_number {
+                    self.tokens.push_front(token);
+                    let value = self.value(Width::Byte)?;
+                    return self.emit(&[value as Byte]);
+                }
+                if !Compiler::is_name(&token.text) {
+                    return Err(self.error(&token, format!("unexpected `{}`", token.text)));
+                }
+
+                // any other name is a call to a subroutine
+                self.tokens.push_front(token);
+                self.address_instruction(0x2000)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `i := ...` and `i += ...`.
+    fn index_statement(&mut self) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        match operator.text.as_str() {
+            ":=" if self.peek_is("hex") || self.peek_is("bighex") => {
+                let low = if self.next()?.text == "hex" { 0x29 } else { 0x30 };
+                let x = self.register()?;
+                self.instruction(0xF000 | x << 8 | low)
+            },
+            ":=" if self.peek_is("long") => {
+                self.next()?;
+                let (token, target) = self.target()?;
+                self.instruction(0xF000)?;
+                let address = self.here;
+                self.instruction(0x0000)?;
+                self.reference(token, target, address, Patch::Long)
+            },
+            ":=" => self.address_instruction(0xA000),
+            "+=" => {
+                let x = self.register()?;
+                self.instruction(0xF01E | x << 8)
+            },
+            _ => {
+                let message = format!("expected `:=` or `+=`, found `{}`", operator.text);
+                Err(self.error(&operator, message))
+            }
+        }
+    }
+
+    /// An assignment or arithmetic on register `x`.
+    fn register_statement(&mut self, x: Word) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        let y = self.peek_register().map(|y| y as Word);
+        let alu = match operator.text.as_str() {
+            ":=" => 0x0,
+            "|=" => 0x1,
+            "&=" => 0x2,
+            "^=" => 0x3,
+            "+=" => 0x4,
+            "-=" => 0x5,
+            ">>=" => 0x6,
+            "=-" => 0x7,
+            "<<=" => 0xE,
+            _ => return Err(self.error(&operator, format!("unexpected `{}`", operator.text)))
+        };
+
+        if let Some(y) = y {
+            self.next()?;
+            return self.instruction(0x8000 | x << 8 | y << 4 | alu);
+        }
+        match operator.text.as_str() {
+            ":=" if self.peek_is("random") => {
+                self.next()?;
+                let mask = self.value(Width::Byte)? as Word & 0xFF;
+                self.instruction(0xC000 | x << 8 | mask)
+            },
+            ":=" if self.peek_is("key") => {
+                self.next()?;
+                self.instruction(0xF00A | x << 8)
+            },
+            ":=" if self.peek_is("delay") => {
+                self.next()?;
+                self.instruction(0xF007 | x << 8)
+            },
+            ":=" => {
+                let value = self.value(Width::Byte)? as Word & 0xFF;
+                self.instruction(0x6000 | x << 8 | value)
+            },
+            "+=" => {
+                let value = self.value(Width::Byte)? as Word & 0xFF;
+                self.instruction(0x7000 | x << 8 | value)
+            },
+            "-=" => {
+                let value = self.value(Width::Byte)?.wrapping_neg() as Word & 0xFF;
+                self.instruction(0x7000 | x << 8 | value)
+            },
+            _ => {
+                let message = format!("`{}` needs a register on the right", operator.text);
+                Err(self.error(&operator, message))
+            }
+        }
+    }
+
+    /// Take the comparison of an `if` or `while`, without writing anything yet.
+    fn comparison(&mut self) -> Result<Comparison, AsmError> {
+        let x = self.register()?;
+        let operator = self.next()?;
+        match operator.text.as_str() {
+            "key" | "-key" => return Ok(Comparison { x, operator, y: None, value: 0 }),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => (),
+            _ => {
+                let message = format!("expected a comparison, found `{}`", operator.text);
+                return Err(self.error(&operator, message));
+            }
+        }
+
+        let y = self.peek_register().map(|y| y as Word);
+        if y.is_some() {
+            self.next()?;
+        }
+        let value = match y {
+            Some(_) => 0,
+            None => self.value(Width::Byte)? as Word & 0xFF
+        };
+        Ok(Comparison { x, operator, y, value })
+    }
+
+    /// The instructions for a comparison: a skip that passes over the next instruction when
+    /// the comparison is false, or when it is true if `negated`.
+    fn condition(&mut self, comparison: &Comparison, negated: bool) -> Result<(), AsmError> {
+        let Comparison { x, y, value, .. } = *comparison;
+        let operator = match (comparison.operator.text.as_str(), negated) {
+            ("==", true) => "!=",
+            ("!=", true) => "==",
+            ("key", true) => "-key",
+            ("-key", true) => "key",
+            ("<", true) => ">=",
+            (">", true) => "<=",
+            ("<=", true) => ">",
+            (">=", true) => "<",
+            (operator, _) => operator
+        };
+
+        match (operator, y) {
+            ("key", _) => self.instruction(0xE0A1 | x << 8),
+            ("-key", _) => self.instruction(0xE09E | x << 8),
+            ("==", Some(y)) => self.instruction(0x9000 | x << 8 | y << 4),
+            ("==", None) => self.instruction(0x4000 | x << 8 | value),
+            ("!=", Some(y)) => self.instruction(0x5000 | x << 8 | y << 4),
+            ("!=", None) => self.instruction(0x3000 | x << 8 | value),
+            _ => {
+                // put the right hand side in the scratch register and subtract, leaving VF
+                // holding whether the subtraction did not borrow
+                match y {
+                    Some(y) => self.instruction(0x8000 | COMPARE_TEMP << 8 | y << 4)?,
+                    None => self.instruction(0x6000 | COMPARE_TEMP << 8 | value)?
+                }
+                let (subtract, skip_if) = match operator {
+                    ">" => (0x5, 1),
+                    "<" => (0x7, 1),
+                    ">=" => (0x7, 0),
+                    _ => (0x5, 0)
+                };
+                self.instruction(0x8000 | COMPARE_TEMP << 8 | x << 4 | subtract)?;
+                self.instruction(0x3F00 | skip_if)
+            }
+        }
+    }
+
+    /// `:macro name parameters { body }`.
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.new_name()?;
+        let mut parameters = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            parameters.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 1 => break,
+                "}" => depth -= 1,
+                _ => ()
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { parameters, body });
+        Ok(())
+    }
+
+    /// Replace a macro call with the macro's body, with its arguments put in place.
+    fn expand(&mut self, token: &Token, definition: Macro) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(token, format!("too many expansions of `{}`", token.text)));
+        }
+
+        let mut arguments = Vec::new();
+        for _ in 0..definition.parameters.len() {
+            arguments.push(self.next()?.text);
+        }
+        for body in definition.body.iter().rev() {
+            let mut body = body.clone();
+            if let Some(index) = definition.parameters.iter().position(|name| *name == body.text) {
+                body.text = arguments[index].clone();
+            }
+            self.tokens.push_front(body);
+        }
+        Ok(())
+    }
+
+    /// A `{ ... }` expression, read after the opening brace. Like Octo, binary operators all
+    /// have the same precedence and group to the right, so `1 + 2 * 3` is `1 + (2 * 3)`.
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        let value = self.calc_expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expression(&mut self) -> Result<f64, AsmError> {
+        let left = self.calc_term()?;
+        if self.peek_is("}") || self.peek_is(")") {
+            return Ok(left);
+        }
+
+        let operator = self.next()?;
+        let right = self.calc_expression()?;
+        let (a, b) = (left as i64, right as i64);
+        let truth = |condition: bool| if condition { 1.0 } else { 0.0 };
+        Ok(match operator.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.wrapping_shl(b as u32) as f64,
+            ">>" => a.wrapping_shr(b as u32) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => truth(left < right),
+            "<=" => truth(left <= right),
+            "==" => truth(left == right),
+            "!=" => truth(left != right),
+            ">=" => truth(left >= right),
+            ">" => truth(left > right),
+            _ => return Err(self.error(&operator, format!("unknown operator `{}`", operator.text)))
+        })
+    }
+
+    fn calc_term(&mut self) -> Result<f64, AsmError> {
+        let token = self.next()?;
+        let unary = |operation: fn(f64) -> f64, compiler: &mut Compiler| {
+            compiler.calc_term().map(operation)
+        };
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            "-" => unary(|x| -x, self),
+            "~" => unary(|x| !(x as i64) as f64, self),
+            "!" => unary(|x| if x == 0.0 { 1.0 } else { 0.0 }, self),
+            "sin" => unary(f64::sin, self),
+            "cos" => unary(f64::cos, self),
+            "tan" => unary(f64::tan, self),
+            "exp" => unary(f64::exp, self),
+            "log" => unary(f64::ln, self),
+            "abs" => unary(f64::abs, self),
+            "sqrt" => unary(f64::sqrt, self),
+            "sign" => unary(|x| if x == 0.0 { 0.0 } else { x.signum() }, self),
+            "ceil" => unary(f64::ceil, self),
+            "floor" => unary(f64::floor, self),
+            "@" => {
+                let address = self.calc_term()? as usize;
+                let offset = address.wrapping_sub(PROGRAM_ADDRESS as usize);
+                Ok(self.rom.get(offset).cloned().unwrap_or(0) as f64)
+            },
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(::std::f64::consts::PI),
+            "E" => Ok(::std::f64::consts::E),
+            text => match self.known_value(text) {
+                Some(value) => Ok(value),
+                None => Err(self.error(&token, format!("`{}` is not defined", text)))
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use system::System;
+
+    /** Compile a program, panicking with the error if it fails. */
+    fn rom(source: &str) -> Vec<Byte> {
+        compile(source).unwrap_or_else(|error| panic!("{}", error)).rom
+    }
+
+    /** Run a program until it is stuck in a loop, and return its registers. */
+    fn run(source: &str) -> Vec<Byte> {
+        let mut system = System::new();
+        system.load_rom(&rom(source)[..]).unwrap();
+        system.run_cycles(1000).unwrap();
+        system.registers().to_vec()
+    }
+
+    /** Plain statements should compile to the instructions they stand for. */
+    #[test]
+    fn statements() {
+        assert_eq!(vec![
+            0x00, 0xE0, 0x6A, 0x05, 0x8A, 0xB4, 0x7A, 0xFF, 0xCA, 0x0F, 0xA2, 0x0E,
+            0xDA, 0xB5, 0x00, 0xEE, 0xF0, 0x00, 0x12, 0x34, 0x50, 0x32, 0xFA, 0x29
+        ], rom("
+            : main
+            clear
+            va := 5
+            va += vb
+            va -= 1       # adds 255
+            va := random 0xF
+            i := ball
+            sprite va vb 5
+            : ball ;
+            i := long 0x1234
+            save v0 - v3
+            i := hex va
+        "));
+    }
+
+    /** Labels used before they are defined should be patched once they are. */
+    #[test]
+    fn forward_references() {
+        assert_eq!(vec![0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0x00, 0xEE],
+                   rom(": main  draw  jump main  0 0  : draw  return"));
+        assert_eq!(vec![0x60, 0xA2, 0x61, 0x06, 0x00, 0x00, 0x12, 0x34],
+                   rom(": main  :unpack 0xA data  0 0  : data  0x12 0x34"));
+    }
+
+    /** Execution should start at `: main` even when data and subroutines come before it. */
+    #[test]
+    fn main_entry_point() {
+        assert_eq!(vec![0x12, 0x06, 0xFF, 0x00, 0x00, 0xEE, 0x22, 0x04],
+                   rom(": face  0xFF 0x00  : draw  return  : main  draw"));
+
+        let registers = run("
+            : double  v0 += v0  return
+            : main
+            v0 := 3
+            double
+            double
+            : halt  jump halt
+        ");
+        assert_eq!(12, registers[0]);
+    }
+
+    /** `if`, `loop` and `while` should behave as they read. */
+    #[test]
+    fn control_flow() {
+        let registers = run("
+            : main
+            v0 := 0
+            v1 := 0
+            loop
+                v0 += 1
+                if v0 == 3 then v1 += 10
+                while v0 != 5
+            again
+            if v1 == 10 begin
+                v2 := 1
+            else
+                v2 := 2
+            end
+            if v0 == { 2 + 3 } begin
+                v3 := 7
+            end
+            if v1 < { 4 * 2 } begin
+                v4 := 8
+            else
+                v4 := 9
+            end
+            : halt  jump halt
+        ");
+        assert_eq!(&[5, 10, 1, 7, 9], &registers[..5]);
+    }
+
+    /** Comparisons other than equality should subtract in VF and skip on its borrow flag. */
+    #[test]
+    fn comparisons() {
+        let cases = [(">", 0x5, 1), ("<", 0x7, 1), (">=", 0x7, 0), ("<=", 0x5, 0)];
+        for &(operator, subtract, skip) in cases.iter() {
+            assert_eq!(vec![0x6F, 0x05, 0x8F, 0x10 | subtract, 0x3F, skip, 0x62, 0x01],
+                       rom(&format!(": main  if v1 {} 5 then v2 := 1", operator)), "{}", operator);
+        }
+
+        // `begin` inverts the comparison to skip the jump past the block
+        assert_eq!(vec![0x8F, 0x20, 0x8F, 0x17, 0x3F, 0x00, 0x12, 0x0A, 0x63, 0x01],
+                   rom(": main  if v1 < v2 begin v3 := 1 end"));
+    }
+
+    /** Macros, constants, aliases, calculations and self-modifying labels should all expand. */
+    #[test]
+    fn directives() {
+        assert_eq!(vec![0x63, 0x07, 0x73, 0x0A, 0x66, 0x12, 0x02, 0x12, 0x07, 0xA2, 0x08], rom("
+            : main
+            :alias counter v3
+            :const START 7
+            :calc STEP { START + 3 }
+            :macro bump register amount { register += amount }
+            counter := START
+            bump counter STEP
+            v6 := { 2 * 3 * 3 }
+            :byte { HERE >> 8 }
+            :next target
+            jump 0x207
+            i := target
+        "));
+    }
+
+    /** Errors should point at the token at fault. */
+    #[test]
+    fn errors() {
+        let error = compile(": main  v0 := 1\n  jump nowhere").unwrap_err();
+        assert_eq!((2, 8, "`nowhere` is not defined".to_string()),
+                   (error.line, error.column, error.message));
+
+        let error = compile("loop\n  if v0 == 1 begin\n  again").unwrap_err();
+        assert_eq!((2, 3), (error.line, error.column));
+
+        let error = compile("v0 := 300").unwrap_err();
+        assert_eq!("<source>:1:7: 300 does not fit in a byte", error.to_string());
+
+        let error = compile(": start\n  jump start").unwrap_err();
+        assert_eq!("<source>:2:8: the program has no `: main` to start at", error.to_string());
+        assert_eq!("`x` is already defined", compile(": x : x").unwrap_err().message);
+        assert_eq!("expected a register, found `i`", compile("sprite i v0 1").unwrap_err().message);
+    }
+}
@@ -0,0 +1,153 @@
+use system::Byte;
+
+/// Number of bytes in an XO-CHIP audio pattern.
+pub const PATTERN_SIZE: usize = 16;
+
+/// Number of bits played per second from an audio pattern at the default pitch.
+pub const PATTERN_RATE: f64 = 4000.0;
+
+/// The pitch register value at which patterns play at `PATTERN_RATE`.
+pub const DEFAULT_PITCH: Byte = 64;
+
+/// Frequency of the square wave played when no audio pattern has been loaded.
+pub const BEEP_FREQUENCY: f64 = 440.0;
+
+/// Amplitude of the samples produced while sound is playing.
+pub const AMPLITUDE: i16 = 8192;
+
+const PATTERN_BITS: f64 = (PATTERN_SIZE * 8) as f64;
+
+/// Turns the sound timer, and the XO-CHIP audio pattern and pitch registers, into PCM samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Audio {
+    pub(super) pattern: Option<[Byte; PATTERN_SIZE]>,
+    pub(super) pitch: Byte,
+    phase: f64
+}
+
+impl Audio {
+    /// Creates a new Audio playing the classic square wave beep.
+    pub fn new() -> Self {
+        Audio {
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            phase: 0.0
+        }
+    }
+
+    /// The loaded audio pattern, or None while the classic beep is used.
+    pub fn pattern(&self) -> Option<&[Byte; PATTERN_SIZE]> {
+        self.pattern.as_ref()
+    }
+
+    /// Load a 16 byte, 1 bit per sample audio pattern, played from the most significant bit of
+    /// the first byte.
+    pub fn set_pattern(&mut self, pattern: &[Byte]) {
+        let mut buffer = [0; PATTERN_SIZE];
+        buffer.copy_from_slice(&pattern[..PATTERN_SIZE]);
+        self.pattern = Some(buffer);
+    }
+
+    /// The pitch register, which sets how fast the audio pattern plays.
+    pub fn pitch(&self) -> Byte {
+        self.pitch
+    }
+
+    /// Set the pitch register.
+    pub fn set_pitch(&mut self, pitch: Byte) {
+        self.pitch = pitch;
+    }
+
+    /// Number of pattern bits played per second at the current pitch.
+    pub fn playback_rate(&self) -> f64 {
+        PATTERN_RATE * 2f64.powf((self.pitch as f64 - DEFAULT_PITCH as f64) / 48.0)
+    }
+
+    /// Fill `buffer` with mono samples at `sample_rate`, continuing on from the last buffer.
+    /// While `playing` is false the buffer is filled with silence.
+    pub fn render(&mut self, playing: bool, sample_rate: u32, buffer: &mut [i16]) {
+        if !playing {
+            for sample in buffer.iter_mut() {
+                *sample = 0;
+            }
+            self.phase = 0.0;
+            return;
+        }
+
+        match self.pattern {
+            Some(pattern) => {
+                let step = self.playback_rate() / sample_rate as f64;
+                for sample in buffer.iter_mut() {
+                    let bit = self.phase as usize;
+                    let high = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                    *sample = if high { AMPLITUDE } else { -AMPLITUDE };
+                    self.phase = (self.phase + step) % PATTERN_BITS;
+                }
+            },
+
+            None => {
+                let step = BEEP_FREQUENCY / sample_rate as f64;
+                for sample in buffer.iter_mut() {
+                    *sample = if self.phase < 0.5 { AMPLITUDE } else { -AMPLITUDE };
+                    self.phase = (self.phase + step) % 1.0;
+                }
+            }
+        }
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Nothing should be heard while the sound timer is not running. */
+    #[test]
+    fn render_silence() {
+        let mut audio = Audio::new();
+        let mut buffer = [1; 64];
+
+        audio.render(false, 44100, &mut buffer);
+        assert!(buffer.iter().all(|&sample| sample == 0));
+    }
+
+    /** Without a pattern, a square wave at the beep frequency should be heard. */
+    #[test]
+    fn render_beep() {
+        let mut audio = Audio::new();
+        let mut buffer = [0; 100];
+
+        audio.render(true, 44000, &mut buffer);
+        assert!(buffer[..50].iter().all(|&sample| sample == AMPLITUDE));
+        assert!(buffer[50..].iter().all(|&sample| sample == -AMPLITUDE));
+    }
+
+    /** A pattern should be played bit by bit at 4000 bits per second at the default pitch, and
+      *     twice as fast 48 pitch steps higher. */
+    #[test]
+    fn render_pattern() {
+        let mut audio = Audio::new();
+        let mut pattern = [0x00; PATTERN_SIZE];
+        pattern[0] = 0xF0;
+        audio.set_pattern(&pattern);
+        let mut buffer = [0; 16];
+
+        audio.render(true, 4000, &mut buffer);
+        assert_eq!([AMPLITUDE; 4], buffer[..4]);
+        assert!(buffer[4..].iter().all(|&sample| sample == -AMPLITUDE));
+
+        audio.set_pitch(DEFAULT_PITCH + 48);
+        assert_eq!(8000.0, audio.playback_rate());
+        let mut buffer = [0; 64];
+        audio.render(true, 4000, &mut buffer);
+        // the rest of the pattern is silent, then it starts again
+        assert!(buffer[..56].iter().all(|&sample| sample == -AMPLITUDE));
+        assert_eq!([AMPLITUDE, AMPLITUDE, -AMPLITUDE], buffer[56..59]);
+    }
+}
@@ -0,0 +1,375 @@
+use system::Byte;
+
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+
+/// How sprite pixels that fall past the edge of the display are treated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeBehaviour {
+    /// Pixels past the edge reappear on the opposite side.
+    Wrap,
+    /// Pixels past the edge are discarded.
+    Clip
+}
+
+/// The framebuffer, stored row by row. It is 64x32 pixels, or 128x64 pixels in the
+/// SUPER-CHIP high resolution mode.
+///
+/// Each pixel holds one bit for each of the two XO-CHIP drawing planes, giving four colours.
+/// Plain CHIP-8 and SUPER-CHIP programs only ever draw to the first plane, so their pixels are
+/// either 0 or 1.
+#[derive(Clone, Debug)]
+pub struct Display {
+    pub(super) width: usize,
+    pub(super) height: usize,
+    pub(super) pixels: Vec<Byte>,
+    pub(super) planes: Byte,
+    pub(super) dirty: bool
+}
+
+impl Display {
+    /// Creates a new, blank display drawing to the first plane.
+    pub fn new() -> Self {
+        Display {
+            width: DISPLAY_WIDTH,
+            height: DISPLAY_HEIGHT,
+            pixels: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            planes: 0x1,
+            dirty: true
+        }
+    }
+
+    /// Width of the display in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the display in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether the display is in the 128x64 high resolution mode.
+    pub fn is_high_resolution(&self) -> bool {
+        self.width == HIRES_DISPLAY_WIDTH
+    }
+
+    /// Switch between the 64x32 and 128x64 resolutions, which also clears every plane.
+    pub fn set_high_resolution(&mut self, high: bool) {
+        let (width, height) = if high {
+            (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)
+        } else {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        };
+
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width * height];
+        self.dirty = true;
+    }
+
+    /// The planes that drawing, clearing and scrolling apply to, as a bit mask.
+    pub fn planes(&self) -> Byte {
+        self.planes
+    }
+
+    /// Select the planes that drawing, clearing and scrolling apply to: 0x1 for the first,
+    /// 0x2 for the second, 0x3 for both or 0x0 for neither.
+    pub fn set_planes(&mut self, planes: Byte) {
+        self.planes = planes & 0x3;
+    }
+
+    /// Number of planes currently selected.
+    pub fn plane_count(&self) -> usize {
+        self.planes.count_ones() as usize
+    }
+
+    /// Whether the pixel at (x, y) is lit on any plane.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.colour(x, y) != 0
+    }
+
+    /// The colour of the pixel at (x, y), from 0 to 3, with one bit per plane.
+    pub fn colour(&self, x: usize, y: usize) -> Byte {
+        self.pixels[y * self.width + x]
+    }
+
+    /// The colours of all pixels of the display, row by row.
+    pub fn pixels(&self) -> &[Byte] {
+        &self.pixels
+    }
+
+    /// Turn off every pixel on the selected planes.
+    pub fn clear(&mut self) {
+        let keep = !self.planes;
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= keep;
+        }
+        self.dirty = true;
+    }
+
+    /// XOR an 8 pixel wide sprite onto the selected planes with its top left corner at (x, y).
+    /// When both planes are selected, the data for the second plane follows that of the first.
+    /// The starting coordinate always wraps; `edges` decides what happens to the rest of the
+    /// sprite.
+    ///
+    /// Returns true if any lit pixel was turned off.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[Byte], edges: EdgeBehaviour) -> bool {
+        self.draw(x, y, sprite, 1, edges)
+    }
+
+    /// XOR a 16x16 SUPER-CHIP sprite, stored as 16 rows of two bytes, onto the selected planes.
+    pub fn draw_large_sprite(&mut self, x: usize, y: usize, sprite: &[Byte], edges: EdgeBehaviour) -> bool {
+        self.draw(x, y, sprite, 2, edges)
+    }
+
+    /// Move the selected planes down by `rows`, filling in blank rows at the top.
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.scroll(0, rows as isize);
+    }
+
+    /// Move the selected planes up by `rows`, filling in blank rows at the bottom.
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.scroll(0, -(rows as isize));
+    }
+
+    /// Move the selected planes right by `columns`, filling in blank columns on the left.
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.scroll(columns as isize, 0);
+    }
+
+    /// Move the selected planes left by `columns`, filling in blank columns on the right.
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.scroll(-(columns as isize), 0);
+    }
+
+    /// Move the selected planes by (dx, dy), leaving the other plane where it is.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let source = self.pixels.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = if sx >= 0 && sx < width && sy >= 0 && sy < height {
+                    source[(sy * width + sx) as usize]
+                } else {
+                    0
+                };
+
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = (*pixel & !self.planes) | (moved & self.planes);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// XOR a sprite `bytes_per_row` bytes wide onto each selected plane in turn.
+    fn draw(&mut self, x: usize, y: usize, sprite: &[Byte], bytes_per_row: usize, edges: EdgeBehaviour) -> bool {
+        let count = self.plane_count();
+        if count == 0 {
+            return false;
+        }
+
+        let length = sprite.len() / count;
+        let mut collision = false;
+        let mut offset = 0;
+        for plane in 0..2 {
+            let bit = 1 << plane;
+            if self.planes & bit != 0 {
+                collision |= self.draw_plane(x, y, &sprite[offset..offset + length], bytes_per_row, bit, edges);
+                offset += length;
+            }
+        }
+
+        self.dirty = true;
+        collision
+    }
+
+    /// XOR a sprite onto the plane given by `bit`.
+    fn draw_plane(&mut self, x: usize, y: usize, sprite: &[Byte], bytes_per_row: usize, bit: Byte,
+                  edges: EdgeBehaviour) -> bool {
+        let x = x % self.width;
+        let y = y % self.height;
+        let mut collision = false;
+
+        for (row, bytes) in sprite.chunks(bytes_per_row).enumerate() {
+            let mut py = y + row;
+            if py >= self.height {
+                match edges {
+                    EdgeBehaviour::Wrap => py %= self.height,
+                    EdgeBehaviour::Clip => break
+                }
+            }
+
+            for column in 0..bytes.len() * 8 {
+                if bytes[column / 8] & (0x80 >> (column % 8)) == 0 {
+                    continue;
+                }
+
+                let mut px = x + column;
+                if px >= self.width {
+                    match edges {
+                        EdgeBehaviour::Wrap => px %= self.width,
+                        EdgeBehaviour::Clip => break
+                    }
+                }
+
+                let pixel = &mut self.pixels[py * self.width + px];
+                collision |= *pixel & bit != 0;
+                *pixel ^= bit;
+            }
+        }
+
+        collision
+    }
+
+    /// Whether the display has changed since the dirty flag was last taken.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Return the dirty flag and reset it, for frontends that only redraw on change.
+    pub fn take_dirty(&mut self) -> bool {
+        let dirty = self.dirty;
+        self.dirty = false;
+        dirty
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** A sprite crossing the right and bottom edges should wrap when asked to. */
+    #[test]
+    fn draw_sprite_wrap() {
+        let mut display = Display::new();
+
+        display.draw_sprite(62, 31, &[0xF0, 0xF0], EdgeBehaviour::Wrap);
+        assert!(display.pixel(63, 31));
+        assert!(display.pixel(0, 31));
+        assert!(display.pixel(1, 0));
+        assert!(!display.pixel(2, 0));
+    }
+
+    /** A sprite crossing the right and bottom edges should be cut off when clipping. */
+    #[test]
+    fn draw_sprite_clip() {
+        let mut display = Display::new();
+
+        display.draw_sprite(62, 31, &[0xF0, 0xF0], EdgeBehaviour::Clip);
+        assert!(display.pixel(62, 31));
+        assert!(display.pixel(63, 31));
+        assert!(!display.pixel(0, 31));
+        assert!(!display.pixel(0, 0));
+    }
+
+    /** A 16x16 sprite should be drawn two bytes per row. */
+    #[test]
+    fn draw_large_sprite() {
+        let mut display = Display::new();
+        display.set_high_resolution(true);
+        let mut sprite = [0; 32];
+        sprite[0] = 0x80;
+        sprite[1] = 0x01;
+        sprite[31] = 0x01;
+
+        assert!(!display.draw_large_sprite(100, 40, &sprite, EdgeBehaviour::Clip));
+        assert!(display.pixel(100, 40));
+        assert!(display.pixel(115, 40));
+        assert!(display.pixel(115, 55));
+        assert_eq!(3, display.pixels().iter().filter(|&&pixel| pixel != 0).count());
+    }
+
+    /** Switching resolution should resize and clear the display. */
+    #[test]
+    fn set_high_resolution() {
+        let mut display = Display::new();
+        display.draw_sprite(0, 0, &[0x80], EdgeBehaviour::Clip);
+
+        display.set_high_resolution(true);
+        assert!(display.is_high_resolution());
+        assert_eq!((128, 64), (display.width(), display.height()));
+        assert!(!display.pixel(0, 0));
+
+        display.set_high_resolution(false);
+        assert_eq!((64, 32), (display.width(), display.height()));
+    }
+
+    /** Scrolling should move pixels and fill in blanks behind them. */
+    #[test]
+    fn scroll() {
+        let mut display = Display::new();
+        display.draw_sprite(0, 0, &[0x81], EdgeBehaviour::Clip);
+
+        display.scroll_down(3);
+        assert!(display.pixel(0, 3));
+        assert!(display.pixel(7, 3));
+        assert!(!display.pixel(0, 0));
+
+        display.scroll_right(4);
+        assert!(display.pixel(4, 3));
+        assert!(display.pixel(11, 3));
+        assert!(!display.pixel(0, 3));
+
+        display.scroll_left(10);
+        assert!(display.pixel(1, 3));
+        assert_eq!(1, display.pixels().iter().filter(|&&pixel| pixel != 0).count());
+
+        display.scroll_down(40);
+        assert!(display.pixels().iter().all(|&pixel| pixel == 0));
+    }
+
+    /** With both planes selected, a sprite should carry data for the first plane then the
+      *     second, and clearing and scrolling should leave unselected planes alone. */
+    #[test]
+    fn planes() {
+        let mut display = Display::new();
+        display.set_planes(0x3);
+        display.draw_sprite(0, 0, &[0xC0, 0x40], EdgeBehaviour::Clip);
+        assert_eq!(0x3, display.colour(1, 0));
+        assert_eq!(0x1, display.colour(0, 0));
+        assert_eq!(0x0, display.colour(2, 0));
+
+        display.set_planes(0x2);
+        assert!(display.draw_sprite(0, 0, &[0x40], EdgeBehaviour::Clip));
+        assert_eq!(0x1, display.colour(1, 0));
+
+        display.draw_sprite(0, 0, &[0x80], EdgeBehaviour::Clip);
+        assert_eq!(0x3, display.colour(0, 0));
+        display.scroll_right(1);
+        assert_eq!(0x1, display.colour(0, 0));
+        assert_eq!(0x3, display.colour(1, 0));
+
+        display.clear();
+        assert_eq!(0x1, display.colour(0, 0));
+        assert_eq!(0x1, display.colour(1, 0));
+
+        display.set_planes(0x0);
+        assert!(!display.draw_sprite(0, 0, &[0xFF], EdgeBehaviour::Clip));
+        assert_eq!(0x0, display.colour(2, 0));
+    }
+
+    /** Taking the dirty flag should reset it until the display next changes. */
+    #[test]
+    fn dirty_flag() {
+        let mut display = Display::new();
+
+        assert!(display.take_dirty());
+        assert!(!display.is_dirty());
+
+        display.draw_sprite(0, 0, &[0x80], EdgeBehaviour::Clip);
+        assert!(display.take_dirty());
+        assert!(!display.take_dirty());
+    }
+}
@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use system::{Address, Byte, RegisterIndex, Word};
+
+/// The ways fetching or executing an instruction can fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The word does not decode to any instruction.
+    UnknownOpcode,
+    /// A subroutine call was made with every stack slot in use.
+    StackOverflow,
+    /// A subroutine return was made with nothing on the stack.
+    StackUnderflow,
+    /// Memory was accessed at the given address, which lies past the end of memory.
+    MemoryOutOfBounds(usize),
+    /// A key was looked up with a value that is not a key on the keypad.
+    InvalidKey(Byte),
+    /// A 0NNN call was made to the machine code routine at the given address.
+    MachineCall(Address),
+    /// FX75 or FX85 named a register past the last user flag.
+    FlagOutOfRange(RegisterIndex),
+    /// The user flags could not be loaded from or saved to flag storage.
+    FlagStorage(io::ErrorKind)
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::UnknownOpcode               => write!(f, "unrecognized instruction"),
+            ErrorKind::StackOverflow               => write!(f, "stack overflow"),
+            ErrorKind::StackUnderflow              => write!(f, "stack underflow"),
+            ErrorKind::MemoryOutOfBounds(address)  =>
+                write!(f, "memory access out of bounds at {:#06X}", address),
+            ErrorKind::InvalidKey(key)             => write!(f, "invalid key {:#04X}", key),
+            ErrorKind::MachineCall(address)        =>
+                write!(f, "unsupported machine code call to {:#05X}", address),
+            ErrorKind::FlagOutOfRange(register)    =>
+                write!(f, "no user flag for register V{:X}", register),
+            ErrorKind::FlagStorage(kind)           =>
+                write!(f, "could not access user flag storage ({:?})", kind)
+        }
+    }
+}
+
+/// An error raised by the interpreter, along with where it happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chip8Error {
+    /// Address of the instruction that failed.
+    pub pc: Address,
+    /// The raw instruction word, or None if it could not be fetched.
+    pub opcode: Option<Word>,
+    /// What went wrong.
+    pub kind: ErrorKind
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.opcode {
+            Some(word)  => write!(f, "{} at {:#05X} (opcode {:04X})", self.kind, self.pc, word),
+            None        => write!(f, "{} while fetching from {:#05X}", self.kind, self.pc)
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+/// An error raised while loading a ROM into memory.
+#[derive(Debug)]
+pub enum RomError {
+    /// The ROM could not be read.
+    Io(io::Error),
+    /// The ROM does not fit between the load address and the end of memory.
+    TooLarge { size: usize, capacity: usize }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomError::Io(ref error) => write!(f, "could not read ROM: {}", error),
+            RomError::TooLarge { size, capacity } =>
+                write!(f, "ROM is {} bytes but only {} bytes are available", size, capacity)
+        }
+    }
+}
+
+impl Error for RomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RomError::Io(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> Self {
+        RomError::Io(error)
+    }
+}
+
+/// An error raised while saving or loading a save state.
+#[derive(Debug)]
+pub enum StateError {
+    /// The state could not be read or written.
+    Io(io::Error),
+    /// The data is not a save state.
+    NotAState,
+    /// The save state was written in a format version this interpreter cannot read.
+    UnsupportedVersion(u16),
+    /// The save state is damaged or inconsistent; the string names the part at fault.
+    Corrupt(&'static str)
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::Io(ref error)                  =>
+                write!(f, "could not access save state: {}", error),
+            StateError::NotAState                      => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version)    =>
+                write!(f, "unsupported save state version {}", version),
+            StateError::Corrupt(part)                  =>
+                write!(f, "save state is corrupt ({})", part)
+        }
+    }
+}
+
+impl Error for StateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            StateError::Io(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for StateError {
+    fn from(error: io::Error) -> Self {
+        StateError::Io(error)
+    }
+}
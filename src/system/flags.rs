@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::PathBuf;
+
+use system::Byte;
+
+/// Number of RPL user flag registers on the HP-48, as used by SUPER-CHIP.
+pub const NUM_FLAGS: usize = 8;
+
+/// Number of user flag registers XO-CHIP extends FX75 and FX85 to.
+pub const NUM_XO_FLAGS: usize = 16;
+
+/// Somewhere to keep the FX75/FX85 user flags of each ROM between runs.
+pub trait FlagStorage: fmt::Debug {
+    /// The flags last saved for the ROM with hash `rom`, or None if it has never saved any.
+    fn load(&mut self, rom: u64) -> io::Result<Option<Vec<Byte>>>;
+
+    /// Save the flags of the ROM with hash `rom`.
+    fn save(&mut self, rom: u64, flags: &[Byte]) -> io::Result<()>;
+}
+
+/// Keeps each ROM's flags in a file in a directory, named after the ROM's hash.
+#[derive(Clone, Debug)]
+pub struct FileFlagStorage {
+    directory: PathBuf
+}
+
+impl FileFlagStorage {
+    /// Creates a new FileFlagStorage, keeping flag files in `directory`. The directory is
+    /// created the first time flags are saved.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        FileFlagStorage { directory: directory.into() }
+    }
+
+    /// Creates a new FileFlagStorage in this user's data directory, which is
+    /// `$XDG_DATA_HOME/chip8-interpreter/flags`, falling back to `~/.local/share` and then to
+    /// `%APPDATA%`, or returns None if the environment names none of them.
+    pub fn for_user() -> Option<Self> {
+        user_data_directory(|name| env::var_os(name)).map(FileFlagStorage::new)
+    }
+
+    /// The file the flags of the ROM with hash `rom` are kept in.
+    pub fn path(&self, rom: u64) -> PathBuf {
+        self.directory.join(format!("{:016x}.flags", rom))
+    }
+}
+
+impl FlagStorage for FileFlagStorage {
+    fn load(&mut self, rom: u64) -> io::Result<Option<Vec<Byte>>> {
+        let mut file = match File::open(self.path(rom)) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error)
+        };
+
+        let mut flags = Vec::new();
+        file.read_to_end(&mut flags)?;
+        Ok(Some(flags))
+    }
+
+    fn save(&mut self, rom: u64, flags: &[Byte]) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        File::create(self.path(rom))?.write_all(flags)
+    }
+}
+
+/// Where `FileFlagStorage::for_user` keeps flags, looking environment variables up with `var`.
+fn user_data_directory<F: Fn(&str) -> Option<OsString>>(var: F) -> Option<PathBuf> {
+    let set = |name| var(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let data = set("XDG_DATA_HOME")
+        .or_else(|| set("HOME").map(|home| home.join(".local").join("share")))
+        .or_else(|| set("APPDATA"))?;
+    Some(data.join("chip8-interpreter").join("flags"))
+}
+
+/// Keeps flags in memory only, for tests and for frontends that should not touch the disk.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFlagStorage {
+    flags: HashMap<u64, Vec<Byte>>
+}
+
+impl MemoryFlagStorage {
+    /// Creates a new, empty MemoryFlagStorage.
+    pub fn new() -> Self {
+        MemoryFlagStorage::default()
+    }
+
+    /// The flags saved for the ROM with hash `rom`, if any.
+    pub fn flags(&self, rom: u64) -> Option<&[Byte]> {
+        self.flags.get(&rom).map(|flags| &flags[..])
+    }
+}
+
+impl FlagStorage for MemoryFlagStorage {
+    fn load(&mut self, rom: u64) -> io::Result<Option<Vec<Byte>>> {
+        Ok(self.flags.get(&rom).cloned())
+    }
+
+    fn save(&mut self, rom: u64, flags: &[Byte]) -> io::Result<()> {
+        self.flags.insert(rom, flags.to_vec());
+        Ok(())
+    }
+}
+
+/// The 64 bit FNV-1a hash of a ROM, which identifies it to flag storage.
+pub fn rom_hash(rom: &[Byte]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in rom {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    /** The ROM hash should be the standard FNV-1a hash. */
+    #[test]
+    fn rom_hash_fnv1a() {
+        assert_eq!(0xcbf2_9ce4_8422_2325, rom_hash(&[]));
+        assert_eq!(0xaf63_dc4c_8601_ec8c, rom_hash(b"a"));
+        assert_eq!(0x8594_4171_f739_67e8, rom_hash(b"foobar"));
+    }
+
+    /** Flags saved to a file should be loaded back, and ROMs without a file should have none. */
+    #[test]
+    fn file_storage() {
+        let directory = env::temp_dir().join(format!("chip8-flags-test-{}", process::id()));
+        let mut storage = FileFlagStorage::new(directory.clone());
+
+        assert_eq!(None, storage.load(0x1234).unwrap());
+        storage.save(0x1234, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(Some(vec![1, 2, 3, 4, 5, 6, 7, 8]), storage.load(0x1234).unwrap());
+        assert!(storage.path(0x1234).ends_with("0000000000001234.flags"));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    /** The user's flag directory should follow XDG_DATA_HOME, then HOME, then APPDATA. */
+    #[test]
+    fn user_directory() {
+        let directory = |variables: &[(&str, &str)]| {
+            let variables: HashMap<&str, &str> = variables.iter().cloned().collect();
+            user_data_directory(|name| variables.get(name).map(OsString::from))
+        };
+        let flags = |base: &str| Some(PathBuf::from(base).join("chip8-interpreter").join("flags"));
+
+        assert_eq!(flags("/data"), directory(&[("XDG_DATA_HOME", "/data"), ("HOME", "/home/u")]));
+        assert_eq!(flags("/home/u/.local/share"),
+                   directory(&[("XDG_DATA_HOME", ""), ("HOME", "/home/u")]));
+        assert_eq!(flags("C:/Users/u/AppData"), directory(&[("APPDATA", "C:/Users/u/AppData")]));
+        assert_eq!(None, directory(&[]));
+    }
+}
@@ -0,0 +1,132 @@
+use system::{Address, Byte};
+
+/// Default location of the font in the interpreter area of memory.
+pub const FONT_ADDRESS: Address = 0x050;
+
+/// Number of bytes in a single hex digit sprite.
+pub const GLYPH_SIZE: usize = 5;
+
+/// Number of bytes in a single 8x10 SUPER-CHIP hex digit sprite.
+pub const LARGE_GLYPH_SIZE: usize = 10;
+
+/// A built-in set of 4x5 hex digit sprites.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Font {
+    /// The font most modern interpreters ship with.
+    Standard,
+    /// The font from the RCA COSMAC VIP interpreter.
+    CosmacVip,
+    /// The font from the DREAM 6800 interpreter.
+    Dream6800,
+    /// The font from the ETI-660 interpreter.
+    Eti660
+}
+
+impl Font {
+    /// The sprite data for digits 0 through F, one glyph after another.
+    pub fn glyphs(&self) -> &'static [Byte] {
+        match *self {
+            Font::Standard     => &STANDARD,
+            Font::CosmacVip    => &COSMAC_VIP,
+            Font::Dream6800    => &DREAM_6800,
+            Font::Eti660       => &ETI_660
+        }
+    }
+}
+
+static STANDARD: [Byte; 16 * GLYPH_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0
+    0x20, 0x60, 0x20, 0x20, 0x70,   // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0,   // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0,   // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10,   // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0,   // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0,   // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40,   // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0,   // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0,   // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90,   // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0,   // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0,   // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0,   // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0,   // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80    // F
+];
+
+static COSMAC_VIP: [Byte; 16 * GLYPH_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0
+    0x60, 0x20, 0x20, 0x20, 0x70,   // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0,   // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0,   // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20,   // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0,   // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0,   // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10,   // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0,   // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0,   // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90,   // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0,   // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0,   // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0,   // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0,   // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80    // F
+];
+
+static DREAM_6800: [Byte; 16 * GLYPH_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0,   // 0
+    0x40, 0x40, 0x40, 0x40, 0x40,   // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0,   // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0,   // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20,   // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0,   // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0,   // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20,   // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0,   // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0,   // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0,   // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0,   // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0,   // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0,   // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0,   // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80    // F
+];
+
+static ETI_660: [Byte; 16 * GLYPH_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0,   // 0
+    0x20, 0x20, 0x20, 0x20, 0x20,   // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0,   // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0,   // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20,   // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0,   // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0,   // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20,   // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0,   // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0,   // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0,   // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0,   // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0,   // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0,   // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0,   // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80    // F
+];
+
+/// The 8x10 digit sprites from SUPER-CHIP 1.1, which only defined 0 to 9, completed with the
+/// A to F sprites from XO-CHIP.
+pub static LARGE_GLYPHS: [Byte; 16 * LARGE_GLYPH_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C,     // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C,     // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF,     // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C,     // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06,     // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C,     // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C,     // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,     // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C,     // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C,     // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3,     // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC,     // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C,     // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,     // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,     // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0      // F
+];
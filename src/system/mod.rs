@@ -1,13 +1,45 @@
+mod audio;
+mod display;
+mod error;
+mod flags;
+mod font;
 mod opcode;
+mod platform;
+mod quirks;
+mod rewind;
+mod rng;
+mod savestate;
+mod timer;
+pub use self::audio::{Audio, PATTERN_SIZE};
+pub use self::display::{Display, EdgeBehaviour, DISPLAY_WIDTH, DISPLAY_HEIGHT};
+pub use self::display::{HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT};
+pub use self::error::{Chip8Error, ErrorKind, RomError, StateError};
+pub use self::flags::{FlagStorage, FileFlagStorage, MemoryFlagStorage, rom_hash};
+pub use self::flags::{NUM_FLAGS, NUM_XO_FLAGS};
+pub use self::font::{Font, FONT_ADDRESS};
+use self::font::LARGE_GLYPHS;
+pub use self::opcode::OpCode;
+pub use self::platform::Platform;
+pub use self::quirks::{Quirks, ShiftSource, IndexIncrement};
+pub use self::rewind::{Rewind, KEYFRAME_INTERVAL};
+pub use self::rng::{RandomSource, XorShiftRng, SequenceRng, VipRng};
+pub use self::savestate::{STATE_MAGIC, STATE_VERSION};
+pub use self::timer::{TimerClock, TIMER_FREQUENCY};
+
+use std::cell::RefCell;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
+use std::rc::Rc;
 
 pub type Word = u16;
 pub type Byte = u8;
 pub type Address = u16;
 pub type RegisterIndex = usize;
 pub type Key = u32;
-pub type Waiting = bool;
+
+/// Default address programs are loaded at and started from.
+pub const PROGRAM_ADDRESS: Address = 0x200;
 
 const NUM_BYTES: usize = 4096;
 const NUM_REGISTERS: usize = 16;
@@ -19,6 +51,58 @@ pub enum KeyEventType {
     KeyRelease
 }
 
+/// What the CPU is doing between instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuState {
+    /// Fetching and executing instructions.
+    Running,
+    /// Blocked on FX0A until a key is entered, which will be stored in `register`.
+    /// `pressed` holds a key that has gone down but not yet been released.
+    WaitingForKey { register: RegisterIndex, pressed: Option<Byte> },
+    /// Blocked after DXYN until the start of the next 60 Hz frame.
+    WaitingForFrame,
+    /// Stopped for good by the SUPER-CHIP 00FD instruction.
+    Halted
+}
+
+/// When FX0A considers a key to have been entered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyWaitMode {
+    /// As soon as the key is pressed.
+    Press,
+    /// Once the key is pressed and then released, as on the COSMAC VIP.
+    Release
+}
+
+/// How 0NNN calls to machine code routines on the original hardware are handled.
+#[derive(Clone, Copy, Debug)]
+pub enum MachineCallPolicy {
+    /// Skip the call, as most interpreters do.
+    Ignore,
+    /// Fail with `ErrorKind::MachineCall`.
+    Error,
+    /// Hand the call to a native routine emulating the machine code at that address.
+    Hook(fn(&mut System, Address) -> Result<(), ErrorKind>)
+}
+
+/// The outcome of a single fetch-decode-execute cycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepResult {
+    /// Address the instruction was fetched from.
+    pub address: Address,
+    /// The instruction that was executed.
+    pub opcode: OpCode,
+    /// Set if the instruction could not be fetched or executed.
+    pub error: Option<Chip8Error>
+}
+
+impl StepResult {
+    /// Whether the instruction executed without error.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Debug)]
 pub struct System {
@@ -31,63 +115,123 @@ pub struct System {
     delay_timer: Byte,
     sound_timer: Byte,
     keys: Vec<bool>,
-    last_key_pressed: (Byte, Waiting),
-    stopped: bool
+    state: CpuState,
+    key_wait_mode: KeyWaitMode,
+    machine_call_policy: MachineCallPolicy,
+    platform: Platform,
+    display: Display,
+    audio: Audio,
+    quirks: Quirks,
+    font: Font,
+    font_address: Address,
+    load_address: Address,
+    timer_clock: Option<TimerClock>,
+    flags: [Byte; NUM_XO_FLAGS],
+    rom_hash: u64,
+    flag_storage: Option<Rc<RefCell<dyn FlagStorage>>>,
+    rng: Box<dyn RandomSource>
 }
 
 #[allow(dead_code)]
 impl System{
     /// Creates a new System.
     pub fn new() -> Self {
-        System {
+        let mut system = System {
             memory: vec![0; NUM_BYTES],
             registers: [0; NUM_REGISTERS],
-            pc: 0x200,
+            pc: PROGRAM_ADDRESS,
             index: 0,
             stack: vec![0; STACK_SIZE],
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
             keys: vec![false; NUM_KEYS],
-            last_key_pressed: (0xFF, false),
-            stopped: false
-        }
+            state: CpuState::Running,
+            key_wait_mode: KeyWaitMode::Press,
+            machine_call_policy: MachineCallPolicy::Ignore,
+            platform: Platform::Chip8,
+            display: Display::new(),
+            audio: Audio::new(),
+            quirks: Platform::Chip8.quirks(),
+            font: Font::Standard,
+            font_address: FONT_ADDRESS,
+            load_address: PROGRAM_ADDRESS,
+            timer_clock: None,
+            flags: [0; NUM_XO_FLAGS],
+            rom_hash: rom_hash(&[]),
+            flag_storage: None,
+            rng: Box::new(XorShiftRng::from_entropy())
+        };
+
+        system.load_font();
+        system
     }
 
     /// Creates a new System, given a path to a Chip8 rom to be loaded.
-    pub fn from_rom(path: &str) -> Self {
-        let mut system = System {
-            memory: vec![0; NUM_BYTES],
-            registers: [0; NUM_REGISTERS],
-            pc: 0x200,
-            index: 0,
-            stack: vec![0; STACK_SIZE],
-            sp: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            keys: vec![false; NUM_KEYS],
-            last_key_pressed: (0xFF, false),
-            stopped: false
-        };
+    pub fn from_rom<P: AsRef<Path>>(path: P) -> Result<Self, RomError> {
+        let mut system = System::new();
+        system.load_rom_file(path)?;
+        Ok(system)
+    }
 
-        let mut handle = File::open(path).expect("File not found!");
+    /// Reset the system and load a ROM from any reader (a `&[u8]` works too) at the load
+    /// address, ready to run from its first byte.
+    ///
+    /// Returns the size of the ROM. If it does not fit in memory the system is left untouched.
+    /// The user flags are cleared, to be loaded from flag storage for the new ROM.
+    pub fn load_rom<R: Read>(&mut self, mut reader: R) -> Result<usize, RomError> {
         let mut buffer: Vec<Byte> = Vec::new();
-        handle.read_to_end(&mut buffer).unwrap();
-        for i in 0..buffer.len() {
-            system.memory[0x200 + i] = buffer[i];
-        };
+        reader.read_to_end(&mut buffer)?;
 
-        system
+        let start = self.load_address as usize;
+        let capacity = self.memory.len().saturating_sub(start);
+        if buffer.len() > capacity {
+            return Err(RomError::TooLarge { size: buffer.len(), capacity });
+        }
+
+        self.reset();
+        self.memory[start..start + buffer.len()].copy_from_slice(&buffer);
+        self.flags = [0; NUM_XO_FLAGS];
+        self.rom_hash = rom_hash(&buffer);
+        Ok(buffer.len())
     }
 
-    /// Halt execution of the system.
-    pub fn stop(&mut self) {
-        self.stopped = true;
+    /// Reset the system and load the ROM at `path`.
+    pub fn load_rom_file<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, RomError> {
+        self.load_rom(File::open(path)?)
     }
 
-    /// Start execution of the system.
-    pub fn start(&mut self) {
-        self.stopped = false;
+    /// Choose the address ROMs are loaded at and started from, e.g. 0x600 for ETI-660
+    /// programs. Takes effect from the next ROM loaded.
+    pub fn set_load_address(&mut self, address: Address) {
+        self.load_address = address;
+    }
+
+    /// Return the system to its power on state, keeping its configuration. Memory is
+    /// cleared apart from the font.
+    pub fn reset(&mut self) {
+        for byte in self.memory.iter_mut() {
+            *byte = 0;
+        }
+        self.registers = [0; NUM_REGISTERS];
+        self.pc = self.load_address;
+        self.index = 0;
+        for slot in self.stack.iter_mut() {
+            *slot = 0;
+        }
+        self.sp = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        for key in self.keys.iter_mut() {
+            *key = false;
+        }
+        self.state = CpuState::Running;
+        self.display.set_high_resolution(false);
+        self.display.set_planes(0x1);
+        self.audio = Audio::new();
+        self.timer_clock = self.timer_clock.map(|clock| TimerClock::new(clock.cycles_per_second()));
+
+        self.load_font();
     }
 
     /// Handles key input.
@@ -97,96 +241,449 @@ impl System{
             KeyEventType::KeyRelease    => false
         };
 
-        let value = match key {
-            49  => {    // 1 -> 1
-                self.set_key(0x1, state);
-                0x1
-            },
-            50  => {    // 2 -> 2
-                self.set_key(0x2, state);
-                0x2
-            },
-            51  => {    // 3 -> 3
-                self.set_key(0x3, state);
-                0x3
-            },
-            52  => {    // 4 -> C
-                self.set_key(0xC, state);
-                0xC
-            },
-            113 => {    // Q -> 4
-                self.set_key(0x4, state);
-                0x4
-            },
-            119 => {    // W -> 5
-                self.set_key(0x5, state);
-                0x5
-            },
-            101 => {    // E -> 6
-                self.set_key(0x6, state);
-                0x6
-            },
-            114 => {    // R -> D
-                self.set_key(0xD, state);
-                0xD
-            },
-            97  => {    // A -> 7
-                self.set_key(0x7, state);
-                0x7
-            },
-            115 => {    // S -> 8
-                self.set_key(0x8, state);
-                0x8
-            },
-            100 => {    // D -> 9
-                self.set_key(0x9, state);
-                0x9
-            },
-            102 => {    // F -> E
-                self.set_key(0xE, state);
-                0xE
-            },
-            122 => {    // Z -> A
-                self.set_key(0xA, state);
-                0xA
-            },
-            120 => {    // X -> 0
-                self.set_key(0x0, state);
-                0x0
-            },
-            99  => {    // C -> B
-                self.set_key(0xB, state);
-                0xB
-            },
-            118 => {    // V -> F
-                self.set_key(0xF, state);
-                0xF
-            },
-            _ => 0xFF
-        };
-
-        if self.stopped {
-            self.last_key_pressed = (value, true);
-        } else {
-            self.last_key_pressed = (value, false);
+        match key {
+            49  => self.set_key(0x1, state),  // 1 -> 1
+            50  => self.set_key(0x2, state),  // 2 -> 2
+            51  => self.set_key(0x3, state),  // 3 -> 3
+            52  => self.set_key(0xC, state),  // 4 -> C
+            113 => self.set_key(0x4, state),  // Q -> 4
+            119 => self.set_key(0x5, state),  // W -> 5
+            101 => self.set_key(0x6, state),  // E -> 6
+            114 => self.set_key(0xD, state),  // R -> D
+            97  => self.set_key(0x7, state),  // A -> 7
+            115 => self.set_key(0x8, state),  // S -> 8
+            100 => self.set_key(0x9, state),  // D -> 9
+            102 => self.set_key(0xE, state),  // F -> E
+            122 => self.set_key(0xA, state),  // Z -> A
+            120 => self.set_key(0x0, state),  // X -> 0
+            99  => self.set_key(0xB, state),  // C -> B
+            118 => self.set_key(0xF, state),  // V -> F
+            _ => ()
         }
     }
 
     /// Index into system memory at address and return word located there.
-    pub fn read_word(&self, address: usize) -> Word {
+    pub fn read_word(&self, address: usize) -> Result<Word, ErrorKind> {
+        self.check_memory(address, 2)?;
+
         let left = self.memory[address] as Word;
         let right = self.memory[address + 1] as Word;
-        (left << 8) | right
+        Ok((left << 8) | right)
+    }
+
+    /// The program counter.
+    pub fn pc(&self) -> Address {
+        self.pc
+    }
+
+    /// The index register I.
+    pub fn index(&self) -> Word {
+        self.index
+    }
+
+    /// The general purpose registers V0 to VF.
+    pub fn registers(&self) -> &[Byte] {
+        &self.registers
+    }
+
+    /// The whole of system memory.
+    pub fn memory(&self) -> &[Byte] {
+        &self.memory
+    }
+
+    /// The return addresses of the subroutines currently being run, innermost last.
+    pub fn stack(&self) -> &[Word] {
+        &self.stack[..self.sp as usize]
     }
 
     /// Increment the program counter for this system.
     pub fn increment_pc(&mut self) {
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    /// Fetch the instruction at the program counter, advance the program counter past it,
+    /// then decode and execute it.
+    ///
+    /// While the CPU is waiting on FX0A or for the next frame, or has halted, this returns
+    /// immediately without executing anything, reporting the instruction it is stopped on. The cycle
+    /// still counts towards cycle driven timers.
+    pub fn step(&mut self) -> StepResult {
+        if self.state != CpuState::Running {
+            self.advance_timer_clock();
+            let address = self.pc.wrapping_sub(2);
+            let opcode = self.read_word(address as usize).map(OpCode::from).unwrap_or(OpCode::Unknown);
+            return StepResult { address, opcode, error: None };
+        }
+
+        let address = self.pc;
+        let word = match self.read_word(address as usize) {
+            Ok(word) => word,
+            Err(kind) => {
+                return StepResult {
+                    address,
+                    opcode: OpCode::Unknown,
+                    error: Some(Chip8Error { pc: address, opcode: None, kind })
+                };
+            }
+        };
+        self.increment_pc();
+
+        let opcode = OpCode::from(word);
+        let error = opcode.execute(self).err()
+            .map(|kind| Chip8Error { pc: address, opcode: Some(word), kind });
+        self.advance_timer_clock();
+
+        StepResult { address, opcode, error }
+    }
+
+    /// Account for one cycle on the timer clock, if the timers are driven by cycle count.
+    fn advance_timer_clock(&mut self) {
+        let ticks = match self.timer_clock {
+            Some(ref mut clock) => clock.advance(1),
+            None => 0
+        };
+        for _ in 0..ticks {
+            self.tick_timers();
+        }
+    }
+
+    /// Execute up to `cycles` instructions, stopping at the first one that fails.
+    ///
+    /// Returns the number of cycles run, or the result of the failing step.
+    pub fn run_cycles(&mut self, cycles: usize) -> Result<usize, StepResult> {
+        for _ in 0..cycles {
+            let result = self.step();
+            if !result.is_ok() {
+                return Err(result);
+            }
+        }
+
+        Ok(cycles)
+    }
+
+    /// What the CPU is currently doing.
+    pub fn cpu_state(&self) -> CpuState {
+        self.state
+    }
+
+    /// Whether the CPU is blocked on FX0A.
+    pub fn is_waiting_for_key(&self) -> bool {
+        matches!(self.state, CpuState::WaitingForKey { .. })
+    }
+
+    /// Choose whether FX0A completes when a key is pressed or when it is released.
+    pub fn set_key_wait_mode(&mut self, mode: KeyWaitMode) {
+        self.key_wait_mode = mode;
+    }
+
+    /// Execute a single 60 Hz frame worth of instructions, then count the timers down once.
+    ///
+    /// When the timers are driven by cycle count, they are left to tick as the instructions run.
+    pub fn run_frame(&mut self, instructions_per_frame: usize) -> Result<usize, StepResult> {
+        let executed = self.run_cycles(instructions_per_frame)?;
+        if self.timer_clock.is_none() {
+            self.tick_timers();
+        }
+
+        Ok(executed)
+    }
+
+    /// Count the delay and sound timers down by one 60 Hz tick, which also marks the start
+    /// of a new frame for a CPU waiting after DXYN.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+
+        if self.state == CpuState::WaitingForFrame {
+            self.state = CpuState::Running;
+        }
+    }
+
+    /// Drive the timers from the number of instructions executed, as if the CPU ran at
+    /// `cycles_per_second`, or pass None to leave ticking the timers to the frontend. A speed of
+    /// zero is taken to mean None.
+    pub fn set_cycle_timing(&mut self, cycles_per_second: Option<u32>) {
+        self.timer_clock = cycles_per_second.filter(|&speed| speed > 0).map(TimerClock::new);
+    }
+
+    /// The current value of the delay timer.
+    pub fn delay_timer(&self) -> Byte {
+        self.delay_timer
+    }
+
+    /// The current value of the sound timer.
+    pub fn sound_timer(&self) -> Byte {
+        self.sound_timer
     }
 
-    /// Set key at specified index.
+    /// Choose where CXNN gets its random numbers from.
+    pub fn set_random_source(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    /// Seed CXNN's random numbers, so that runs given the same input play out the same way.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = Box::new(XorShiftRng::new(seed));
+    }
+
+    /// Keep the user flags saved by FX75 in `storage`, so they outlive this system, or pass
+    /// None to keep them in the system only.
+    pub fn set_flag_storage(&mut self, storage: Option<Rc<RefCell<dyn FlagStorage>>>) {
+        self.flag_storage = storage;
+    }
+
+    /// Hash of the loaded ROM, under which its user flags are stored.
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// The user flag registers.
+    pub fn flags(&self) -> &[Byte] {
+        &self.flags[..self.platform.flag_count()]
+    }
+
+    /// Fail with `ErrorKind::FlagOutOfRange` unless V0 to VX fit in the user flags.
+    fn check_flags(&self, register: RegisterIndex) -> Result<(), ErrorKind> {
+        if register >= self.platform.flag_count() {
+            return Err(ErrorKind::FlagOutOfRange(register));
+        }
+
+        Ok(())
+    }
+
+    /// Write the user flags out to flag storage, if there is any.
+    fn save_flags(&mut self) -> Result<(), ErrorKind> {
+        if let Some(ref storage) = self.flag_storage {
+            storage.borrow_mut().save(self.rom_hash, self.flags())
+                .map_err(|error| ErrorKind::FlagStorage(error.kind()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Refresh the user flags from flag storage, if there is any and it holds flags for this ROM.
+    fn load_flags(&mut self) -> Result<(), ErrorKind> {
+        let stored = match self.flag_storage {
+            Some(ref storage) => storage.borrow_mut().load(self.rom_hash)
+                .map_err(|error| ErrorKind::FlagStorage(error.kind()))?,
+            None => None
+        };
+
+        if let Some(stored) = stored {
+            let length = stored.len().min(NUM_XO_FLAGS);
+            self.flags = [0; NUM_XO_FLAGS];
+            self.flags[..length].copy_from_slice(&stored[..length]);
+        }
+
+        Ok(())
+    }
+
+    /// Whether the buzzer is sounding, which it does while the sound timer is running.
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// The audio pattern and pitch registers of this system.
+    pub fn audio(&self) -> &Audio {
+        &self.audio
+    }
+
+    /// Fill `buffer` with the next mono samples at `sample_rate`: the audio pattern on
+    /// XO-CHIP, or a square wave beep, while the sound timer runs, and silence otherwise.
+    pub fn render_audio(&mut self, sample_rate: u32, buffer: &mut [i16]) {
+        let playing = self.is_sound_playing();
+        self.audio.render(playing, sample_rate, buffer);
+    }
+
+    /// The framebuffer of this system.
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Return whether the display has changed since the last call, and reset the flag.
+    pub fn take_display_dirty(&mut self) -> bool {
+        self.display.take_dirty()
+    }
+
+    /// The platform behaviours this system follows.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Choose the platform behaviours to follow, e.g. `Quirks::cosmac_vip()`.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Check that `length` bytes starting at `address` lie within system memory.
+    fn check_memory(&self, address: usize, length: usize) -> Result<(), ErrorKind> {
+        if address + length > self.memory.len() {
+            return Err(ErrorKind::MemoryOutOfBounds(address.max(self.memory.len())));
+        }
+
+        Ok(())
+    }
+
+    /// Choose the built-in font and the address it is loaded at. The large SUPER-CHIP font is
+    /// loaded straight after it.
+    pub fn set_font(&mut self, font: Font, address: Address) -> Result<(), ErrorKind> {
+        self.check_memory(address as usize, font.glyphs().len() + LARGE_GLYPHS.len())?;
+
+        self.font = font;
+        self.font_address = address;
+        self.load_font();
+        Ok(())
+    }
+
+    /// Copy the active font into memory at the font address.
+    fn load_font(&mut self) {
+        let glyphs = self.font.glyphs();
+        let start = self.font_address as usize;
+        self.memory[start..start + glyphs.len()].copy_from_slice(glyphs);
+
+        let start = self.large_font_address() as usize;
+        self.memory[start..start + LARGE_GLYPHS.len()].copy_from_slice(&LARGE_GLYPHS);
+    }
+
+    /// Address of the large SUPER-CHIP font, which sits straight after the regular font.
+    fn large_font_address(&self) -> Address {
+        self.font_address + self.font.glyphs().len() as Address
+    }
+
+    /// The CHIP-8 variant this system emulates.
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Choose the CHIP-8 variant to emulate. This also selects the quirks of that platform,
+    /// which can be overridden afterwards with `set_quirks`, and resizes memory to suit it.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.quirks = platform.quirks();
+        self.memory.resize(platform.memory_size(), 0);
+        if !platform.has_super_chip() {
+            self.display.set_high_resolution(false);
+        }
+        if !platform.has_xo_chip() {
+            self.display.set_planes(0x1);
+            self.audio = Audio::new();
+        }
+    }
+
+    /// Handle a 0NNN call to the machine code routine at `address` as the policy says.
+    fn machine_call(&mut self, address: Address) -> Result<(), ErrorKind> {
+        match self.machine_call_policy {
+            MachineCallPolicy::Ignore => Ok(()),
+            MachineCallPolicy::Error => Err(ErrorKind::MachineCall(address)),
+            MachineCallPolicy::Hook(hook) => hook(self, address)
+        }
+    }
+
+    /// Fail with `ErrorKind::UnknownOpcode` unless the SUPER-CHIP instructions are available.
+    fn require_super_chip(&self) -> Result<(), ErrorKind> {
+        if !self.platform.has_super_chip() {
+            return Err(ErrorKind::UnknownOpcode);
+        }
+
+        Ok(())
+    }
+
+    /// Fail with `ErrorKind::UnknownOpcode` unless the XO-CHIP instructions are available.
+    fn require_xo_chip(&self) -> Result<(), ErrorKind> {
+        if !self.platform.has_xo_chip() {
+            return Err(ErrorKind::UnknownOpcode);
+        }
+
+        Ok(())
+    }
+
+    /// Skip over the next instruction, which on XO-CHIP may be the 4 byte F000 NNNN.
+    fn skip_instruction(&mut self) {
+        let long = self.platform.has_xo_chip()
+            && self.read_word(self.pc as usize) == Ok(0xF000);
+
+        self.increment_pc();
+        if long {
+            self.increment_pc();
+        }
+    }
+
+    /// Whether the CPU has been stopped for good by 00FD.
+    pub fn is_halted(&self) -> bool {
+        self.state == CpuState::Halted
+    }
+
+    /// Choose how 0NNN machine code calls are handled.
+    pub fn set_machine_call_policy(&mut self, policy: MachineCallPolicy) {
+        self.machine_call_policy = policy;
+    }
+
+    /// The register 8XY6 and 8XYE shift, according to the quirks.
+    fn shift_source(&self, left: RegisterIndex, right: RegisterIndex) -> RegisterIndex {
+        match self.quirks.shift_source {
+            ShiftSource::Vy => right,
+            ShiftSource::Vx => left
+        }
+    }
+
+    /// Reset VF after 8XY1, 8XY2 or 8XY3, if the quirks call for it.
+    fn reset_flag_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0x0;
+        }
+    }
+
+    /// Move the index register on after FX55 or FX65 transferred V0 to VX, as the quirks say.
+    fn increment_index_after_transfer(&mut self, register: RegisterIndex) {
+        let increment = match self.quirks.index_increment {
+            IndexIncrement::Unchanged   => 0,
+            IndexIncrement::ByX         => register,
+            IndexIncrement::ByXPlusOne  => register + 1
+        };
+        self.index = self.index.wrapping_add(increment as Word);
+    }
+
+    /// Look up the key named by the value in a register.
+    fn key_index(&self, register: RegisterIndex) -> Result<usize, ErrorKind> {
+        let key = self.registers[register];
+        if key as usize >= self.keys.len() {
+            return Err(ErrorKind::InvalidKey(key));
+        }
+
+        Ok(key as usize)
+    }
+
+    /// Set key at specified index, completing a pending FX0A if this enters a key. Indices past
+    /// the end of the keypad are ignored.
     pub fn set_key(&mut self, index: usize, state: bool) {
+        if index >= NUM_KEYS {
+            return;
+        }
         self.keys[index] = state;
+
+        if let CpuState::WaitingForKey { register, pressed } = self.state {
+            let key = index as Byte;
+            let entered = match (self.key_wait_mode, state) {
+                (KeyWaitMode::Press, true) => true,
+                (KeyWaitMode::Release, true) => {
+                    self.state = CpuState::WaitingForKey { register, pressed: Some(key) };
+                    false
+                },
+                (KeyWaitMode::Release, false) => pressed == Some(key),
+                (KeyWaitMode::Press, false) => false
+            };
+
+            if entered {
+                self.registers[register] = key;
+                self.state = CpuState::Running;
+            }
+        }
+    }
+}
+
+impl Default for System {
+    fn default() -> Self {
+        System::new()
     }
 }
 
@@ -210,8 +707,409 @@ mod tests {
     fn read_word() {
         let mut system = System::new();
         load_test_data(&mut system);
-        assert_eq!(0xAF7F, system.read_word(0));
-        assert_eq!(0x7788, system.read_word(NUM_BYTES / 2));
-        assert_eq!(0x3A01, system.read_word(NUM_BYTES - 2));
+        assert_eq!(Ok(0xAF7F), system.read_word(0));
+        assert_eq!(Ok(0x7788), system.read_word(NUM_BYTES / 2));
+        assert_eq!(Ok(0x3A01), system.read_word(NUM_BYTES - 2));
+    }
+
+    /** Reading a word that runs past the end of memory should fail rather than panic. */
+    #[test]
+    fn read_word_out_of_bounds() {
+        let system = System::new();
+        assert_eq!(Err(ErrorKind::MemoryOutOfBounds(NUM_BYTES)), system.read_word(NUM_BYTES - 1));
+        assert_eq!(Err(ErrorKind::MemoryOutOfBounds(NUM_BYTES + 8)), system.read_word(NUM_BYTES + 8));
+    }
+
+    /** Running off the end of memory should report where the fetch failed. */
+    #[test]
+    fn step_fetch_out_of_bounds() {
+        let mut system = System::new();
+        system.pc = (NUM_BYTES - 1) as Address;
+
+        let error = system.step().error.unwrap();
+        assert_eq!(Chip8Error {
+            pc: (NUM_BYTES - 1) as Address,
+            opcode: None,
+            kind: ErrorKind::MemoryOutOfBounds(NUM_BYTES)
+        }, error);
+        assert_eq!((NUM_BYTES - 1) as Address, system.pc);
+    }
+
+    /** Loading a ROM should copy it to the load address and start from there. */
+    #[test]
+    fn load_rom() {
+        let mut system = System::new();
+        system.registers[0x3] = 0x42;
+
+        assert_eq!(4, system.load_rom(&[0x60, 0x15, 0x12, 0x00][..]).unwrap());
+        assert_eq!([0x60, 0x15, 0x12, 0x00], system.memory[0x200..0x204]);
+        assert_eq!(0x200, system.pc);
+        assert_eq!(0x00, system.registers[0x3]);
+        assert_eq!(0xF0, system.memory[FONT_ADDRESS as usize]);
+
+        system.set_load_address(0x600);
+        system.load_rom(&[0x6A, 0x01][..]).unwrap();
+        assert_eq!(0x600, system.pc);
+        assert_eq!([0x6A, 0x01], system.memory[0x600..0x602]);
+        assert_eq!([0x00, 0x00], system.memory[0x200..0x202]);
+    }
+
+    /** A ROM that does not fit in memory should be rejected without touching the system. */
+    #[test]
+    fn load_rom_too_large() {
+        let mut system = System::new();
+        system.load_rom(&[0x60, 0x15][..]).unwrap();
+
+        let rom = vec![0xFF; NUM_BYTES - 0x200 + 1];
+        match system.load_rom(&rom[..]) {
+            Err(RomError::TooLarge { size, capacity }) => {
+                assert_eq!(NUM_BYTES - 0x200 + 1, size);
+                assert_eq!(NUM_BYTES - 0x200, capacity);
+            },
+            result => panic!("unexpected result {:?}", result)
+        }
+        assert_eq!([0x60, 0x15], system.memory[0x200..0x202]);
+
+        assert_eq!(NUM_BYTES - 0x200, system.load_rom(&rom[1..]).unwrap());
+    }
+
+    /** Loading a ROM from a file that does not exist should fail rather than panic. */
+    #[test]
+    fn load_rom_missing_file() {
+        match System::from_rom("src/resources/roms/DOES_NOT_EXIST") {
+            Err(RomError::Io(_)) => (),
+            _ => panic!("expected an I/O error")
+        }
+    }
+
+    /** A new system should start with the CHIP-8 quirks. Switching to XO-CHIP should give it
+      *     64 KiB of memory, and switching back should take it away again. */
+    #[test]
+    fn platform_memory() {
+        let mut system = System::new();
+        assert_eq!(Platform::Chip8.quirks(), system.quirks());
+
+        system.set_platform(Platform::XoChip);
+        assert_eq!(0x10000, system.memory.len());
+        assert_eq!(Quirks::xo_chip(), system.quirks());
+        let rom = vec![0xAA; 0x10000 - 0x200];
+        assert_eq!(0x10000 - 0x200, system.load_rom(&rom[..]).unwrap());
+        assert_eq!(0xF0, system.memory[FONT_ADDRESS as usize]);
+
+        system.set_platform(Platform::Chip8);
+        assert_eq!(NUM_BYTES, system.memory.len());
+        assert_eq!(Quirks::cosmac_vip(), system.quirks());
+    }
+
+    /** On XO-CHIP, F000 NNNN should run as a single 4 byte instruction. */
+    #[test]
+    fn step_long_instruction() {
+        let mut system = System::new();
+        system.set_platform(Platform::XoChip);
+        system.load_rom(&[
+            0xF0, 0x00, 0xBE, 0xEF,     // i := long 0xBEEF
+            0x30, 0x00,                 // skip if V0 == 0
+            0xF0, 0x00, 0x12, 0x34,     // i := long 0x1234
+            0x60, 0x01                  // V0 = 1
+        ][..]).unwrap();
+
+        system.step();
+        assert_eq!(0xBEEF, system.index);
+        assert_eq!(0x204, system.pc);
+
+        system.run_cycles(2).unwrap();
+        assert_eq!(0xBEEF, system.index);
+        assert_eq!(0x01, system.registers[0x0]);
+        assert_eq!(0x20C, system.pc);
+    }
+
+    /** FX0A should leave the CPU waiting, without executing anything, until a key is pressed. */
+    #[test]
+    fn wait_for_key_press() {
+        let mut system = System::new();
+        system.memory[0x200] = 0xF5;    // V5 = key
+        system.memory[0x201] = 0x0A;
+        system.memory[0x202] = 0x70;    // V0 += 1
+        system.memory[0x203] = 0x01;
+
+        system.run_cycles(10).unwrap();
+        assert!(system.is_waiting_for_key());
+        assert_eq!(0x202, system.pc);
+
+        let result = system.step();
+        assert_eq!(0x200, result.address);
+        assert_eq!(OpCode::WaitKeyPress(0x5), result.opcode);
+        system.run_frame(10).unwrap();
+        assert_eq!(0x202, system.pc);
+        assert_eq!(0x00, system.registers[0x0]);
+
+        system.handle_input(115, KeyEventType::KeyPress);   // S -> 8
+        assert_eq!(CpuState::Running, system.cpu_state());
+        assert_eq!(0x8, system.registers[0x5]);
+
+        system.step();
+        assert_eq!(0x01, system.registers[0x0]);
+    }
+
+    /** In release mode FX0A should only complete once the pressed key is let go. */
+    #[test]
+    fn wait_for_key_release() {
+        let mut system = System::new();
+        system.set_key_wait_mode(KeyWaitMode::Release);
+        system.memory[0x200] = 0xF5;    // V5 = key
+        system.memory[0x201] = 0x0A;
+
+        system.step();
+        system.set_key(0x3, true);
+        assert!(system.is_waiting_for_key());
+
+        // releasing some other key should not count
+        system.set_key(0x7, false);
+        assert!(system.is_waiting_for_key());
+
+        system.set_key(0x3, false);
+        assert!(!system.is_waiting_for_key());
+        assert_eq!(0x3, system.registers[0x5]);
+    }
+
+    /** Setting a key that is not on the keypad should change nothing, even during FX0A. */
+    #[test]
+    fn set_key_out_of_range() {
+        let mut system = System::new();
+        system.state = CpuState::WaitingForKey { register: 0x5, pressed: None };
+
+        system.set_key(NUM_KEYS, true);
+        system.set_key(usize::MAX, true);
+        assert_eq!(vec![false; NUM_KEYS], system.keys);
+        assert_eq!(CpuState::WaitingForKey { register: 0x5, pressed: None }, system.cpu_state());
+    }
+
+    /** With the display wait quirk, nothing should run after DXYN until the next frame. */
+    #[test]
+    fn display_wait() {
+        let mut system = System::new();
+        system.set_quirks(Quirks::cosmac_vip());
+        system.memory[0x200] = 0xD0;    // draw
+        system.memory[0x201] = 0x01;
+        system.memory[0x202] = 0x70;    // V0 += 1
+        system.memory[0x203] = 0x01;
+
+        system.run_frame(10).unwrap();
+        assert_eq!(0x202, system.pc);
+        assert_eq!(CpuState::Running, system.cpu_state());
+
+        system.run_frame(1).unwrap();
+        assert_eq!(0x204, system.pc);
+        assert_eq!(0x01, system.registers[0x0]);
+    }
+
+    /** Ticking the timers should count each down by one, stopping at zero. */
+    #[test]
+    fn tick_timers() {
+        let mut system = System::new();
+        system.delay_timer = 2;
+        system.sound_timer = 1;
+
+        system.tick_timers();
+        assert_eq!(1, system.delay_timer());
+        assert_eq!(0, system.sound_timer());
+
+        system.tick_timers();
+        system.tick_timers();
+        assert_eq!(0, system.delay_timer());
+        assert_eq!(0, system.sound_timer());
+    }
+
+    /** Each frame should tick the timers exactly once, however many instructions it runs. */
+    #[test]
+    fn run_frame_ticks_timers() {
+        let mut system = System::new();
+        for address in (0x200..0x400).step_by(2) {
+            system.memory[address] = 0x70;  // V0 += 1
+            system.memory[address + 1] = 0x01;
+        }
+        system.delay_timer = 10;
+
+        system.run_frame(15).unwrap();
+        assert_eq!(9, system.delay_timer());
+
+        system.run_frame(1).unwrap();
+        assert_eq!(8, system.delay_timer());
+    }
+
+    /** Cycle driven timers should tick at 60 Hz of emulated time, and not again per frame. */
+    #[test]
+    fn cycle_timing() {
+        let mut system = System::new();
+        for address in (0x200..0x1000).step_by(2) {
+            system.memory[address] = 0x70;  // V0 += 1
+            system.memory[address + 1] = 0x01;
+        }
+        system.set_cycle_timing(Some(600));
+        system.delay_timer = 60;
+
+        system.run_cycles(9).unwrap();
+        assert_eq!(60, system.delay_timer());
+        system.run_cycles(1).unwrap();
+        assert_eq!(59, system.delay_timer());
+
+        system.run_frame(10).unwrap();
+        assert_eq!(58, system.delay_timer());
+
+        system.run_cycles(580).unwrap();
+        assert_eq!(0, system.delay_timer());
+
+        system.delay_timer = 60;
+        system.set_cycle_timing(Some(0));
+        system.run_cycles(600).unwrap();
+        assert_eq!(60, system.delay_timer());
+    }
+
+    /** Two systems seeded alike should draw the same random numbers, and a clone should carry on
+      *     drawing the numbers the original would have. */
+    #[test]
+    fn seed_random() {
+        let mut rom = Vec::new();
+        for register in 0..16 {
+            rom.push(0xC0 | register);  // VX = random
+            rom.push(0xFF);
+        }
+        let run = |system: &mut System| {
+            system.run_cycles(16).unwrap();
+            system.registers
+        };
+
+        let mut first = System::new();
+        first.seed_random(1234);
+        first.load_rom(&rom[..]).unwrap();
+        let mut second = System::new();
+        second.seed_random(1234);
+        second.load_rom(&rom[..]).unwrap();
+        assert_eq!(run(&mut first), run(&mut second));
+
+        first.load_rom(&rom[..]).unwrap();
+        let mut copy = first.clone();
+        assert_eq!(run(&mut first), run(&mut copy));
+    }
+
+    /** User flags saved by one system should be restored by another running the same ROM, but
+      *     not by one running a different ROM. */
+    #[test]
+    fn flags_persist_per_rom() {
+        let storage = Rc::new(RefCell::new(MemoryFlagStorage::new()));
+        let rom = [0x60, 0x2A, 0xF0, 0x75];    // V0 = 42, save V0
+
+        let mut system = System::new();
+        system.set_platform(Platform::SuperChip);
+        system.set_flag_storage(Some(storage.clone()));
+        system.load_rom(&rom[..]).unwrap();
+        system.run_cycles(2).unwrap();
+        assert_eq!(Some(&[0x2A, 0, 0, 0, 0, 0, 0, 0][..]),
+            storage.borrow().flags(rom_hash(&rom)));
+
+        let mut system = System::new();
+        system.set_platform(Platform::SuperChip);
+        system.set_flag_storage(Some(storage.clone()));
+        system.load_rom(&[0xF0, 0x85][..]).unwrap();
+        system.step();
+        assert_eq!(0x00, system.registers[0x0]);
+
+        system.load_rom(&rom[..]).unwrap();
+        system.memory[0x200] = 0xF0;    // restore V0
+        system.memory[0x201] = 0x85;
+        system.step();
+        assert_eq!(0x2A, system.registers[0x0]);
+    }
+
+    /** Sound should be rendered while the sound timer runs, and stop once it runs out. */
+    #[test]
+    fn render_audio() {
+        let mut system = System::new();
+        let mut buffer = [0; 32];
+
+        system.render_audio(44100, &mut buffer);
+        assert!(buffer.iter().all(|&sample| sample == 0));
+
+        system.sound_timer = 1;
+        system.render_audio(44100, &mut buffer);
+        assert!(buffer.iter().any(|&sample| sample != 0));
+
+        system.tick_timers();
+        system.render_audio(44100, &mut buffer);
+        assert!(buffer.iter().all(|&sample| sample == 0));
+    }
+
+    /** A new system should have the standard font loaded at the default font address. */
+    #[test]
+    fn font_loaded() {
+        let system = System::new();
+        let start = FONT_ADDRESS as usize;
+        assert_eq!([0xF0, 0x90, 0x90, 0x90, 0xF0], system.memory[start..start + 5]);
+        assert_eq!([0xF0, 0x80, 0xF0, 0x80, 0x80], system.memory[start + 75..start + 80]);
+    }
+
+    /** Selecting another font should load its glyphs at the requested address. */
+    #[test]
+    fn set_font() {
+        let mut system = System::new();
+
+        system.set_font(Font::Dream6800, 0x000).unwrap();
+        assert_eq!([0x40, 0x40, 0x40, 0x40, 0x40], system.memory[5..10]);
+        assert_eq!(0x000, system.font_address);
+
+        assert!(system.set_font(Font::Eti660, 0xFF0).is_err());
+        assert_eq!(Font::Dream6800, system.font);
+    }
+
+    /** Stepping should fetch the word at the program counter, advance past it and execute it. */
+    #[test]
+    fn step() {
+        let mut system = System::new();
+        system.memory[0x200] = 0x6A;
+        system.memory[0x201] = 0x42;
+
+        let result = system.step();
+        assert_eq!(0x200, result.address);
+        assert_eq!(OpCode::SetValue(0xA, 0x42), result.opcode);
+        assert!(result.is_ok());
+        assert_eq!(0x202, system.pc);
+        assert_eq!(0x42, system.registers[0xA]);
+    }
+
+    /** A subroutine call made through step() should return to the following instruction. */
+    #[test]
+    fn step_subroutine() {
+        let mut system = System::new();
+        system.memory[0x200] = 0x23;    // call 0x300
+        system.memory[0x201] = 0x00;
+        system.memory[0x300] = 0x00;    // return
+        system.memory[0x301] = 0xEE;
+
+        assert_eq!(Ok(2), system.run_cycles(2));
+        assert_eq!(0x202, system.pc);
+        assert_eq!(0, system.sp);
+    }
+
+    /** Running cycles should stop at the first instruction that fails to execute. */
+    #[test]
+    fn run_cycles_stops_on_error() {
+        let mut system = System::new();
+        system.memory[0x200] = 0x60;    // V0 = 0x01
+        system.memory[0x201] = 0x01;
+        system.memory[0x202] = 0x80;    // unknown 0x800F
+        system.memory[0x203] = 0x0F;
+        system.memory[0x204] = 0x61;    // V1 = 0x01
+        system.memory[0x205] = 0x01;
+
+        let result = system.run_frame(10).unwrap_err();
+        assert_eq!(0x202, result.address);
+        assert_eq!(OpCode::Unknown, result.opcode);
+        assert_eq!(Some(Chip8Error {
+            pc: 0x202,
+            opcode: Some(0x800F),
+            kind: ErrorKind::UnknownOpcode
+        }), result.error);
+        assert_eq!(0x01, system.registers[0x0]);
+        assert_eq!(0x00, system.registers[0x1]);
     }
 }
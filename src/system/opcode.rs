@@ -1,7 +1,10 @@
-extern crate rand;
+use std::fmt;
 
-use system::{System, Word, Byte, RegisterIndex, Address};
+use system::{System, CpuState, ErrorKind, Word, Byte, RegisterIndex, Address};
+use system::PATTERN_SIZE;
+use system::font::{GLYPH_SIZE, LARGE_GLYPH_SIZE};
 
+const FAMILY_MASK: Word = 0b1111_0000_0000_0000;
 const VALUE_MASK: Word = 0b0000_0000_1111_1111;
 const REGISTER_MASK: Word = 0b0000_1111_0000_0000;
 const ADDRESS_MASK: Word = 0b0000_1111_1111_1111;
@@ -10,12 +13,15 @@ const TYPE_MASK: Word = 0b0000_0000_0000_1111;
 const LEFT_MASK: Word = 0b0000_1111_0000_0000;
 const RIGHT_MASK: Word = 0b0000_0000_1111_0000;
 
+/// A single decoded Chip8 instruction.
 #[allow(dead_code)]
-enum OpCode {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpCode {
 	JumpAddress(Address),
 	JumpAddressOffset(Address),
 	SubJump(Address),
 	SubReturn,
+	MachineCall(Address),
 	SkipValue(RegisterIndex, Byte),
 	SkipRegister(RegisterIndex, RegisterIndex),
 	SkipNotValue(RegisterIndex, Byte),
@@ -38,94 +44,143 @@ enum OpCode {
 	WaitKeyPress(RegisterIndex),
 	SkipKeyPressed(RegisterIndex),
 	SkipKeyNotPressed(RegisterIndex),
+	ClearScreen,
+	Draw(RegisterIndex, RegisterIndex, Byte),
+	SetIndex(Address),
+	AddIndex(RegisterIndex),
+	StoreDecimal(RegisterIndex),
+	StoreRegisters(RegisterIndex),
+	LoadRegisters(RegisterIndex),
+	SetIndexToSprite(RegisterIndex),
+	SetIndexToLargeSprite(RegisterIndex),
+	ScrollDown(Byte),
+	ScrollRight,
+	ScrollLeft,
+	Exit,
+	LowResolution,
+	HighResolution,
+	ScrollUp(Byte),
+	SetIndexLong,
+	SaveRange(RegisterIndex, RegisterIndex),
+	LoadRange(RegisterIndex, RegisterIndex),
+	SelectPlanes(Byte),
+	LoadAudioPattern,
+	SetPitch(RegisterIndex),
+	SaveFlags(RegisterIndex),
+	LoadFlags(RegisterIndex),
 	Unknown
 }
 
 #[allow(dead_code)]
 impl OpCode {
-	fn execute(&self, system: &mut System) -> Result<(), &'static str> {
-		match self {
-			&OpCode::JumpAddress(address) => {
+	pub(super) fn execute(&self, system: &mut System) -> Result<(), ErrorKind> {
+		// CHIP-8 has no instructions at these addresses, only calls to machine code
+		if !system.platform.has_super_chip() {
+			if let Some(address) = self.machine_call_address() {
+				return system.machine_call(address);
+			}
+		}
+
+		match *self {
+			OpCode::JumpAddress(address) => {
 				system.pc = address;
 			},
 
-			&OpCode::JumpAddressOffset(address) => {
-				system.pc = address + system.registers[0x0] as Word;
+			OpCode::JumpAddressOffset(address) => {
+				let register = if system.quirks.jump_uses_vx {
+					((address & REGISTER_MASK) >> 8) as usize
+				} else {
+					0x0
+				};
+				system.pc = address + system.registers[register] as Word;
 			},
 
-			&OpCode::SubJump(address) => {
+			OpCode::SubJump(address) => {
+				if system.sp as usize >= system.stack.len() {
+					return Err(ErrorKind::StackOverflow);
+				}
 				system.stack[system.sp as usize] = system.pc;
 				system.sp += 1;
 				system.pc = address;
 			},
 
-			&OpCode::SubReturn => {
+			OpCode::SubReturn => {
+				if system.sp == 0 {
+					return Err(ErrorKind::StackUnderflow);
+				}
 				system.sp -= 1;
 				system.pc = system.stack[system.sp as usize];
 			},
 
-			&OpCode::SkipValue(register, value) => {
+			OpCode::MachineCall(address) => {
+				system.machine_call(address)?;
+			},
+
+			OpCode::SkipValue(register, value) => {
 				if system.registers[register] == value {
-					system.increment_pc();
+					system.skip_instruction();
 				}
 			},
 
-			&OpCode::SkipRegister(left, right) => {
+			OpCode::SkipRegister(left, right) => {
 				if system.registers[left] == system.registers[right] {
-					system.increment_pc();
+					system.skip_instruction();
 				}
 			},
 
-			&OpCode::SkipNotValue(register, value) => {
+			OpCode::SkipNotValue(register, value) => {
 				if system.registers[register] != value {
-					system.increment_pc();
+					system.skip_instruction();
 				}
 			},
 
-			&OpCode::SkipNotRegister(left, right) => {
+			OpCode::SkipNotRegister(left, right) => {
 				if system.registers[left] != system.registers[right] {
-					system.increment_pc();
+					system.skip_instruction();
 				}
 			},
 
-			&OpCode::SetDelayTimer(register) => {
+			OpCode::SetDelayTimer(register) => {
 				system.delay_timer = system.registers[register];
 			},
 
-			&OpCode::SetRegisterFromTimer(register) => {
+			OpCode::SetRegisterFromTimer(register) => {
 				system.registers[register] = system.delay_timer;
 			},
 
-			&OpCode::SetSoundTimer(register) => {
+			OpCode::SetSoundTimer(register) => {
 				system.sound_timer = system.registers[register];
 			},
 
-			&OpCode::SetValue(register, value) => {
+			OpCode::SetValue(register, value) => {
 				system.registers[register] = value;
 			},
 
-			&OpCode::AddValue(register, value) => {
+			OpCode::AddValue(register, value) => {
 				system.registers[register]
 					= system.registers[register].wrapping_add(value);
 			},
 
-			&OpCode::SetRegister(left, right) => {
+			OpCode::SetRegister(left, right) => {
 				system.registers[left] = system.registers[right];
 			},
 
-			&OpCode::OrRegister(left, right) => {
+			OpCode::OrRegister(left, right) => {
 				system.registers[left] |= system.registers[right];
+				system.reset_flag_after_logic();
 			},
 
-			&OpCode::AndRegister(left, right) => {
+			OpCode::AndRegister(left, right) => {
 				system.registers[left] &= system.registers[right];
+				system.reset_flag_after_logic();
 			},
 
-			&OpCode::XorRegister(left, right) => {
+			OpCode::XorRegister(left, right) => {
 				system.registers[left] ^= system.registers[right];
+				system.reset_flag_after_logic();
 			},
 
-			&OpCode::AddRegister(left, right) => {
+			OpCode::AddRegister(left, right) => {
 				let (value, overflow) =
 					system.registers[left].overflowing_add(system.registers[right]);
 				system.registers[left] = value;
@@ -136,73 +191,238 @@ impl OpCode {
 				}
 			},
 
-			&OpCode::SubRegisterRight(left, right) => {
-				let (value, overflow) =
+			OpCode::SubRegisterRight(left, right) => {
+				let (value, borrow) =
 					system.registers[left].overflowing_sub(system.registers[right]);
 				system.registers[left] = value;
-				if overflow {
-					system.registers[0xF] = 0x1;
-				} else {
+				if borrow {
 					system.registers[0xF] = 0x0;
+				} else {
+					system.registers[0xF] = 0x1;
 				}
 			},
 
-			&OpCode::RShiftRegister(left, right) => {
-				let least_bit = system.registers[right] & 0x1;
-				system.registers[left] = system.registers[right] >> 1;
+			OpCode::RShiftRegister(left, right) => {
+				let source = system.shift_source(left, right);
+				let least_bit = system.registers[source] & 0x1;
+				system.registers[left] = system.registers[source] >> 1;
 				system.registers[0xF] = least_bit;
 			},
 
-			&OpCode::SubRegisterLeft(left, right) => {
-				let (value, overflow) =
+			OpCode::SubRegisterLeft(left, right) => {
+				let (value, borrow) =
 					system.registers[right].overflowing_sub(system.registers[left]);
-				system.registers[right] = value;
-				if overflow {
-					system.registers[0xF] = 0x1;
-				} else {
+				system.registers[left] = value;
+				if borrow {
 					system.registers[0xF] = 0x0;
+				} else {
+					system.registers[0xF] = 0x1;
 				}
 			},
 
-			&OpCode::LShiftRegister(left, right) => {
-				let most_bit = system.registers[right] >> 0x7;
-				system.registers[left] = system.registers[right] << 1;
+			OpCode::LShiftRegister(left, right) => {
+				let source = system.shift_source(left, right);
+				let most_bit = system.registers[source] >> 0x7;
+				system.registers[left] = system.registers[source] << 1;
 				system.registers[0xF] = most_bit;
 			},
 
-			&OpCode::RandomValue(register, value) => {
-				system.registers[register] = rand::random::<Byte>() & value;
+			OpCode::RandomValue(register, value) => {
+				system.registers[register] = system.rng.next_byte() & value;
 			},
 
-			&OpCode::WaitKeyPress(register) => {
-                system.stop();
-                loop {
-                    // Will grab the last key pressed since execution of system has stopped!
-                    let (key, is_new) = system.last_key_pressed;
-                    if is_new {
-                        system.registers[register] = key;
-                        break;
-                    }
-                }
-                system.start();
+			OpCode::WaitKeyPress(register) => {
+				system.state = CpuState::WaitingForKey { register, pressed: None };
 			},
 
-			&OpCode::SkipKeyPressed(register) => {
-				let index = system.registers[register] as usize;
+			OpCode::SkipKeyPressed(register) => {
+				let index = system.key_index(register)?;
 				if system.keys[index] {
-					system.increment_pc();
+					system.skip_instruction();
 				}
 			},
 
-			&OpCode::SkipKeyNotPressed(register) => {
-				let index = system.registers[register] as usize;
+			OpCode::SkipKeyNotPressed(register) => {
+				let index = system.key_index(register)?;
 				if !system.keys[index] {
-					system.increment_pc();
+					system.skip_instruction();
+				}
+			},
+
+			OpCode::ClearScreen => {
+				system.display.clear();
+			},
+
+			OpCode::Draw(left, right, rows) => {
+				let large = rows == 0 && system.platform.has_super_chip();
+				let plane_size = if large { 32 } else { rows as usize };
+				let length = plane_size * system.display.plane_count();
+				let start = system.index as usize;
+				system.check_memory(start, length)?;
+
+				let x = system.registers[left] as usize;
+				let y = system.registers[right] as usize;
+				let sprite = &system.memory[start..start + length];
+				let edges = system.quirks.sprite_edges;
+				let collision = if large {
+					system.display.draw_large_sprite(x, y, sprite, edges)
+				} else {
+					system.display.draw_sprite(x, y, sprite, edges)
+				};
+				system.registers[0xF] = if collision { 0x1 } else { 0x0 };
+
+				if system.quirks.display_wait {
+					system.state = CpuState::WaitingForFrame;
 				}
 			},
 
-			&OpCode::Unknown => {
-				return Err("Unrecognized Instruction!");
+			OpCode::SetIndex(address) => {
+				system.index = address;
+			},
+
+			OpCode::AddIndex(register) => {
+				system.index = system.index.wrapping_add(system.registers[register] as Word);
+			},
+
+			OpCode::StoreDecimal(register) => {
+				let address = system.index as usize;
+				system.check_memory(address, 3)?;
+
+				let value = system.registers[register];
+				system.memory[address] = value / 100;
+				system.memory[address + 1] = (value / 10) % 10;
+				system.memory[address + 2] = value % 10;
+			},
+
+			OpCode::StoreRegisters(register) => {
+				let address = system.index as usize;
+				system.check_memory(address, register + 1)?;
+
+				for offset in 0..(register + 1) {
+					system.memory[address + offset] = system.registers[offset];
+				}
+				system.increment_index_after_transfer(register);
+			},
+
+			OpCode::LoadRegisters(register) => {
+				let address = system.index as usize;
+				system.check_memory(address, register + 1)?;
+
+				for offset in 0..(register + 1) {
+					system.registers[offset] = system.memory[address + offset];
+				}
+				system.increment_index_after_transfer(register);
+			},
+
+			OpCode::SetIndexToSprite(register) => {
+				let digit = (system.registers[register] & 0xF) as Word;
+				system.index = system.font_address + digit * GLYPH_SIZE as Word;
+			},
+
+			OpCode::SetIndexToLargeSprite(register) => {
+				system.require_super_chip()?;
+				let digit = (system.registers[register] & 0xF) as Word;
+				system.index = system.large_font_address() + digit * LARGE_GLYPH_SIZE as Word;
+			},
+
+			OpCode::ScrollDown(rows) => {
+				system.require_super_chip()?;
+				system.display.scroll_down(rows as usize);
+			},
+
+			OpCode::ScrollRight => {
+				system.require_super_chip()?;
+				system.display.scroll_right(4);
+			},
+
+			OpCode::ScrollLeft => {
+				system.require_super_chip()?;
+				system.display.scroll_left(4);
+			},
+
+			OpCode::Exit => {
+				system.require_super_chip()?;
+				system.state = CpuState::Halted;
+			},
+
+			OpCode::LowResolution => {
+				system.require_super_chip()?;
+				system.display.set_high_resolution(false);
+			},
+
+			OpCode::HighResolution => {
+				system.require_super_chip()?;
+				system.display.set_high_resolution(true);
+			},
+
+			OpCode::ScrollUp(rows) => {
+				system.require_xo_chip()?;
+				system.display.scroll_up(rows as usize);
+			},
+
+			OpCode::SetIndexLong => {
+				system.require_xo_chip()?;
+				system.index = system.read_word(system.pc as usize)?;
+				system.increment_pc();
+			},
+
+			OpCode::SaveRange(left, right) => {
+				system.require_xo_chip()?;
+				let address = system.index as usize;
+				let registers = register_range(left, right);
+				system.check_memory(address, registers.len())?;
+
+				for (offset, &register) in registers.iter().enumerate() {
+					system.memory[address + offset] = system.registers[register];
+				}
+			},
+
+			OpCode::LoadRange(left, right) => {
+				system.require_xo_chip()?;
+				let address = system.index as usize;
+				let registers = register_range(left, right);
+				system.check_memory(address, registers.len())?;
+
+				for (offset, &register) in registers.iter().enumerate() {
+					system.registers[register] = system.memory[address + offset];
+				}
+			},
+
+			OpCode::SelectPlanes(planes) => {
+				system.require_xo_chip()?;
+				system.display.set_planes(planes);
+			},
+
+			OpCode::LoadAudioPattern => {
+				system.require_xo_chip()?;
+				let address = system.index as usize;
+				system.check_memory(address, PATTERN_SIZE)?;
+				system.audio.set_pattern(&system.memory[address..address + PATTERN_SIZE]);
+			},
+
+			OpCode::SetPitch(register) => {
+				system.require_xo_chip()?;
+				system.audio.set_pitch(system.registers[register]);
+			},
+
+			OpCode::SaveFlags(register) => {
+				system.require_super_chip()?;
+				system.check_flags(register)?;
+
+				system.flags[..register + 1].copy_from_slice(&system.registers[..register + 1]);
+				system.save_flags()?;
+			},
+
+			OpCode::LoadFlags(register) => {
+				system.require_super_chip()?;
+				system.check_flags(register)?;
+
+				system.load_flags()?;
+				system.registers[..register + 1].copy_from_slice(&system.flags[..register + 1]);
+			},
+
+			OpCode::Unknown => {
+				return Err(ErrorKind::UnknownOpcode);
 			}
 		}
 
@@ -210,49 +430,113 @@ impl OpCode {
 	}
 }
 
+/// The registers from VX to VY inclusive, counting down if Y is less than X.
+fn register_range(left: RegisterIndex, right: RegisterIndex) -> Vec<RegisterIndex> {
+	if left <= right {
+		(left..right + 1).collect()
+	} else {
+		(right..left + 1).rev().collect()
+	}
+}
+
 impl From<Word> for OpCode {
 	fn from(word: Word) -> Self {
 		let register = ((word & REGISTER_MASK) >> 8) as usize;
+		let left = ((word & LEFT_MASK) >> 8) as usize;
+		let right = ((word & RIGHT_MASK) >> 4) as usize;
 		let value = (word & VALUE_MASK) as Byte;
+		let address = word & ADDRESS_MASK;
+
+		match (word & FAMILY_MASK) >> 12 {
+			0x0 => {
+				match word {
+					0x00E0 => {
+						OpCode::ClearScreen
+					},
+
+					0x00EE => {
+						OpCode::SubReturn
+					},
+
+					0x00C0..=0x00CF => {
+						OpCode::ScrollDown((word & TYPE_MASK) as Byte)
+					},
+
+					0x00D0..=0x00DF => {
+						OpCode::ScrollUp((word & TYPE_MASK) as Byte)
+					},
+
+					0x00FB => {
+						OpCode::ScrollRight
+					},
 
-		match word {
-			0x0000...0x1000 => {
-				OpCode::SubReturn
+					0x00FC => {
+						OpCode::ScrollLeft
+					},
+
+					0x00FD => {
+						OpCode::Exit
+					},
+
+					0x00FE => {
+						OpCode::LowResolution
+					},
+
+					0x00FF => {
+						OpCode::HighResolution
+					},
+
+					_ => {
+						OpCode::MachineCall(address)
+					}
+				}
 			},
 
-			0x1000...0x2000 => {
-				OpCode::JumpAddress(word & ADDRESS_MASK)
+			0x1 => {
+				OpCode::JumpAddress(address)
 			},
 
-			0x2000...0x3000 => {
-				OpCode::SubJump(word & ADDRESS_MASK)
+			0x2 => {
+				OpCode::SubJump(address)
 			},
 
-			0x3000...0x4000 => {
+			0x3 => {
 				OpCode::SkipValue(register, value)
 			},
 
-			0x4000...0x5000 => {
+			0x4 => {
 				OpCode::SkipNotValue(register, value)
-			}
+			},
 
-			0x5000...0x6000 => {
-				OpCode::SkipRegister(
-					((word & LEFT_MASK) >> 8) as usize,
-					((word & RIGHT_MASK) >> 4) as usize)
+			0x5 => {
+				match word & TYPE_MASK {
+					0x0 => {
+						OpCode::SkipRegister(left, right)
+					},
+
+					0x2 => {
+						OpCode::SaveRange(left, right)
+					},
+
+					0x3 => {
+						OpCode::LoadRange(left, right)
+					},
+
+					_ => {
+						OpCode::Unknown
+					}
+				}
 			},
 
-			0x6000...0x7000 => {
+			0x6 => {
 				OpCode::SetValue(register, value)
 			},
 
-			0x7000...0x8000 => {
+			0x7 => {
 				OpCode::AddValue(register, value)
 			},
 
-			0x8000...0x9000 => {
-				let left = ((word & LEFT_MASK) >> 8) as usize;
-                let right = ((word & RIGHT_MASK) >> 4) as usize;
+			0x8 => {
 				match word & TYPE_MASK {
 					0x0 => {
 						OpCode::SetRegister(left, right)
@@ -284,7 +568,7 @@ impl From<Word> for OpCode {
 
 					0x7 => {
 						OpCode::SubRegisterLeft(left, right)
-					}
+					},
 
 					0xE => {
 						OpCode::LShiftRegister(left, right)
@@ -296,21 +580,35 @@ impl From<Word> for OpCode {
 				}
 			},
 
-			0x9000...0xA000 => {
-				OpCode::SkipNotRegister(
-					((word & LEFT_MASK) >> 8) as usize,
-					((word & RIGHT_MASK) >> 4) as usize)
+			0x9 => {
+				match word & TYPE_MASK {
+					0x0 => {
+						OpCode::SkipNotRegister(left, right)
+					},
+
+					_ => {
+						OpCode::Unknown
+					}
+				}
+			},
+
+			0xA => {
+				OpCode::SetIndex(address)
 			},
 
-			0xB000...0xC000 => {
-				OpCode::JumpAddressOffset(word & ADDRESS_MASK)
+			0xB => {
+				OpCode::JumpAddressOffset(address)
 			},
 
-			0xC000...0xD000 => {
+			0xC => {
 				OpCode::RandomValue(register, value)
 			},
 
-			0xE000...0xF000 => {
+			0xD => {
+				OpCode::Draw(left, right, (word & TYPE_MASK) as Byte)
+			},
+
+			0xE => {
 				match word & VALUE_MASK {
 					0x9E => {
 						OpCode::SkipKeyPressed(register)
@@ -326,8 +624,20 @@ impl From<Word> for OpCode {
 				}
 			},
 
-			0xF000...0xFFFF => {
+			_ => {
 				match word & VALUE_MASK {
+					0x00 if word == 0xF000 => {
+						OpCode::SetIndexLong
+					},
+
+					0x01 => {
+						OpCode::SelectPlanes(register as Byte)
+					},
+
+					0x02 if word == 0xF002 => {
+						OpCode::LoadAudioPattern
+					},
+
 					0x15 => {
 						OpCode::SetDelayTimer(register)
 					},
@@ -344,22 +654,257 @@ impl From<Word> for OpCode {
 						OpCode::WaitKeyPress(register)
 					},
 
+					0x1E => {
+						OpCode::AddIndex(register)
+					},
+
+					0x29 => {
+						OpCode::SetIndexToSprite(register)
+					},
+
+					0x30 => {
+						OpCode::SetIndexToLargeSprite(register)
+					},
+
+					0x33 => {
+						OpCode::StoreDecimal(register)
+					},
+
+					0x3A => {
+						OpCode::SetPitch(register)
+					},
+
+					0x55 => {
+						OpCode::StoreRegisters(register)
+					},
+
+					0x65 => {
+						OpCode::LoadRegisters(register)
+					},
+
+					0x75 => {
+						OpCode::SaveFlags(register)
+					},
+
+					0x85 => {
+						OpCode::LoadFlags(register)
+					},
+
 					_ => {
 						OpCode::Unknown
 					}
 				}
-			},
-
-			_ => {
-				OpCode::Unknown
 			}
 		}
 	}
 }
 
+impl OpCode {
+	/// The 0NNN address of a SUPER-CHIP or XO-CHIP instruction that took over part of the
+	/// range CHIP-8 leaves to machine code, or None for any other instruction.
+	pub fn machine_call_address(&self) -> Option<Address> {
+		match *self {
+			OpCode::ScrollDown(_) | OpCode::ScrollUp(_) | OpCode::ScrollRight
+				| OpCode::ScrollLeft | OpCode::Exit | OpCode::LowResolution
+				| OpCode::HighResolution => self.encode(),
+			_ => None
+		}
+	}
+
+	/// The word this instruction decodes from, or None for `Unknown`. XO-CHIP's F000 NNNN
+	/// encodes as F000 alone, since the address is held in the following word.
+	pub fn encode(&self) -> Option<Word> {
+		let xy = |x: RegisterIndex, y: RegisterIndex| (x as Word) << 8 | (y as Word) << 4;
+		let xnn = |x: RegisterIndex, value: Byte| (x as Word) << 8 | value as Word;
+		let fx = |x: RegisterIndex, low: Word| 0xF000 | (x as Word) << 8 | low;
+
+		Some(match *self {
+			OpCode::JumpAddress(address) => 0x1000 | address & ADDRESS_MASK,
+			OpCode::JumpAddressOffset(address) => 0xB000 | address & ADDRESS_MASK,
+			OpCode::SubJump(address) => 0x2000 | address & ADDRESS_MASK,
+			OpCode::SubReturn => 0x00EE,
+			OpCode::MachineCall(address) => address & ADDRESS_MASK,
+			OpCode::SkipValue(x, value) => 0x3000 | xnn(x, value),
+			OpCode::SkipRegister(x, y) => 0x5000 | xy(x, y),
+			OpCode::SkipNotValue(x, value) => 0x4000 | xnn(x, value),
+			OpCode::SkipNotRegister(x, y) => 0x9000 | xy(x, y),
+			OpCode::SetDelayTimer(x) => fx(x, 0x15),
+			OpCode::SetRegisterFromTimer(x) => fx(x, 0x07),
+			OpCode::SetSoundTimer(x) => fx(x, 0x18),
+			OpCode::SetValue(x, value) => 0x6000 | xnn(x, value),
+			OpCode::AddValue(x, value) => 0x7000 | xnn(x, value),
+			OpCode::SetRegister(x, y) => 0x8000 | xy(x, y),
+			OpCode::OrRegister(x, y) => 0x8001 | xy(x, y),
+			OpCode::AndRegister(x, y) => 0x8002 | xy(x, y),
+			OpCode::XorRegister(x, y) => 0x8003 | xy(x, y),
+			OpCode::AddRegister(x, y) => 0x8004 | xy(x, y),
+			OpCode::SubRegisterRight(x, y) => 0x8005 | xy(x, y),
+			OpCode::RShiftRegister(x, y) => 0x8006 | xy(x, y),
+			OpCode::SubRegisterLeft(x, y) => 0x8007 | xy(x, y),
+			OpCode::LShiftRegister(x, y) => 0x800E | xy(x, y),
+			OpCode::RandomValue(x, value) => 0xC000 | xnn(x, value),
+			OpCode::WaitKeyPress(x) => fx(x, 0x0A),
+			OpCode::SkipKeyPressed(x) => 0xE09E | (x as Word) << 8,
+			OpCode::SkipKeyNotPressed(x) => 0xE0A1 | (x as Word) << 8,
+			OpCode::ClearScreen => 0x00E0,
+			OpCode::Draw(x, y, rows) => 0xD000 | xy(x, y) | rows as Word & TYPE_MASK,
+			OpCode::SetIndex(address) => 0xA000 | address & ADDRESS_MASK,
+			OpCode::AddIndex(x) => fx(x, 0x1E),
+			OpCode::StoreDecimal(x) => fx(x, 0x33),
+			OpCode::StoreRegisters(x) => fx(x, 0x55),
+			OpCode::LoadRegisters(x) => fx(x, 0x65),
+			OpCode::SetIndexToSprite(x) => fx(x, 0x29),
+			OpCode::SetIndexToLargeSprite(x) => fx(x, 0x30),
+			OpCode::ScrollDown(rows) => 0x00C0 | rows as Word & TYPE_MASK,
+			OpCode::ScrollRight => 0x00FB,
+			OpCode::ScrollLeft => 0x00FC,
+			OpCode::Exit => 0x00FD,
+			OpCode::LowResolution => 0x00FE,
+			OpCode::HighResolution => 0x00FF,
+			OpCode::ScrollUp(rows) => 0x00D0 | rows as Word & TYPE_MASK,
+			OpCode::SetIndexLong => 0xF000,
+			OpCode::SaveRange(x, y) => 0x5002 | xy(x, y),
+			OpCode::LoadRange(x, y) => 0x5003 | xy(x, y),
+			OpCode::SelectPlanes(planes) => fx(planes as RegisterIndex, 0x01),
+			OpCode::LoadAudioPattern => 0xF002,
+			OpCode::SetPitch(x) => fx(x, 0x3A),
+			OpCode::SaveFlags(x) => fx(x, 0x75),
+			OpCode::LoadFlags(x) => fx(x, 0x85),
+			OpCode::Unknown => return None
+		})
+	}
+}
+
+/// Classic Cowgod syntax, e.g. `LD V0, #64` and `DRW V0, V1, #5`. XO-CHIP's F000 NNNN is shown as
+/// `LD I, LONG`, since the address is held in the following word.
+impl fmt::Display for OpCode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			OpCode::JumpAddress(address) => write!(f, "JP #{:03X}", address),
+			OpCode::JumpAddressOffset(address) => write!(f, "JP V0, #{:03X}", address),
+			OpCode::SubJump(address) => write!(f, "CALL #{:03X}", address),
+			OpCode::SubReturn => write!(f, "RET"),
+			OpCode::MachineCall(address) => write!(f, "SYS #{:03X}", address),
+			OpCode::SkipValue(x, value) => write!(f, "SE V{:X}, #{:02X}", x, value),
+			OpCode::SkipRegister(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+			OpCode::SkipNotValue(x, value) => write!(f, "SNE V{:X}, #{:02X}", x, value),
+			OpCode::SkipNotRegister(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+			OpCode::SetDelayTimer(x) => write!(f, "LD DT, V{:X}", x),
+			OpCode::SetRegisterFromTimer(x) => write!(f, "LD V{:X}, DT", x),
+			OpCode::SetSoundTimer(x) => write!(f, "LD ST, V{:X}", x),
+			OpCode::SetValue(x, value) => write!(f, "LD V{:X}, #{:02X}", x, value),
+			OpCode::AddValue(x, value) => write!(f, "ADD V{:X}, #{:02X}", x, value),
+			OpCode::SetRegister(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+			OpCode::OrRegister(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+			OpCode::AndRegister(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+			OpCode::XorRegister(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+			OpCode::AddRegister(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+			OpCode::SubRegisterRight(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+			OpCode::RShiftRegister(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+			OpCode::SubRegisterLeft(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+			OpCode::LShiftRegister(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+			OpCode::RandomValue(x, value) => write!(f, "RND V{:X}, #{:02X}", x, value),
+			OpCode::WaitKeyPress(x) => write!(f, "LD V{:X}, K", x),
+			OpCode::SkipKeyPressed(x) => write!(f, "SKP V{:X}", x),
+			OpCode::SkipKeyNotPressed(x) => write!(f, "SKNP V{:X}", x),
+			OpCode::ClearScreen => write!(f, "CLS"),
+			OpCode::Draw(x, y, rows) => write!(f, "DRW V{:X}, V{:X}, #{:X}", x, y, rows),
+			OpCode::SetIndex(address) => write!(f, "LD I, #{:03X}", address),
+			OpCode::AddIndex(x) => write!(f, "ADD I, V{:X}", x),
+			OpCode::StoreDecimal(x) => write!(f, "LD B, V{:X}", x),
+			OpCode::StoreRegisters(x) => write!(f, "LD [I], V{:X}", x),
+			OpCode::LoadRegisters(x) => write!(f, "LD V{:X}, [I]", x),
+			OpCode::SetIndexToSprite(x) => write!(f, "LD F, V{:X}", x),
+			OpCode::SetIndexToLargeSprite(x) => write!(f, "LD HF, V{:X}", x),
+			OpCode::ScrollDown(rows) => write!(f, "SCD #{:X}", rows),
+			OpCode::ScrollRight => write!(f, "SCR"),
+			OpCode::ScrollLeft => write!(f, "SCL"),
+			OpCode::Exit => write!(f, "EXIT"),
+			OpCode::LowResolution => write!(f, "LOW"),
+			OpCode::HighResolution => write!(f, "HIGH"),
+			OpCode::ScrollUp(rows) => write!(f, "SCU #{:X}", rows),
+			OpCode::SetIndexLong => write!(f, "LD I, LONG"),
+			OpCode::SaveRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+			OpCode::LoadRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+			OpCode::SelectPlanes(planes) => write!(f, "PLANE #{:X}", planes),
+			OpCode::LoadAudioPattern => write!(f, "AUDIO"),
+			OpCode::SetPitch(x) => write!(f, "PITCH V{:X}", x),
+			OpCode::SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+			OpCode::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+			OpCode::Unknown => write!(f, "???")
+		}
+	}
+}
+
+impl OpCode {
+	/// The instruction in Octo syntax, e.g. `v0 := 0x64` and `sprite v0 v1 5`. Skips are shown
+	/// as the `if ... then` that compiles to them. XO-CHIP's F000 NNNN is shown as `i := long`,
+	/// since the address is held in the following word.
+	pub fn to_octo(&self) -> String {
+		match *self {
+			OpCode::JumpAddress(address) => format!("jump 0x{:03X}", address),
+			OpCode::JumpAddressOffset(address) => format!("jump0 0x{:03X}", address),
+			OpCode::SubJump(address) => format!(":call 0x{:03X}", address),
+			OpCode::SubReturn => "return".to_string(),
+			OpCode::MachineCall(address) => format!("native 0x{:03X}", address),
+			OpCode::SkipValue(x, value) => format!("if v{:x} != 0x{:02X} then", x, value),
+			OpCode::SkipRegister(x, y) => format!("if v{:x} != v{:x} then", x, y),
+			OpCode::SkipNotValue(x, value) => format!("if v{:x} == 0x{:02X} then", x, value),
+			OpCode::SkipNotRegister(x, y) => format!("if v{:x} == v{:x} then", x, y),
+			OpCode::SetDelayTimer(x) => format!("delay := v{:x}", x),
+			OpCode::SetRegisterFromTimer(x) => format!("v{:x} := delay", x),
+			OpCode::SetSoundTimer(x) => format!("buzzer := v{:x}", x),
+			OpCode::SetValue(x, value) => format!("v{:x} := 0x{:02X}", x, value),
+			OpCode::AddValue(x, value) => format!("v{:x} += 0x{:02X}", x, value),
+			OpCode::SetRegister(x, y) => format!("v{:x} := v{:x}", x, y),
+			OpCode::OrRegister(x, y) => format!("v{:x} |= v{:x}", x, y),
+			OpCode::AndRegister(x, y) => format!("v{:x} &= v{:x}", x, y),
+			OpCode::XorRegister(x, y) => format!("v{:x} ^= v{:x}", x, y),
+			OpCode::AddRegister(x, y) => format!("v{:x} += v{:x}", x, y),
+			OpCode::SubRegisterRight(x, y) => format!("v{:x} -= v{:x}", x, y),
+			OpCode::RShiftRegister(x, y) => format!("v{:x} >>= v{:x}", x, y),
+			OpCode::SubRegisterLeft(x, y) => format!("v{:x} =- v{:x}", x, y),
+			OpCode::LShiftRegister(x, y) => format!("v{:x} <<= v{:x}", x, y),
+			OpCode::RandomValue(x, value) => format!("v{:x} := random 0x{:02X}", x, value),
+			OpCode::WaitKeyPress(x) => format!("v{:x} := key", x),
+			OpCode::SkipKeyPressed(x) => format!("if v{:x} -key then", x),
+			OpCode::SkipKeyNotPressed(x) => format!("if v{:x} key then", x),
+			OpCode::ClearScreen => "clear".to_string(),
+			OpCode::Draw(x, y, rows) => format!("sprite v{:x} v{:x} {}", x, y, rows),
+			OpCode::SetIndex(address) => format!("i := 0x{:03X}", address),
+			OpCode::AddIndex(x) => format!("i += v{:x}", x),
+			OpCode::StoreDecimal(x) => format!("bcd v{:x}", x),
+			OpCode::StoreRegisters(x) => format!("save v{:x}", x),
+			OpCode::LoadRegisters(x) => format!("load v{:x}", x),
+			OpCode::SetIndexToSprite(x) => format!("i := hex v{:x}", x),
+			OpCode::SetIndexToLargeSprite(x) => format!("i := bighex v{:x}", x),
+			OpCode::ScrollDown(rows) => format!("scroll-down {}", rows),
+			OpCode::ScrollRight => "scroll-right".to_string(),
+			OpCode::ScrollLeft => "scroll-left".to_string(),
+			OpCode::Exit => "exit".to_string(),
+			OpCode::LowResolution => "lores".to_string(),
+			OpCode::HighResolution => "hires".to_string(),
+			OpCode::ScrollUp(rows) => format!("scroll-up {}", rows),
+			OpCode::SetIndexLong => "i := long".to_string(),
+			OpCode::SaveRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+			OpCode::LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+			OpCode::SelectPlanes(planes) => format!("plane {}", planes),
+			OpCode::LoadAudioPattern => "audio".to_string(),
+			OpCode::SetPitch(x) => format!("pitch := v{:x}", x),
+			OpCode::SaveFlags(x) => format!("saveflags v{:x}", x),
+			OpCode::LoadFlags(x) => format!("loadflags v{:x}", x),
+			OpCode::Unknown => "# unknown".to_string()
+		}
+	}
+}
+
 #[cfg(test)]
+// continuation lines of the test descriptions are indented with tabs, like the rest of the file
+#[allow(clippy::tabs_in_doc_comments)]
 mod tests {
     use super::*;
+    use system::{Font, MachineCallPolicy, Platform, Quirks, IndexIncrement, SequenceRng,
+                 FONT_ADDRESS};
 
     /** Set some registers for the purposes of testing. */
     fn set_registers_for_test(system: &mut System) {
@@ -477,7 +1022,8 @@ mod tests {
         assert_eq!(0xAE, system.registers[0x3]);
 
         OpCode::from(0x8FE1).execute(&mut system).unwrap();
-        assert_eq!(0x25, system.registers[0xF]);
+        // note - on CHIP-8 logic instructions reset register VF afterwards
+        assert_eq!(0x00, system.registers[0xF]);
         assert_eq!(0x00, system.registers[0xE]);
     }
 
@@ -515,7 +1061,8 @@ mod tests {
         assert_eq!(0xAE, system.registers[0x3]);
 
         OpCode::from(0x8FE3).execute(&mut system).unwrap();
-        assert_eq!(0x25, system.registers[0xF]);
+        // note - on CHIP-8 logic instructions reset register VF afterwards
+        assert_eq!(0x00, system.registers[0xF]);
         assert_eq!(0x00, system.registers[0xE]);
     }
 
@@ -542,7 +1089,7 @@ mod tests {
     }
 
     /** The opcode 0x8XY5 should subtract register VY from register VX
-      * If a borrow occurs, set register VF to 01. */
+      * If a borrow occurs, set register VF to 00, otherwise to 01. */
     #[test]
     fn sub_register_with_borrow_right_subtrahend() {
         let mut system = System::new();
@@ -550,18 +1097,18 @@ mod tests {
 
         OpCode::from(0x8125).execute(&mut system).unwrap();
         assert_eq!(0x15, system.registers[0x1]);
-        assert_eq!(0x00, system.registers[0xF]);
+        assert_eq!(0x01, system.registers[0xF]);
 
         OpCode::from(0x8455).execute(&mut system).unwrap();
         assert_eq!(0x4B, system.registers[0x4]);
-        assert_eq!(0x00, system.registers[0xF]);
+        assert_eq!(0x01, system.registers[0xF]);
 
         OpCode::from(0x6501).execute(&mut system).unwrap();
         OpCode::from(0x8B55).execute(&mut system).unwrap();
         assert_eq!(0xFF, system.registers[0xB]);
         // note - a borrow occurs here because subtrahend > minuend,
-            // therefore register VF should be set to 0x01
-        assert_eq!(0x01, system.registers[0xF]);
+            // therefore register VF should be set to 0x00
+        assert_eq!(0x00, system.registers[0xF]);
     }
 
     /** The opcode 0x8XY6 should store the value stored in register VY right shifted by 1 bit
@@ -584,27 +1131,27 @@ mod tests {
         assert_eq!(0x01, system.registers[0xF]);
     }
 
-    /** The opcode 0x8XY7 should subtract register VX from register VY
-      *     If a borrow occurs, set register VF to 01. */
+    /** The opcode 0x8XY7 should subtract register VX from register VY and store the result in
+      *     register VX. If a borrow occurs, set register VF to 00, otherwise to 01. */
     #[test]
     fn sub_register_with_borrow_left_subtrahend() {
         let mut system = System::new();
         set_registers_for_test(&mut system);
 
         OpCode::from(0x8217).execute(&mut system).unwrap();
-        assert_eq!(0x15, system.registers[0x1]);
-        assert_eq!(0x00, system.registers[0xF]);
+        assert_eq!(0x15, system.registers[0x2]);
+        assert_eq!(0x01, system.registers[0xF]);
 
         OpCode::from(0x8547).execute(&mut system).unwrap();
-        assert_eq!(0x4B, system.registers[0x4]);
-        assert_eq!(0x00, system.registers[0xF]);
+        assert_eq!(0x4B, system.registers[0x5]);
+        assert_eq!(0x01, system.registers[0xF]);
 
         OpCode::from(0x6501).execute(&mut system).unwrap();
         OpCode::from(0x85B7).execute(&mut system).unwrap();
-        assert_eq!(0xFF, system.registers[0xB]);
+        assert_eq!(0xFF, system.registers[0x5]);
         // note - a borrow occurs here because subtrahend > minuend,
-            // therefore register VF should be set to 0x01
-        assert_eq!(0x01, system.registers[0xF]);
+            // therefore register VF should be set to 0x00
+        assert_eq!(0x00, system.registers[0xF]);
     }
 
     /** The opcode 0x8XYE should store the value stored in register VY left shifted by 1 bit
@@ -630,9 +1177,18 @@ mod tests {
     /** The opcode 0xCXNN should generate a random number, mask it with NN and store it in
       *     register VX. */
     #[test]
-    #[ignore]
     fn random_register() {
-        // TODO
+        let mut system = System::new();
+        system.set_random_source(Box::new(SequenceRng::new(vec![0xFF, 0x5A])));
+
+        OpCode::from(0xC30F).execute(&mut system).unwrap();
+        assert_eq!(0x0F, system.registers[0x3]);
+
+        OpCode::from(0xC3F0).execute(&mut system).unwrap();
+        assert_eq!(0x50, system.registers[0x3]);
+
+        OpCode::from(0xC300).execute(&mut system).unwrap();
+        assert_eq!(0x00, system.registers[0x3]);
     }
 
 	/** The opcode 0x1NNN instructs the interpreter to jump to address NNN. */
@@ -774,4 +1330,687 @@ mod tests {
 		OpCode::from(0xF218).execute(&mut system).unwrap();
 		assert_eq!(0x27, system.sound_timer);
 	}
+
+	/** The opcode 0xDXYN should XOR an N byte sprite from address I onto the display at
+	  * 	(VX, VY), setting register VF if any lit pixel is turned off. */
+	#[test]
+	fn draw_sprite() {
+		let mut system = System::new();
+		system.memory[0x300] = 0xC0;
+		system.memory[0x301] = 0x40;
+		system.index = 0x300;
+		OpCode::from(0x6A02).execute(&mut system).unwrap();
+		OpCode::from(0x6B03).execute(&mut system).unwrap();
+
+		OpCode::from(0xDAB2).execute(&mut system).unwrap();
+		assert!(system.display.pixel(2, 3));
+		assert!(system.display.pixel(3, 3));
+		assert!(!system.display.pixel(2, 4));
+		assert!(system.display.pixel(3, 4));
+		assert_eq!(0x00, system.registers[0xF]);
+
+		// drawing the same sprite again erases it and reports a collision
+		OpCode::from(0xDAB2).execute(&mut system).unwrap();
+		assert!(!system.display.pixel(2, 3));
+		assert!(!system.display.pixel(3, 4));
+		assert_eq!(0x01, system.registers[0xF]);
+	}
+
+	/** The opcode 0xDXYN should fail rather than read sprite data past the end of memory. */
+	#[test]
+	fn draw_sprite_out_of_bounds() {
+		let mut system = System::new();
+		system.index = 0xFFE;
+
+		assert!(OpCode::from(0xD01F).execute(&mut system).is_err());
+	}
+
+	/** The opcode 0x00E0 should turn off every pixel on the display. */
+	#[test]
+	fn clear_screen() {
+		let mut system = System::new();
+		system.memory[0x300] = 0xFF;
+		system.index = 0x300;
+		OpCode::from(0xD001).execute(&mut system).unwrap();
+		system.take_display_dirty();
+
+		OpCode::from(0x00E0).execute(&mut system).unwrap();
+		assert!(system.display.pixels().iter().all(|&pixel| pixel == 0));
+		assert!(system.take_display_dirty());
+	}
+
+	/** The opcode 0xANNN should store the address NNN in the index register. */
+	#[test]
+	fn set_index() {
+		let mut system = System::new();
+
+		OpCode::from(0xA2F0).execute(&mut system).unwrap();
+		assert_eq!(0x2F0, system.index);
+	}
+
+	/** The opcode 0xFX1E should add the value stored in register VX to the index register. */
+	#[test]
+	fn add_index() {
+		let mut system = System::new();
+		set_registers_for_test(&mut system);
+		OpCode::from(0xA2F0).execute(&mut system).unwrap();
+
+		OpCode::from(0xF01E).execute(&mut system).unwrap();
+		assert_eq!(0x354, system.index);
+
+		OpCode::from(0xF41E).execute(&mut system).unwrap();
+		assert_eq!(0x453, system.index);
+	}
+
+	/** The opcode 0xFX33 should store the binary-coded decimal form of the value in register VX
+	  * 	at addresses I, I + 1 and I + 2. */
+	#[test]
+	fn store_decimal() {
+		let mut system = System::new();
+		set_registers_for_test(&mut system);
+		OpCode::from(0xA300).execute(&mut system).unwrap();
+
+		OpCode::from(0xF433).execute(&mut system).unwrap();
+		assert_eq!([2, 5, 5], system.memory[0x300..0x303]);
+
+		OpCode::from(0xF033).execute(&mut system).unwrap();
+		assert_eq!([1, 0, 0], system.memory[0x300..0x303]);
+
+		OpCode::from(0xF233).execute(&mut system).unwrap();
+		assert_eq!([0, 1, 8], system.memory[0x300..0x303]);
+		assert_eq!(0x300, system.index);
+	}
+
+	/** The opcode 0xFX55 should store registers V0 to VX in memory starting at address I. */
+	#[test]
+	fn store_registers() {
+		let mut system = System::new();
+		set_registers_for_test(&mut system);
+		OpCode::from(0xA300).execute(&mut system).unwrap();
+
+		OpCode::from(0xF355).execute(&mut system).unwrap();
+		assert_eq!([0x64, 0x27, 0x12, 0xAE, 0x00], system.memory[0x300..0x305]);
+		assert_eq!(0x304, system.index);
+	}
+
+	/** The opcode 0xFX65 should load registers V0 to VX from memory starting at address I. */
+	#[test]
+	fn load_registers() {
+		let mut system = System::new();
+		system.memory[0x300] = 0x11;
+		system.memory[0x301] = 0x22;
+		system.memory[0x302] = 0x33;
+		OpCode::from(0xA300).execute(&mut system).unwrap();
+
+		OpCode::from(0xF165).execute(&mut system).unwrap();
+		assert_eq!(0x11, system.registers[0x0]);
+		assert_eq!(0x22, system.registers[0x1]);
+		assert_eq!(0x00, system.registers[0x2]);
+		assert_eq!(0x302, system.index);
+	}
+
+	/** The opcodes 0xFX33, 0xFX55 and 0xFX65 should fail rather than touch memory past the end
+	  * 	of system memory. */
+	#[test]
+	fn index_out_of_bounds() {
+		let mut system = System::new();
+		OpCode::from(0xAFFE).execute(&mut system).unwrap();
+
+		assert!(OpCode::from(0xF033).execute(&mut system).is_err());
+		assert!(OpCode::from(0xF255).execute(&mut system).is_err());
+		assert!(OpCode::from(0xF265).execute(&mut system).is_err());
+		OpCode::from(0xF155).execute(&mut system).unwrap();
+	}
+
+	/** The opcode 0xFX29 should point the index register at the font sprite for the hex digit
+	  * 	stored in register VX. */
+	#[test]
+	fn set_index_to_sprite() {
+		let mut system = System::new();
+		set_registers_for_test(&mut system);
+
+		OpCode::from(0xF229).execute(&mut system).unwrap();
+		assert_eq!(FONT_ADDRESS + 0x2 * 5, system.index);
+
+		OpCode::from(0xF329).execute(&mut system).unwrap();
+		assert_eq!(FONT_ADDRESS + 0xE * 5, system.index);
+
+		system.set_font(Font::CosmacVip, 0x000).unwrap();
+		OpCode::from(0xF029).execute(&mut system).unwrap();
+		assert_eq!(0x4 * 5, system.index);
+	}
+
+	/** The opcode 0xFX0A should put the CPU into a waiting state rather than block, and the next
+	  * 	key pressed should be stored in register VX. */
+	#[test]
+	fn wait_key_press() {
+		let mut system = System::new();
+
+		OpCode::from(0xF30A).execute(&mut system).unwrap();
+		assert_eq!(CpuState::WaitingForKey { register: 0x3, pressed: None }, system.state);
+
+		system.set_key(0xB, true);
+		assert_eq!(CpuState::Running, system.state);
+		assert_eq!(0xB, system.registers[0x3]);
+	}
+
+	/** The opcode 0x2NNN should fail rather than overflow the stack. */
+	#[test]
+	fn subroutine_jump_overflow() {
+		let mut system = System::new();
+		for _ in 0..system.stack.len() {
+			OpCode::from(0x22AE).execute(&mut system).unwrap();
+		}
+
+		assert_eq!(Err(ErrorKind::StackOverflow), OpCode::from(0x22AE).execute(&mut system));
+	}
+
+	/** The opcode 0x00EE should fail rather than underflow the stack. */
+	#[test]
+	fn subroutine_return_underflow() {
+		let mut system = System::new();
+
+		assert_eq!(Err(ErrorKind::StackUnderflow), OpCode::from(0x00EE).execute(&mut system));
+		assert_eq!(0, system.sp);
+	}
+
+	/** The opcodes 0xEX9E and 0xEXA1 should skip the next instruction depending on whether the
+	  * 	key named by register VX is held down, failing for values that are not keys. */
+	#[test]
+	fn skip_key() {
+		let mut system = System::new();
+		set_registers_for_test(&mut system);
+		OpCode::from(0x6A0C).execute(&mut system).unwrap();
+		system.set_key(0xC, true);
+
+		OpCode::from(0xEA9E).execute(&mut system).unwrap();
+		assert_eq!(0x202, system.pc);
+		OpCode::from(0xEAA1).execute(&mut system).unwrap();
+		assert_eq!(0x202, system.pc);
+
+		assert_eq!(Err(ErrorKind::InvalidKey(0x27)), OpCode::from(0xE19E).execute(&mut system));
+		assert_eq!(Err(ErrorKind::InvalidKey(0x27)), OpCode::from(0xE1A1).execute(&mut system));
+	}
+
+	/** The family (first nibble) of the word each instruction is encoded in, or None for
+	  * 	OpCode::Unknown. */
+	fn family(opcode: OpCode) -> Option<Word> {
+		match opcode {
+			OpCode::ClearScreen | OpCode::SubReturn | OpCode::MachineCall(_) | OpCode::ScrollDown(_)
+				| OpCode::ScrollUp(_)
+				| OpCode::ScrollRight | OpCode::ScrollLeft | OpCode::Exit | OpCode::LowResolution
+				| OpCode::HighResolution => Some(0x0),
+			OpCode::JumpAddress(_) => Some(0x1),
+			OpCode::SubJump(_) => Some(0x2),
+			OpCode::SkipValue(..) => Some(0x3),
+			OpCode::SkipNotValue(..) => Some(0x4),
+			OpCode::SkipRegister(..) | OpCode::SaveRange(..) | OpCode::LoadRange(..) => Some(0x5),
+			OpCode::SetValue(..) => Some(0x6),
+			OpCode::AddValue(..) => Some(0x7),
+			OpCode::SetRegister(..) | OpCode::OrRegister(..) | OpCode::AndRegister(..)
+				| OpCode::XorRegister(..) | OpCode::AddRegister(..) | OpCode::SubRegisterRight(..)
+				| OpCode::RShiftRegister(..) | OpCode::SubRegisterLeft(..)
+				| OpCode::LShiftRegister(..) => Some(0x8),
+			OpCode::SkipNotRegister(..) => Some(0x9),
+			OpCode::SetIndex(_) => Some(0xA),
+			OpCode::JumpAddressOffset(_) => Some(0xB),
+			OpCode::RandomValue(..) => Some(0xC),
+			OpCode::Draw(..) => Some(0xD),
+			OpCode::SkipKeyPressed(_) | OpCode::SkipKeyNotPressed(_) => Some(0xE),
+			OpCode::SetDelayTimer(_) | OpCode::SetRegisterFromTimer(_) | OpCode::SetSoundTimer(_)
+				| OpCode::WaitKeyPress(_) | OpCode::AddIndex(_) | OpCode::SetIndexToSprite(_)
+				| OpCode::SetIndexToLargeSprite(_) | OpCode::SetIndexLong | OpCode::SelectPlanes(_)
+				| OpCode::LoadAudioPattern | OpCode::SetPitch(_) | OpCode::StoreDecimal(_)
+				| OpCode::StoreRegisters(_) | OpCode::LoadRegisters(_) | OpCode::SaveFlags(_)
+				| OpCode::LoadFlags(_) => Some(0xF),
+			OpCode::Unknown => None
+		}
+	}
+
+	/** Every word in 0x0000 to 0x0FFF should decode to exactly one of 00E0, 00EE, the SUPER-CHIP
+	  * 	00CN and 00FB to 00FF, the XO-CHIP 00DN, or 0NNN. */
+	#[test]
+	fn decode_machine_calls() {
+		for word in 0x0000..0x1000 {
+			let expected = match word {
+				0x00E0 => OpCode::ClearScreen,
+				0x00EE => OpCode::SubReturn,
+				0x00C0..=0x00CF => OpCode::ScrollDown((word & 0xF) as Byte),
+				0x00D0..=0x00DF => OpCode::ScrollUp((word & 0xF) as Byte),
+				0x00FB => OpCode::ScrollRight,
+				0x00FC => OpCode::ScrollLeft,
+				0x00FD => OpCode::Exit,
+				0x00FE => OpCode::LowResolution,
+				0x00FF => OpCode::HighResolution,
+				_ => OpCode::MachineCall(word)
+			};
+			assert_eq!(expected, OpCode::from(word), "decoding {:04X}", word);
+		}
+	}
+
+	/** Every one of the 65,536 words should decode to an instruction from the family named by
+	  * 	its first nibble, or to OpCode::Unknown. */
+	#[test]
+	fn decode_all_words() {
+		let mut unknown = 0;
+		for word in 0x0000..0x10000u32 {
+			let word = word as Word;
+			match family(OpCode::from(word)) {
+				Some(family) => assert_eq!(word >> 12, family, "decoding {:04X}", word),
+				None => unknown += 1
+			}
+		}
+
+		// 5XY1 and 5XY4-5XYF, 9XY1-9XYF, 8XY8-8XYD and 8XYF, all but 2 EX-- words, and all but
+		// F000, F002 and 14 FX-- words
+		assert_eq!(13 * 256 + 15 * 256 + 7 * 256 + 254 * 16 + (242 * 16 - 2), unknown);
+	}
+
+	/** Every word that decodes should encode back to itself. */
+	#[test]
+	fn encode_all_words() {
+		for word in 0x0000..0x10000u32 {
+			let word = word as Word;
+			match OpCode::from(word) {
+				OpCode::Unknown => assert_eq!(None, OpCode::Unknown.encode()),
+				opcode => assert_eq!(Some(word), opcode.encode(), "encoding {:04X}", word)
+			}
+		}
+	}
+
+	/** Words at the boundaries between families should decode to the family they start. */
+	#[test]
+	fn decode_family_boundaries() {
+		assert_eq!(OpCode::MachineCall(0xFFF), OpCode::from(0x0FFF));
+		assert_eq!(OpCode::JumpAddress(0x000), OpCode::from(0x1000));
+		assert_eq!(OpCode::SubJump(0x000), OpCode::from(0x2000));
+		assert_eq!(OpCode::SkipRegister(0x0, 0x0), OpCode::from(0x5000));
+		assert_eq!(OpCode::Draw(0x0, 0x0, 0x0), OpCode::from(0xD000));
+		assert_eq!(OpCode::Unknown, OpCode::from(0xE000));
+		assert_eq!(OpCode::SetIndexLong, OpCode::from(0xF000));
+		assert_eq!(OpCode::Unknown, OpCode::from(0xF100));
+		assert_eq!(OpCode::Unknown, OpCode::from(0xFFFF));
+	}
+
+	/** The opcode 0x0NNN should be skipped, rejected or handed to a native routine depending
+	  * 	on the machine call policy. */
+	#[test]
+	fn machine_call() {
+		fn hook(system: &mut System, address: Address) -> Result<(), ErrorKind> {
+			system.index = address;
+			Ok(())
+		}
+
+		let mut system = System::new();
+		OpCode::from(0x0123).execute(&mut system).unwrap();
+		assert_eq!(0x200, system.pc);
+
+		system.set_machine_call_policy(MachineCallPolicy::Error);
+		assert_eq!(Err(ErrorKind::MachineCall(0x123)), OpCode::from(0x0123).execute(&mut system));
+
+		system.set_machine_call_policy(MachineCallPolicy::Hook(hook));
+		OpCode::from(0x0123).execute(&mut system).unwrap();
+		assert_eq!(0x123, system.index);
+	}
+
+	/** With the shift source quirk set to VX, 0x8XY6 and 0x8XYE should shift register VX in place
+	  * 	and ignore register VY. */
+	#[test]
+	fn shift_register_in_place() {
+		let mut system = System::new();
+		system.set_quirks(Quirks::chip48());
+		set_registers_for_test(&mut system);
+
+		OpCode::from(0x8016).execute(&mut system).unwrap();
+		assert_eq!(0x32, system.registers[0x0]);
+		assert_eq!(0x00, system.registers[0xF]);
+
+		OpCode::from(0x831E).execute(&mut system).unwrap();
+		assert_eq!(0x5C, system.registers[0x3]);
+		assert_eq!(0x01, system.registers[0xF]);
+		assert_eq!(0x27, system.registers[0x1]);
+	}
+
+	/** With the jump quirk set, 0xBXNN should jump to address XNN plus the value in register VX. */
+	#[test]
+	fn jump_address_with_register_offset() {
+		let mut system = System::new();
+		system.set_quirks(Quirks::super_chip());
+		set_registers_for_test(&mut system);
+
+		OpCode::from(0xB2AE).execute(&mut system).unwrap();
+		assert_eq!(0x2C0, system.pc);
+	}
+
+	/** With the logic quirk set, 0x8XY1, 0x8XY2 and 0x8XY3 should reset register VF to 0. */
+	#[test]
+	fn logic_resets_flag() {
+		let mut system = System::new();
+		system.set_quirks(Quirks::cosmac_vip());
+
+		for &word in &[0x8011, 0x8012, 0x8013] {
+			set_registers_for_test(&mut system);
+			OpCode::from(word).execute(&mut system).unwrap();
+			assert_eq!(0x00, system.registers[0xF]);
+		}
+	}
+
+	/** The index increment quirk should decide where 0xFX55 and 0xFX65 leave the index register. */
+	#[test]
+	fn transfer_increments_index() {
+		let mut system = System::new();
+		set_registers_for_test(&mut system);
+
+		let mut quirks = Quirks::default();
+		for &(increment, expected) in &[(IndexIncrement::Unchanged, 0x300),
+				(IndexIncrement::ByX, 0x303), (IndexIncrement::ByXPlusOne, 0x304)] {
+			quirks.index_increment = increment;
+			system.set_quirks(quirks);
+
+			system.index = 0x300;
+			OpCode::from(0xF355).execute(&mut system).unwrap();
+			assert_eq!(expected, system.index);
+
+			system.index = 0x300;
+			OpCode::from(0xF365).execute(&mut system).unwrap();
+			assert_eq!(expected, system.index);
+		}
+	}
+
+	/** The sprite edge quirk should decide whether 0xDXYN wraps sprites around the display. */
+	#[test]
+	fn draw_sprite_edges() {
+		let mut system = System::new();
+		system.memory[0x300] = 0xFF;
+		system.index = 0x300;
+		OpCode::from(0x603C).execute(&mut system).unwrap();
+
+		OpCode::from(0xD011).execute(&mut system).unwrap();
+		assert!(system.display.pixel(63, 0));
+		assert!(!system.display.pixel(0, 0));
+
+		system.set_quirks(Quirks::xo_chip());
+		OpCode::from(0xD011).execute(&mut system).unwrap();
+		assert!(!system.display.pixel(63, 0));
+		assert!(system.display.pixel(0, 0));
+	}
+
+	/** The SUPER-CHIP opcodes should only be available on platforms that support them. On
+	  * 	CHIP-8 the ones in the 0x0NNN range should be machine calls instead. */
+	#[test]
+	fn super_chip_requires_platform() {
+		let mut system = System::new();
+		system.set_machine_call_policy(MachineCallPolicy::Error);
+
+		for &word in &[0x00C1, 0x00D1, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF] {
+			assert_eq!(Err(ErrorKind::MachineCall(word)), OpCode::from(word).execute(&mut system));
+		}
+		assert_eq!(Err(ErrorKind::UnknownOpcode), OpCode::from(0xF030).execute(&mut system));
+
+		system.set_machine_call_policy(MachineCallPolicy::Ignore);
+		OpCode::from(0x00FD).execute(&mut system).unwrap();
+		OpCode::from(0x00FF).execute(&mut system).unwrap();
+		assert_eq!(CpuState::Running, system.cpu_state());
+		assert!(!system.display.is_high_resolution());
+	}
+
+	/** The opcodes 0x00FF and 0x00FE should switch the display between 128x64 and 64x32. */
+	#[test]
+	fn switch_resolution() {
+		let mut system = System::new();
+		system.set_platform(Platform::SuperChip);
+
+		OpCode::from(0x00FF).execute(&mut system).unwrap();
+		assert!(system.display.is_high_resolution());
+		assert_eq!(128, system.display.width());
+		assert_eq!(64, system.display.height());
+
+		OpCode::from(0x00FE).execute(&mut system).unwrap();
+		assert!(!system.display.is_high_resolution());
+		assert_eq!(64, system.display.width());
+		assert_eq!(32, system.display.height());
+	}
+
+	/** The opcodes 0x00CN, 0x00FB and 0x00FC should scroll the display down N pixels, right 4
+	  * 	pixels and left 4 pixels. */
+	#[test]
+	fn scroll_display() {
+		let mut system = System::new();
+		system.set_platform(Platform::SuperChip);
+		OpCode::from(0x00FF).execute(&mut system).unwrap();
+		system.memory[0x300] = 0x80;
+		system.index = 0x300;
+		OpCode::from(0xD001).execute(&mut system).unwrap();
+
+		OpCode::from(0x00C5).execute(&mut system).unwrap();
+		assert!(system.display.pixel(0, 5));
+		assert!(!system.display.pixel(0, 0));
+
+		OpCode::from(0x00FB).execute(&mut system).unwrap();
+		assert!(system.display.pixel(4, 5));
+		assert!(!system.display.pixel(0, 5));
+
+		OpCode::from(0x00FC).execute(&mut system).unwrap();
+		OpCode::from(0x00FC).execute(&mut system).unwrap();
+		assert!(system.display.pixels().iter().all(|&pixel| pixel == 0));
+	}
+
+	/** The opcode 0xDXY0 should draw a 16x16 sprite on SUPER-CHIP, and nothing on CHIP-8. */
+	#[test]
+	fn draw_large_sprite() {
+		let mut system = System::new();
+		for address in 0x300..0x320 {
+			system.memory[address] = 0xFF;
+		}
+		system.index = 0x300;
+
+		OpCode::from(0xD000).execute(&mut system).unwrap();
+		assert!(system.display.pixels().iter().all(|&pixel| pixel == 0));
+
+		system.set_platform(Platform::SuperChip);
+		OpCode::from(0x00FF).execute(&mut system).unwrap();
+		OpCode::from(0xD000).execute(&mut system).unwrap();
+		assert_eq!(256, system.display.pixels().iter().filter(|&&pixel| pixel != 0).count());
+		assert!(system.display.pixel(15, 15));
+		assert!(!system.display.pixel(16, 0));
+		assert_eq!(0x00, system.registers[0xF]);
+
+		OpCode::from(0xD000).execute(&mut system).unwrap();
+		assert_eq!(0x01, system.registers[0xF]);
+	}
+
+	/** The opcode 0xFX30 should point the index register at the large font sprite for the hex
+	  * 	digit stored in register VX. */
+	#[test]
+	fn set_index_to_large_sprite() {
+		let mut system = System::new();
+		system.set_platform(Platform::SuperChip);
+		OpCode::from(0x6307).execute(&mut system).unwrap();
+
+		OpCode::from(0xF330).execute(&mut system).unwrap();
+		assert_eq!(FONT_ADDRESS + 16 * 5 + 7 * 10, system.index);
+		let start = system.index as usize;
+		assert_eq!([0xFF, 0xFF, 0x03], system.memory[start..start + 3]);
+	}
+
+	/** The opcode 0x00FD should stop the interpreter for good. */
+	#[test]
+	fn exit() {
+		let mut system = System::new();
+		system.set_platform(Platform::SuperChip);
+
+		OpCode::from(0x00FD).execute(&mut system).unwrap();
+		assert!(system.is_halted());
+
+		system.tick_timers();
+		assert!(system.is_halted());
+	}
+
+	/** The XO-CHIP opcodes should only be available on the XO-CHIP platform. */
+	#[test]
+	fn xo_chip_requires_platform() {
+		let mut system = System::new();
+		system.set_platform(Platform::SuperChip);
+
+		for &word in &[0x00D1, 0xF000, 0x5012, 0x5013, 0xF201, 0xF002, 0xF03A] {
+			assert_eq!(Err(ErrorKind::UnknownOpcode), OpCode::from(word).execute(&mut system));
+		}
+	}
+
+	/** The opcode 0xF000 0xNNNN should load the 16 bit address NNNN into the index register. */
+	#[test]
+	fn set_index_long() {
+		let mut system = System::new();
+		system.set_platform(Platform::XoChip);
+		system.memory[0x200] = 0xE0;
+		system.memory[0x201] = 0x00;
+
+		OpCode::from(0xF000).execute(&mut system).unwrap();
+		assert_eq!(0xE000, system.index);
+		assert_eq!(0x202, system.pc);
+	}
+
+	/** Skip opcodes on XO-CHIP should step over all 4 bytes of a following 0xF000 0xNNNN. */
+	#[test]
+	fn skip_long_instruction() {
+		let mut system = System::new();
+		system.set_platform(Platform::XoChip);
+		system.memory[0x200] = 0xF0;
+		system.memory[0x201] = 0x00;
+
+		OpCode::from(0x3000).execute(&mut system).unwrap();
+		assert_eq!(0x204, system.pc);
+
+		system.pc = 0x200;
+		system.set_platform(Platform::SuperChip);
+		OpCode::from(0x3000).execute(&mut system).unwrap();
+		assert_eq!(0x202, system.pc);
+	}
+
+	/** The opcodes 0x5XY2 and 0x5XY3 should save and load registers VX to VY at address I, in
+	  * 	either order, leaving the index register alone. */
+	#[test]
+	fn save_and_load_range() {
+		let mut system = System::new();
+		system.set_platform(Platform::XoChip);
+		set_registers_for_test(&mut system);
+		system.index = 0x300;
+
+		OpCode::from(0x5132).execute(&mut system).unwrap();
+		assert_eq!([0x27, 0x12, 0xAE], system.memory[0x300..0x303]);
+		assert_eq!(0x300, system.index);
+
+		OpCode::from(0x5312).execute(&mut system).unwrap();
+		assert_eq!([0xAE, 0x12, 0x27], system.memory[0x300..0x303]);
+
+		OpCode::from(0x5673).execute(&mut system).unwrap();
+		assert_eq!(0xAE, system.registers[0x6]);
+		assert_eq!(0x12, system.registers[0x7]);
+		assert_eq!(0x300, system.index);
+	}
+
+	/** The opcode 0xFN01 should select the planes drawn to, and 0xDXYN should then read a sprite
+	  * 	for each selected plane. */
+	#[test]
+	fn select_planes() {
+		let mut system = System::new();
+		system.set_platform(Platform::XoChip);
+		system.memory[0x300] = 0x80;
+		system.memory[0x301] = 0xC0;
+		system.index = 0x300;
+
+		OpCode::from(0xF301).execute(&mut system).unwrap();
+		OpCode::from(0xD001).execute(&mut system).unwrap();
+		assert_eq!(0x3, system.display.colour(0, 0));
+		assert_eq!(0x2, system.display.colour(1, 0));
+
+		OpCode::from(0xF201).execute(&mut system).unwrap();
+		OpCode::from(0x00E0).execute(&mut system).unwrap();
+		assert_eq!(0x1, system.display.colour(0, 0));
+		assert_eq!(0x0, system.display.colour(1, 0));
+	}
+
+	/** The opcode 0x00DN should scroll the display up N pixels. */
+	#[test]
+	fn scroll_up() {
+		let mut system = System::new();
+		system.set_platform(Platform::XoChip);
+		system.memory[0x300] = 0x80;
+		system.index = 0x300;
+		OpCode::from(0x6105).execute(&mut system).unwrap();
+		OpCode::from(0xD011).execute(&mut system).unwrap();
+
+		OpCode::from(0x00D3).execute(&mut system).unwrap();
+		assert!(system.display.pixel(0, 2));
+		assert!(!system.display.pixel(0, 5));
+	}
+
+	/** The opcode 0xF002 should load the 16 byte audio pattern at address I, and 0xFX3A should
+	  * 	set the pitch register from VX. */
+	#[test]
+	fn audio_pattern_and_pitch() {
+		let mut system = System::new();
+		system.set_platform(Platform::XoChip);
+		for offset in 0..16 {
+			system.memory[0x300 + offset] = offset as Byte;
+		}
+		system.index = 0x300;
+		OpCode::from(0x6570).execute(&mut system).unwrap();
+
+		OpCode::from(0xF002).execute(&mut system).unwrap();
+		OpCode::from(0xF53A).execute(&mut system).unwrap();
+		assert_eq!(Some(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]), system.audio.pattern());
+		assert_eq!(0x70, system.audio.pitch());
+
+		system.index = 0xFFF8;
+		assert_eq!(Err(ErrorKind::MemoryOutOfBounds(0x10000)), OpCode::from(0xF002).execute(&mut system));
+	}
+
+	/** The opcodes 0xFX75 and 0xFX85 should save and restore V0 to VX in the user flags, of which
+	  * 	SUPER-CHIP has 8 and XO-CHIP 16. */
+	#[test]
+	fn save_and_load_flags() {
+		let mut system = System::new();
+		system.set_platform(Platform::SuperChip);
+		set_registers_for_test(&mut system);
+
+		OpCode::from(0xF275).execute(&mut system).unwrap();
+		assert_eq!([0x64, 0x27, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00], system.flags());
+		OpCode::from(0x6000).execute(&mut system).unwrap();
+		OpCode::from(0x6100).execute(&mut system).unwrap();
+		OpCode::from(0xF085).execute(&mut system).unwrap();
+		assert_eq!(0x64, system.registers[0x0]);
+		assert_eq!(0x00, system.registers[0x1]);
+
+		assert_eq!(Err(ErrorKind::FlagOutOfRange(0x8)), OpCode::from(0xF875).execute(&mut system));
+		system.set_platform(Platform::XoChip);
+		OpCode::from(0xFF75).execute(&mut system).unwrap();
+		system.set_platform(Platform::Chip8);
+		assert_eq!(Err(ErrorKind::UnknownOpcode), OpCode::from(0xF085).execute(&mut system));
+	}
+
+	/** Instructions should be rendered in both Cowgod and Octo syntax. */
+	#[test]
+	fn mnemonics() {
+		let cases = [
+			(0x00E0, "CLS", "clear"),
+			(0x1ABC, "JP #ABC", "jump 0xABC"),
+			(0x2204, "CALL #204", ":call 0x204"),
+			(0x3A0F, "SE VA, #0F", "if va != 0x0F then"),
+			(0x8126, "SHR V1, V2", "v1 >>= v2"),
+			(0x8127, "SUBN V1, V2", "v1 =- v2"),
+			(0xC3F0, "RND V3, #F0", "v3 := random 0xF0"),
+			(0xD125, "DRW V1, V2, #5", "sprite v1 v2 5"),
+			(0xE19E, "SKP V1", "if v1 -key then"),
+			(0xF233, "LD B, V2", "bcd v2"),
+			(0xF265, "LD V2, [I]", "load v2"),
+			(0x5132, "SAVE V1, V3", "save v1 - v3"),
+			(0xF000, "LD I, LONG", "i := long")
+		];
+
+		for &(word, cowgod, octo) in cases.iter() {
+			assert_eq!(cowgod, OpCode::from(word).to_string());
+			assert_eq!(octo, OpCode::from(word).to_octo());
+		}
+	}
 }
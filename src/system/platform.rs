@@ -0,0 +1,67 @@
+use std::str::FromStr;
+
+use system::Quirks;
+use system::flags::{NUM_FLAGS, NUM_XO_FLAGS};
+
+/// The CHIP-8 variant a system emulates, which decides the instructions available to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    /// The original CHIP-8 instruction set.
+    Chip8,
+    /// SUPER-CHIP 1.1, adding a 128x64 mode, scrolling, 16x16 sprites and a large font.
+    SuperChip,
+    /// XO-CHIP, extending SUPER-CHIP with 64 KiB of memory, a second drawing plane and more.
+    XoChip
+}
+
+impl Platform {
+    /// The quirks of the interpreter this platform is usually run on.
+    pub fn quirks(&self) -> Quirks {
+        match *self {
+            Platform::Chip8        => Quirks::cosmac_vip(),
+            Platform::SuperChip    => Quirks::super_chip(),
+            Platform::XoChip       => Quirks::xo_chip()
+        }
+    }
+
+    /// Number of bytes of memory available on this platform.
+    pub fn memory_size(&self) -> usize {
+        match *self {
+            Platform::XoChip   => 0x10000,
+            _                   => 0x1000
+        }
+    }
+
+    /// Number of user flag registers FX75 and FX85 can save and restore.
+    pub fn flag_count(&self) -> usize {
+        match *self {
+            Platform::Chip8        => 0,
+            Platform::SuperChip    => NUM_FLAGS,
+            Platform::XoChip       => NUM_XO_FLAGS
+        }
+    }
+
+    /// Whether the SUPER-CHIP instructions are available.
+    pub fn has_super_chip(&self) -> bool {
+        *self != Platform::Chip8
+    }
+
+    /// Whether the XO-CHIP instructions are available.
+    pub fn has_xo_chip(&self) -> bool {
+        *self == Platform::XoChip
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    /// Parse a platform name as given on the command line: "chip8", "schip" or "xochip".
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8"                  => Ok(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip"                => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform \"{}\"", name))
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use system::EdgeBehaviour;
+
+/// Which register 8XY6 and 8XYE shift.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShiftSource {
+    /// Shift VY and store the result in VX.
+    Vy,
+    /// Shift VX in place, ignoring VY.
+    Vx
+}
+
+/// How FX55 and FX65 leave the index register once they are done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left unchanged.
+    Unchanged,
+    /// I is increased by X.
+    ByX,
+    /// I is left pointing just past the last register stored or loaded.
+    ByXPlusOne
+}
+
+/// The behaviours that differ between CHIP-8 platforms, consulted as each instruction runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// Which register 8XY6 and 8XYE shift.
+    pub shift_source: ShiftSource,
+    /// Whether BNNN jumps to NNN + VX, where X is the first digit of NNN, instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// How FX55 and FX65 change the index register.
+    pub index_increment: IndexIncrement,
+    /// Whether 8XY1, 8XY2 and 8XY3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// Whether sprites wrap around or are clipped at the edges of the display.
+    pub sprite_edges: EdgeBehaviour,
+    /// Whether DXYN waits for the start of the next 60 Hz frame before continuing.
+    pub display_wait: bool
+}
+
+impl Quirks {
+    /// The original interpreter on the RCA COSMAC VIP.
+    pub fn cosmac_vip() -> Self {
+        Quirks {
+            shift_source: ShiftSource::Vy,
+            jump_uses_vx: false,
+            index_increment: IndexIncrement::ByXPlusOne,
+            logic_resets_vf: true,
+            sprite_edges: EdgeBehaviour::Clip,
+            display_wait: true
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub fn chip48() -> Self {
+        Quirks {
+            shift_source: ShiftSource::Vx,
+            jump_uses_vx: true,
+            index_increment: IndexIncrement::ByX,
+            logic_resets_vf: false,
+            sprite_edges: EdgeBehaviour::Clip,
+            display_wait: false
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP-48 calculators.
+    pub fn super_chip() -> Self {
+        Quirks {
+            shift_source: ShiftSource::Vx,
+            jump_uses_vx: true,
+            index_increment: IndexIncrement::Unchanged,
+            logic_resets_vf: false,
+            sprite_edges: EdgeBehaviour::Clip,
+            display_wait: false
+        }
+    }
+
+    /// XO-CHIP, as implemented by Octo.
+    pub fn xo_chip() -> Self {
+        Quirks {
+            shift_source: ShiftSource::Vy,
+            jump_uses_vx: false,
+            index_increment: IndexIncrement::ByXPlusOne,
+            logic_resets_vf: false,
+            sprite_edges: EdgeBehaviour::Wrap,
+            display_wait: false
+        }
+    }
+}
+
+impl Default for Quirks {
+    /// The CHIP-8 preset, which is also what a new `System` starts with.
+    fn default() -> Self {
+        Quirks::cosmac_vip()
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+
+use system::{System, StateError, Byte};
+
+/// Default number of frames between full snapshots.
+pub const KEYFRAME_INTERVAL: usize = 60;
+
+/// Zero runs shorter than this are kept inside a literal rather than ending it.
+const MIN_ZERO_RUN: usize = 4;
+
+/// A full snapshot, followed by the frames after it stored as differences from it.
+#[derive(Clone, Debug)]
+struct Group {
+    keyframe: Vec<Byte>,
+    deltas: Vec<Vec<Byte>>
+}
+
+impl Group {
+    fn len(&self) -> usize {
+        1 + self.deltas.len()
+    }
+
+    fn size(&self) -> usize {
+        self.keyframe.len() + self.deltas.iter().map(|delta| delta.len()).sum::<usize>()
+    }
+
+    /// The save state of the frame at `offset` within this group.
+    fn state(&self, offset: usize) -> Vec<Byte> {
+        match offset {
+            0 => self.keyframe.clone(),
+            _ => apply_delta(&self.keyframe, &self.deltas[offset - 1])
+        }
+    }
+}
+
+/// A bounded history of per-frame snapshots of a System, for running time backwards.
+///
+/// Every frame is kept as a save state. Most are stored as the run-length encoded difference
+/// from the last keyframe, which is small since little changes from one frame to the next.
+/// Once the history outgrows its memory budget, the oldest keyframe and the frames after it
+/// are dropped.
+#[derive(Clone, Debug)]
+pub struct Rewind {
+    groups: VecDeque<Group>,
+    budget: usize,
+    keyframe_interval: usize,
+    used: usize
+}
+
+impl Rewind {
+    /// Creates a new, empty Rewind that keeps about `budget` bytes of history. The budget can
+    /// be overrun by at most one keyframe and the frames after it.
+    pub fn new(budget: usize) -> Self {
+        Rewind {
+            groups: VecDeque::new(),
+            budget,
+            keyframe_interval: KEYFRAME_INTERVAL,
+            used: 0
+        }
+    }
+
+    /// Choose how many frames there are between full snapshots. Longer intervals save memory
+    /// while shorter ones let older history be dropped sooner.
+    pub fn set_keyframe_interval(&mut self, frames: usize) {
+        assert!(frames > 0, "keyframe interval must be at least one frame!");
+        self.keyframe_interval = frames;
+    }
+
+    /// Number of frames in the history.
+    pub fn len(&self) -> usize {
+        self.groups.iter().map(Group::len).sum()
+    }
+
+    /// Whether the history is empty.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Number of bytes the history takes up.
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    /// Forget the whole history.
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.used = 0;
+    }
+
+    /// Record the state of `system` at the end of a frame.
+    pub fn push(&mut self, system: &System) -> Result<(), StateError> {
+        let mut state = Vec::new();
+        system.save_state(&mut state)?;
+
+        let delta = match self.groups.back() {
+            Some(group) if group.len() < self.keyframe_interval
+                && group.keyframe.len() == state.len() => {
+                Some(make_delta(&group.keyframe, &state))
+            },
+            _ => None
+        };
+
+        match delta {
+            Some(delta) => {
+                self.used += delta.len();
+                self.groups.back_mut().unwrap().deltas.push(delta);
+            },
+            None => {
+                self.used += state.len();
+                self.groups.push_back(Group { keyframe: state, deltas: Vec::new() });
+            }
+        }
+
+        while self.used > self.budget && self.groups.len() > 1 {
+            let group = self.groups.pop_front().unwrap();
+            self.used -= group.size();
+        }
+
+        Ok(())
+    }
+
+    /// Step `system` back by up to `frames` frames, dropping them from the history, and return
+    /// how many frames it went back. The oldest frame recorded is never dropped, so rewinding
+    /// stops there.
+    ///
+    /// Frontends can call this with 1 every frame while the rewind key is held.
+    pub fn rewind(&mut self, frames: usize, system: &mut System) -> Result<usize, StateError> {
+        let mut rewound = 0;
+        while rewound < frames && self.len() > 1 {
+            let delta = self.groups.back_mut().unwrap().deltas.pop();
+            self.used -= match delta {
+                Some(delta) => delta.len(),
+                None => self.groups.pop_back().unwrap().keyframe.len()
+            };
+            rewound += 1;
+        }
+
+        if let Some(group) = self.groups.back() {
+            system.load_state(&mut &group.state(group.len() - 1)[..])?;
+        }
+
+        Ok(rewound)
+    }
+}
+
+/// Encode `state` as its difference from `keyframe`, which must be the same length: runs of
+/// unchanged bytes, each followed by a literal of bytes XORed with the keyframe.
+fn make_delta(keyframe: &[Byte], state: &[Byte]) -> Vec<Byte> {
+    let changes: Vec<Byte> = keyframe.iter().zip(state).map(|(old, new)| old ^ new).collect();
+    let mut delta = Vec::new();
+
+    let mut position = 0;
+    while position < changes.len() {
+        let start = position;
+        while position < changes.len() && changes[position] == 0 && position - start < 0xFFFF {
+            position += 1;
+        }
+        let run = position - start;
+
+        let literal = position;
+        while position < changes.len() && position - literal < 0xFFFF {
+            let zeros = changes[position..].iter().take(MIN_ZERO_RUN)
+                .take_while(|&&change| change == 0).count();
+            if zeros == MIN_ZERO_RUN || position + zeros == changes.len() {
+                break;
+            }
+            position += zeros.max(1);
+        }
+        position = position.min(literal + 0xFFFF);
+
+        delta.extend_from_slice(&[(run >> 8) as Byte, run as Byte]);
+        let length = position - literal;
+        delta.extend_from_slice(&[(length >> 8) as Byte, length as Byte]);
+        delta.extend_from_slice(&changes[literal..position]);
+    }
+
+    delta
+}
+
+/// Rebuild a state from its keyframe and the delta made by `make_delta`.
+fn apply_delta(keyframe: &[Byte], delta: &[Byte]) -> Vec<Byte> {
+    let mut state = keyframe.to_vec();
+
+    let mut position = 0;
+    let mut read = 0;
+    while read < delta.len() {
+        let run = (delta[read] as usize) << 8 | delta[read + 1] as usize;
+        let length = (delta[read + 2] as usize) << 8 | delta[read + 3] as usize;
+        read += 4;
+        position += run;
+
+        for (byte, change) in state[position..position + length].iter_mut()
+                .zip(&delta[read..read + length]) {
+            *byte ^= change;
+        }
+        position += length;
+        read += length;
+    }
+
+    state
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use system::Platform;
+
+    /** A program that keeps drawing at random places, so every frame differs. */
+    fn busy_system() -> System {
+        let mut system = System::new();
+        system.set_platform(Platform::SuperChip);
+        system.seed_random(9);
+        system.load_rom(&[
+            0xC0, 0x3F,     // V0 = random
+            0xC1, 0x1F,     // V1 = random
+            0xF0, 0x29,     // I = sprite for V0
+            0xD0, 0x15,     // draw
+            0x12, 0x00      // jump back
+        ][..]).unwrap();
+        system
+    }
+
+    fn save(system: &System) -> Vec<Byte> {
+        let mut state = Vec::new();
+        system.save_state(&mut state).unwrap();
+        state
+    }
+
+    /** Deltas should rebuild the state they were made from exactly. */
+    #[test]
+    fn delta_round_trip() {
+        let keyframe: Vec<Byte> = (0..20000).map(|index| (index * 7) as Byte).collect();
+        let mut state = keyframe.clone();
+        state[0] ^= 0xFF;
+        state[3] ^= 0x01;
+        for byte in &mut state[5000..19000] {
+            *byte = !*byte;
+        }
+        state[19999] = 0x00;
+
+        let delta = make_delta(&keyframe, &state);
+        assert_eq!(state, apply_delta(&keyframe, &delta));
+        assert_eq!(4, make_delta(&keyframe, &keyframe).len());
+    }
+
+    /** Rewinding N frames and replaying the same input should arrive at an identical System. */
+    #[test]
+    fn rewind_and_replay() {
+        let mut system = busy_system();
+        let mut rewind = Rewind::new(1 << 20);
+        rewind.set_keyframe_interval(8);
+        let mut states = Vec::new();
+        let play = |system: &mut System, frame: usize| {
+            system.set_key(frame % 16, frame % 4 < 2);
+            system.run_frame(7).unwrap();
+        };
+
+        for frame in 0..30 {
+            play(&mut system, frame);
+            rewind.push(&system).unwrap();
+            states.push(save(&system));
+        }
+
+        assert_eq!(10, rewind.rewind(10, &mut system).unwrap());
+        assert_eq!(states[19], save(&system));
+        assert_eq!(20, rewind.len());
+
+        for frame in 20..30 {
+            play(&mut system, frame);
+            rewind.push(&system).unwrap();
+        }
+        assert_eq!(states[29], save(&system));
+
+        assert_eq!(29, rewind.rewind(100, &mut system).unwrap());
+        assert_eq!(states[0], save(&system));
+    }
+
+    /** The history should stay within its budget, give or take one keyframe group. */
+    #[test]
+    fn memory_budget() {
+        let mut system = busy_system();
+        let state_size = save(&system).len();
+        let budget = state_size * 4;
+        let mut rewind = Rewind::new(budget);
+        rewind.set_keyframe_interval(10);
+
+        for _ in 0..200 {
+            system.run_frame(7).unwrap();
+            rewind.push(&system).unwrap();
+            assert!(rewind.memory_used() <= budget + state_size * 10);
+        }
+        assert!(rewind.len() < 200);
+        assert!(rewind.len() > 10);
+    }
+}
@@ -0,0 +1,295 @@
+extern crate rand;
+
+use std::fmt;
+
+use system::{Byte, Word};
+
+/// Where CXNN gets its random numbers from.
+pub trait RandomSource: fmt::Debug {
+    /// The next random byte.
+    fn next_byte(&mut self) -> Byte;
+
+    /// A copy of this source in its current state, which will go on to produce the same bytes.
+    fn box_clone(&self) -> Box<dyn RandomSource>;
+
+    /// The internal state of this source, as stored in save states.
+    fn state(&self) -> Vec<Byte>;
+
+    /// Restore a state taken from a source of the same kind, returning false if `state` is not
+    /// one this source understands.
+    fn set_state(&mut self, state: &[Byte]) -> bool;
+}
+
+impl Clone for Box<dyn RandomSource> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Used in place of the all zero state a seed mixes to, which xorshift would never leave.
+const ZERO_STATE_STANDIN: u64 = 0x2545_F491_4F6C_DD1D;
+
+/// A fast xorshift64* generator, which produces the same bytes every time for the same seed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XorShiftRng {
+    state: u64
+}
+
+impl XorShiftRng {
+    /// Creates a new XorShiftRng from a seed. Any seed is fine, including 0.
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves an all zero state, so mix the seed into a non-zero one
+        let state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        XorShiftRng { state: if state == 0 { ZERO_STATE_STANDIN } else { state } }
+    }
+
+    /// Creates a new XorShiftRng seeded from the operating system.
+    pub fn from_entropy() -> Self {
+        XorShiftRng::new(rand::random())
+    }
+}
+
+impl RandomSource for XorShiftRng {
+    fn next_byte(&mut self) -> Byte {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as Byte
+    }
+
+    fn box_clone(&self) -> Box<dyn RandomSource> {
+        Box::new(*self)
+    }
+
+    fn state(&self) -> Vec<Byte> {
+        (0..8).rev().map(|byte| (self.state >> (byte * 8)) as Byte).collect()
+    }
+
+    fn set_state(&mut self, state: &[Byte]) -> bool {
+        let value = state.iter().fold(0, |value, &byte| value << 8 | byte as u64);
+        if state.len() != 8 || value == 0 {
+            return false;
+        }
+
+        self.state = value;
+        true
+    }
+}
+
+/// Plays back a fixed sequence of bytes over and over, for tests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequenceRng {
+    bytes: Vec<Byte>,
+    position: usize
+}
+
+impl SequenceRng {
+    /// Creates a new SequenceRng, which must be given at least one byte.
+    pub fn new(bytes: Vec<Byte>) -> Self {
+        assert!(!bytes.is_empty(), "a random sequence needs at least one byte");
+        SequenceRng { bytes, position: 0 }
+    }
+}
+
+impl RandomSource for SequenceRng {
+    fn next_byte(&mut self) -> Byte {
+        let byte = self.bytes[self.position];
+        self.position = (self.position + 1) % self.bytes.len();
+        byte
+    }
+
+    fn box_clone(&self) -> Box<dyn RandomSource> {
+        Box::new(self.clone())
+    }
+
+    /// The position in the sequence, followed by the sequence itself.
+    fn state(&self) -> Vec<Byte> {
+        let mut state: Vec<Byte> = (0..4).rev().map(|byte| (self.position >> (byte * 8)) as Byte)
+            .collect();
+        state.extend_from_slice(&self.bytes);
+        state
+    }
+
+    fn set_state(&mut self, state: &[Byte]) -> bool {
+        if state.len() < 5 {
+            return false;
+        }
+        let position = state[..4].iter().fold(0, |value, &byte| value << 8 | byte as usize);
+        if position >= state.len() - 4 {
+            return false;
+        }
+
+        self.position = position;
+        self.bytes = state[4..].to_vec();
+        true
+    }
+}
+
+/// The generator from the COSMAC VIP interpreter, which steps a 16 bit seed and adds a byte of
+/// the interpreter's own code, read from the page at 0x0100, to its high byte.
+///
+/// The interpreter is copyrighted by RCA and is not included here. It is the 512 byte program
+/// the VIP loads into 0x0000 to 0x01FF before running a CHIP-8 game, listed in the VIP
+/// manual and found as a RAM dump with most VIP emulators; `from_dump` takes either that or
+/// just its second page.
+#[derive(Clone)]
+pub struct VipRng {
+    page: [Byte; 256],
+    seed: Word
+}
+
+impl VipRng {
+    /// Creates a new VipRng, given the 256 bytes of interpreter code at 0x0100 to 0x01FF and the
+    /// initial value of the seed register.
+    pub fn new(page: &[Byte], seed: Word) -> Self {
+        assert_eq!(256, page.len(), "the interpreter page is 256 bytes");
+
+        let mut table = [0; 256];
+        table.copy_from_slice(page);
+        VipRng { page: table, seed }
+    }
+
+    /// Creates a new VipRng from a dump of the whole interpreter, or of the page at 0x0100 alone,
+    /// or returns None if the dump is neither 512 nor 256 bytes long.
+    pub fn from_dump(dump: &[Byte], seed: Word) -> Option<Self> {
+        match dump.len() {
+            512 => Some(VipRng::new(&dump[256..], seed)),
+            256 => Some(VipRng::new(dump, seed)),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Debug for VipRng {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VipRng {{ seed: {:#06X} }}", self.seed)
+    }
+}
+
+impl RandomSource for VipRng {
+    fn next_byte(&mut self) -> Byte {
+        self.seed = self.seed.wrapping_add(1);
+        let low = self.seed as Byte;
+        let high = ((self.seed >> 8) as Byte).wrapping_add(self.page[low as usize]);
+        self.seed = (high as Word) << 8 | low as Word;
+        high
+    }
+
+    fn box_clone(&self) -> Box<dyn RandomSource> {
+        Box::new(self.clone())
+    }
+
+    /// The seed register, followed by the interpreter page.
+    fn state(&self) -> Vec<Byte> {
+        let mut state = vec![(self.seed >> 8) as Byte, self.seed as Byte];
+        state.extend_from_slice(&self.page);
+        state
+    }
+
+    fn set_state(&mut self, state: &[Byte]) -> bool {
+        if state.len() != 2 + self.page.len() {
+            return false;
+        }
+
+        self.seed = (state[0] as Word) << 8 | state[1] as Word;
+        self.page.copy_from_slice(&state[2..]);
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** The same seed should always produce the same bytes, and different seeds different ones. */
+    #[test]
+    fn xorshift_deterministic() {
+        let bytes = |seed| {
+            let mut rng = XorShiftRng::new(seed);
+            (0..32).map(|_| rng.next_byte()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(bytes(0), bytes(0));
+        assert_eq!(bytes(42), bytes(42));
+        assert!(bytes(0) != bytes(42));
+        assert!(bytes(0).iter().any(|&byte| byte != bytes(0)[0]));
+    }
+
+    /** The seed that mixes to an all zero state should still produce bytes that change. */
+    #[test]
+    fn xorshift_zero_state() {
+        let mut rng = XorShiftRng::new(0x9E37_79B9_7F4A_7C15);
+        let bytes: Vec<Byte> = (0..32).map(|_| rng.next_byte()).collect();
+        assert!(bytes.iter().any(|&byte| byte != bytes[0]));
+        assert!(!rng.set_state(&[0; 8]));
+    }
+
+    /** A clone should carry on from where the original was. */
+    #[test]
+    fn box_clone() {
+        let mut rng: Box<dyn RandomSource> = Box::new(XorShiftRng::new(7));
+        rng.next_byte();
+
+        let mut copy = rng.clone();
+        for _ in 0..16 {
+            assert_eq!(rng.next_byte(), copy.next_byte());
+        }
+    }
+
+    /** A sequence should be played back in order, starting again at the end. */
+    #[test]
+    fn sequence() {
+        let mut rng = SequenceRng::new(vec![0x12, 0x34, 0x56]);
+        let bytes: Vec<Byte> = (0..5).map(|_| rng.next_byte()).collect();
+        assert_eq!(vec![0x12, 0x34, 0x56, 0x12, 0x34], bytes);
+    }
+
+    /** Restoring a saved state should pick up the sequence from where it was saved. */
+    #[test]
+    fn restore_state() {
+        let mut rng = XorShiftRng::new(99);
+        rng.next_byte();
+        let state = rng.state();
+        let expected: Vec<Byte> = (0..8).map(|_| rng.next_byte()).collect();
+
+        let mut restored = XorShiftRng::new(0);
+        assert!(restored.set_state(&state));
+        assert_eq!(expected, (0..8).map(|_| restored.next_byte()).collect::<Vec<_>>());
+
+        let mut sequence = SequenceRng::new(vec![1, 2, 3]);
+        sequence.next_byte();
+        let mut restored = SequenceRng::new(vec![0]);
+        assert!(restored.set_state(&sequence.state()));
+        assert_eq!(2, restored.next_byte());
+        assert!(!restored.set_state(&state[..3]));
+    }
+
+    /** The VIP generator should add the interpreter byte indexed by the low byte of the stepped
+      *     seed to its high byte. */
+    #[test]
+    fn vip() {
+        let page: Vec<Byte> = (0..256).map(|index| index as Byte).collect();
+        let mut rng = VipRng::new(&page, 0x10FE);
+
+        // 0x10FF: 0x10 + 0xFF wraps to 0x0F
+        assert_eq!(0x0F, rng.next_byte());
+        // 0x0FFF steps to 0x1000: 0x10 + 0x00
+        assert_eq!(0x10, rng.next_byte());
+        // 0x1001: 0x10 + 0x01
+        assert_eq!(0x11, rng.next_byte());
+    }
+
+    /** A dump of the whole interpreter should have its second page used, and a dump of any
+      *     other size should be turned down. */
+    #[test]
+    fn vip_from_dump() {
+        let mut dump = vec![0xAA; 256];
+        dump.extend((0..256).map(|index| index as Byte));
+        let mut whole = VipRng::from_dump(&dump, 0x10FE).unwrap();
+        let mut page = VipRng::from_dump(&dump[256..], 0x10FE).unwrap();
+        assert_eq!(0x0F, whole.next_byte());
+        assert_eq!(0x0F, page.next_byte());
+        assert!(VipRng::from_dump(&dump[1..], 0).is_none());
+    }
+}
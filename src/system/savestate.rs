@@ -0,0 +1,561 @@
+use std::io::prelude::*;
+
+use system::{System, CpuState, KeyWaitMode, Platform, Quirks, ShiftSource, IndexIncrement};
+use system::{EdgeBehaviour, Font, TimerClock, StateError, Byte, Word, RegisterIndex, rom_hash};
+use system::{NUM_REGISTERS, STACK_SIZE, NUM_KEYS, NUM_XO_FLAGS, PATTERN_SIZE, LARGE_GLYPHS};
+use system::display::{DISPLAY_WIDTH, DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT};
+
+/// The bytes every save state starts with.
+pub const STATE_MAGIC: [Byte; 4] = *b"CH8S";
+
+/// Version of the save state format written by this interpreter.
+pub const STATE_VERSION: u16 = 2;
+
+/// Stands in for "no key" in the FX0A wait state.
+const NO_KEY: Byte = 0xFF;
+
+/// The chunks a save state cannot do without. All others fall back to the loading system's
+/// own settings when they are missing.
+const REQUIRED_CHUNKS: [&[Byte; 4]; 3] = [b"CPU ", b"MEM ", b"DISP"];
+
+// Enumerations are stored as their position in these tables, so entries may only be appended.
+const PLATFORMS: [Platform; 3] = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+const KEY_WAIT_MODES: [KeyWaitMode; 2] = [KeyWaitMode::Press, KeyWaitMode::Release];
+const FONTS: [Font; 4] = [Font::Standard, Font::CosmacVip, Font::Dream6800, Font::Eti660];
+const SHIFT_SOURCES: [ShiftSource; 2] = [ShiftSource::Vy, ShiftSource::Vx];
+const INDEX_INCREMENTS: [IndexIncrement; 3] =
+    [IndexIncrement::Unchanged, IndexIncrement::ByX, IndexIncrement::ByXPlusOne];
+const EDGE_BEHAVIOURS: [EdgeBehaviour; 2] = [EdgeBehaviour::Wrap, EdgeBehaviour::Clip];
+
+impl System {
+    /// Write the complete state of this system to `writer`, to be restored with `load_state`.
+    ///
+    /// The format is the magic bytes "CH8S" and a version number, followed by a series of
+    /// chunks, each made of a four byte tag, a 32 bit length and that many bytes of payload. All
+    /// numbers are big-endian. New versions of the format only ever add chunks, or append fields
+    /// to the end of existing ones, so newer states stay readable. Chunks from older versions
+    /// are brought up to date by `migrate` before they are read.
+    ///
+    /// The machine call policy and flag storage belong to the frontend and are not saved.
+    pub fn save_state<W: Write>(&self, writer: &mut W) -> Result<(), StateError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&STATE_MAGIC);
+        push_word(&mut out, STATE_VERSION);
+
+        let mut cpu = Vec::new();
+        push_word(&mut cpu, self.pc);
+        push_word(&mut cpu, self.index);
+        cpu.push(self.sp);
+        cpu.push(self.delay_timer);
+        cpu.push(self.sound_timer);
+        cpu.extend_from_slice(&self.registers);
+        cpu.extend_from_slice(&match self.state {
+            CpuState::Running                           => [0, 0, NO_KEY],
+            CpuState::WaitingForKey { register, pressed } =>
+                [1, register as Byte, pressed.unwrap_or(NO_KEY)],
+            CpuState::WaitingForFrame                   => [2, 0, NO_KEY],
+            CpuState::Halted                            => [3, 0, NO_KEY]
+        });
+        cpu.push(encode(&KEY_WAIT_MODES, self.key_wait_mode));
+        push_word(&mut cpu, self.stack.len() as Word);
+        for &slot in self.stack.iter() {
+            push_word(&mut cpu, slot);
+        }
+        write_chunk(&mut out, b"CPU ", &cpu);
+
+        let mut memory = vec![encode(&PLATFORMS, self.platform)];
+        push_u32(&mut memory, self.memory.len() as u32);
+        memory.extend_from_slice(&self.memory);
+        write_chunk(&mut out, b"MEM ", &memory);
+
+        let mut font = vec![encode(&FONTS, self.font)];
+        push_word(&mut font, self.font_address);
+        push_word(&mut font, self.load_address);
+        write_chunk(&mut out, b"FONT", &font);
+
+        let keys: Vec<Byte> = self.keys.iter().map(|&key| key as Byte).collect();
+        write_chunk(&mut out, b"KEYS", &keys);
+
+        let mut display = vec![self.display.is_high_resolution() as Byte, self.display.planes];
+        display.extend_from_slice(&self.display.pixels);
+        write_chunk(&mut out, b"DISP", &display);
+
+        write_chunk(&mut out, b"QRKS", &[
+            encode(&SHIFT_SOURCES, self.quirks.shift_source),
+            self.quirks.jump_uses_vx as Byte,
+            encode(&INDEX_INCREMENTS, self.quirks.index_increment),
+            self.quirks.logic_resets_vf as Byte,
+            encode(&EDGE_BEHAVIOURS, self.quirks.sprite_edges),
+            self.quirks.display_wait as Byte
+        ]);
+
+        let mut timer = vec![self.timer_clock.is_some() as Byte];
+        if let Some(clock) = self.timer_clock {
+            push_u32(&mut timer, clock.cycles_per_second());
+            push_u64(&mut timer, clock.remainder);
+        }
+        write_chunk(&mut out, b"TIME", &timer);
+
+        let mut audio = vec![self.audio.pattern.is_some() as Byte];
+        audio.extend_from_slice(&self.audio.pattern.unwrap_or([0; PATTERN_SIZE]));
+        audio.push(self.audio.pitch);
+        write_chunk(&mut out, b"AUDI", &audio);
+
+        let mut flags = Vec::new();
+        push_u64(&mut flags, self.rom_hash);
+        flags.extend_from_slice(&self.flags);
+        write_chunk(&mut out, b"FLAG", &flags);
+
+        write_chunk(&mut out, b"RNG ", &self.rng.state());
+
+        writer.write_all(&out)?;
+        Ok(())
+    }
+
+    /// Restore a state written by `save_state`. If the state cannot be read the system is left
+    /// untouched.
+    ///
+    /// The random number state can only be restored into the same kind of `RandomSource` it
+    /// was saved from.
+    pub fn load_state<R: Read>(&mut self, reader: &mut R) -> Result<(), StateError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        if bytes.len() < STATE_MAGIC.len() + 2 || bytes[..STATE_MAGIC.len()] != STATE_MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let mut file = Reader::new(&bytes[STATE_MAGIC.len()..], "header");
+        let version = file.word()?;
+
+        let mut system = self.clone();
+        let mut found = Vec::new();
+        while !file.is_empty() {
+            file.part = "chunk header";
+            let tag = file.take(4)?;
+            let length = file.u32()? as usize;
+            file.part = "chunk length";
+            let payload = migrate(version, tag, file.take(length)?)?;
+            let payload = &payload[..];
+
+            match tag {
+                b"CPU " => system.read_cpu(Reader::new(payload, "CPU"))?,
+                b"MEM " => system.read_memory(Reader::new(payload, "memory"))?,
+                b"FONT" => system.read_font(Reader::new(payload, "font"))?,
+                b"KEYS" => system.read_keys(Reader::new(payload, "keys"))?,
+                b"DISP" => system.read_display(Reader::new(payload, "display"))?,
+                b"QRKS" => system.read_quirks(Reader::new(payload, "quirks"))?,
+                b"TIME" => system.read_timer(Reader::new(payload, "timer clock"))?,
+                b"AUDI" => system.read_audio(Reader::new(payload, "audio"))?,
+                b"FLAG" => system.read_flags(Reader::new(payload, "user flags"))?,
+                b"RNG " => if !system.rng.set_state(payload) {
+                    return Err(StateError::Corrupt("random number state"));
+                },
+                // added by a later version
+                _ => continue
+            }
+            found.push(tag);
+        }
+
+        if REQUIRED_CHUNKS.iter().any(|&required| !found.contains(&&required[..])) {
+            return Err(StateError::Corrupt("missing chunk"));
+        }
+        if system.check_memory(system.font_address as usize,
+                               system.font.glyphs().len() + LARGE_GLYPHS.len()).is_err() {
+            return Err(StateError::Corrupt("font"));
+        }
+
+        *self = system;
+        Ok(())
+    }
+
+    /// A 64 bit FNV-1a hash of everything `save_state` writes, for telling at a glance whether
+    /// two runs ended up in the same state.
+    pub fn state_hash(&self) -> u64 {
+        let mut state = Vec::new();
+        self.save_state(&mut state).expect("writing to memory cannot fail");
+        rom_hash(&state)
+    }
+
+    fn read_cpu(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        self.pc = chunk.word()?;
+        self.index = chunk.word()?;
+        self.sp = chunk.byte()?;
+        self.delay_timer = chunk.byte()?;
+        self.sound_timer = chunk.byte()?;
+        self.registers.copy_from_slice(chunk.take(NUM_REGISTERS)?);
+
+        let state = chunk.byte()?;
+        let register = chunk.byte()? as RegisterIndex;
+        let pressed = match chunk.byte()? {
+            NO_KEY => None,
+            key if (key as usize) < NUM_KEYS => Some(key),
+            _ => return Err(chunk.corrupt())
+        };
+        self.state = match state {
+            0 => CpuState::Running,
+            1 if register < NUM_REGISTERS => CpuState::WaitingForKey { register, pressed },
+            2 => CpuState::WaitingForFrame,
+            3 => CpuState::Halted,
+            _ => return Err(chunk.corrupt())
+        };
+        self.key_wait_mode = chunk.decode(&KEY_WAIT_MODES)?;
+
+        let depth = chunk.word()? as usize;
+        if depth != STACK_SIZE || self.sp as usize > STACK_SIZE {
+            return Err(chunk.corrupt());
+        }
+        for slot in self.stack.iter_mut() {
+            *slot = chunk.word()?;
+        }
+
+        Ok(())
+    }
+
+    fn read_memory(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        let platform = chunk.decode(&PLATFORMS)?;
+        let length = chunk.u32()? as usize;
+        if length != platform.memory_size() {
+            return Err(chunk.corrupt());
+        }
+        let memory = chunk.take(length)?;
+
+        self.platform = platform;
+        self.memory = memory.to_vec();
+        Ok(())
+    }
+
+    fn read_font(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        self.font = chunk.decode(&FONTS)?;
+        self.font_address = chunk.word()?;
+        self.load_address = chunk.word()?;
+        Ok(())
+    }
+
+    fn read_keys(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        for key in self.keys.iter_mut() {
+            *key = chunk.flag()?;
+        }
+
+        Ok(())
+    }
+
+    fn read_display(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        let (width, height) = if chunk.flag()? {
+            (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)
+        } else {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        };
+        let planes = chunk.byte()?;
+        let pixels = chunk.take(width * height)?;
+        if planes > 0x3 || pixels.iter().any(|&pixel| pixel > 0x3) {
+            return Err(chunk.corrupt());
+        }
+
+        self.display.width = width;
+        self.display.height = height;
+        self.display.planes = planes;
+        self.display.pixels = pixels.to_vec();
+        self.display.dirty = true;
+        Ok(())
+    }
+
+    fn read_quirks(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        self.quirks = Quirks {
+            shift_source: chunk.decode(&SHIFT_SOURCES)?,
+            jump_uses_vx: chunk.flag()?,
+            index_increment: chunk.decode(&INDEX_INCREMENTS)?,
+            logic_resets_vf: chunk.flag()?,
+            sprite_edges: chunk.decode(&EDGE_BEHAVIOURS)?,
+            display_wait: chunk.flag()?
+        };
+        Ok(())
+    }
+
+    fn read_timer(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        self.timer_clock = if chunk.flag()? {
+            let cycles_per_second = chunk.u32()?;
+            let remainder = chunk.u64()?;
+            if cycles_per_second == 0 || remainder >= cycles_per_second as u64 {
+                return Err(chunk.corrupt());
+            }
+
+            let mut clock = TimerClock::new(cycles_per_second);
+            clock.remainder = remainder;
+            Some(clock)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    fn read_audio(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        let has_pattern = chunk.flag()?;
+        let pattern = chunk.take(PATTERN_SIZE)?;
+        self.audio.pattern = None;
+        if has_pattern {
+            self.audio.set_pattern(pattern);
+        }
+        self.audio.pitch = chunk.byte()?;
+        Ok(())
+    }
+
+    fn read_flags(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        self.rom_hash = chunk.u64()?;
+        self.flags.copy_from_slice(chunk.take(NUM_XO_FLAGS)?);
+        Ok(())
+    }
+}
+
+/// Rewrite the payload of a chunk saved by an older version of the format into the layout the
+/// current version reads.
+fn migrate(version: u16, tag: &[Byte], payload: &[Byte]) -> Result<Vec<Byte>, StateError> {
+    let mut payload = payload.to_vec();
+    match version {
+        0 => return Err(StateError::UnsupportedVersion(version)),
+        // memory filled the rest of its chunk, so give it the length later fields need
+        1 if tag == b"MEM " && !payload.is_empty() => {
+            let mut length = Vec::new();
+            push_u32(&mut length, payload.len() as u32 - 1);
+            payload.splice(1..1, length);
+        },
+        _ => ()
+    }
+    Ok(payload)
+}
+
+/// Append a chunk, made of its tag, the length of its payload and the payload, to `out`.
+fn write_chunk(out: &mut Vec<Byte>, tag: &[Byte; 4], payload: &[Byte]) {
+    out.extend_from_slice(tag);
+    push_u32(out, payload.len() as u32);
+    out.extend_from_slice(payload);
+}
+
+fn push_word(out: &mut Vec<Byte>, value: Word) {
+    out.extend_from_slice(&[(value >> 8) as Byte, value as Byte]);
+}
+
+fn push_u32(out: &mut Vec<Byte>, value: u32) {
+    push_word(out, (value >> 16) as Word);
+    push_word(out, value as Word);
+}
+
+fn push_u64(out: &mut Vec<Byte>, value: u64) {
+    push_u32(out, (value >> 32) as u32);
+    push_u32(out, value as u32);
+}
+
+/// The position of `value` in `values`, which is how enumerations are stored.
+fn encode<T: PartialEq>(values: &[T], value: T) -> Byte {
+    values.iter().position(|candidate| *candidate == value).unwrap() as Byte
+}
+
+/// Reads the fields of a save state in order, naming the part being read when it runs short.
+struct Reader<'a> {
+    bytes: &'a [Byte],
+    part: &'static str
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [Byte], part: &'static str) -> Self {
+        Reader { bytes, part }
+    }
+
+    fn corrupt(&self) -> StateError {
+        StateError::Corrupt(self.part)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [Byte], StateError> {
+        if length > self.bytes.len() {
+            return Err(self.corrupt());
+        }
+
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<Byte, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn flag(&mut self) -> Result<bool, StateError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.corrupt())
+        }
+    }
+
+    fn word(&mut self) -> Result<Word, StateError> {
+        let bytes = self.take(2)?;
+        Ok((bytes[0] as Word) << 8 | bytes[1] as Word)
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        Ok((self.word()? as u32) << 16 | self.word()? as u32)
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        Ok((self.u32()? as u64) << 32 | self.u32()? as u64)
+    }
+
+    /// Read an enumeration stored as its position in `values`.
+    fn decode<T: Copy>(&mut self, values: &[T]) -> Result<T, StateError> {
+        let index = self.byte()? as usize;
+        values.get(index).cloned().ok_or_else(|| self.corrupt())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use system::{Platform, SequenceRng};
+
+    /** A system part way through a program, using most of what a save state has to cover. */
+    fn busy_system() -> System {
+        let mut system = System::new();
+        system.set_platform(Platform::XoChip);
+        system.seed_random(5);
+        system.set_cycle_timing(Some(1000));
+        system.load_rom(&[
+            0x00, 0xFF,     // high resolution
+            0x60, 0x1E,     // V0 = 30
+            0xF0, 0x29,     // I = sprite for V0
+            0xD0, 0x05,     // draw
+            0xC1, 0xFF,     // V1 = random
+            0xF0, 0x15,     // delay = V0
+            0x23, 0x00,     // call 0x300
+        ][..]).unwrap();
+        system.memory[0x300] = 0xF2;    // V2 = key
+        system.memory[0x301] = 0x0A;
+        system.set_key(0x4, true);
+        system.run_cycles(8).unwrap();
+        system
+    }
+
+    fn save(system: &System) -> Vec<Byte> {
+        let mut state = Vec::new();
+        system.save_state(&mut state).unwrap();
+        state
+    }
+
+    /** Loading a saved state into a fresh system should recreate the original exactly, so that
+      *     both carry on the same way. */
+    #[test]
+    fn round_trip() {
+        let mut original = busy_system();
+        let state = save(&original);
+        assert_eq!(&STATE_MAGIC, &state[..4]);
+
+        let mut restored = System::new();
+        restored.load_state(&mut &state[..]).unwrap();
+        assert_eq!(state, save(&restored));
+        assert!(restored.is_waiting_for_key());
+        assert!(restored.display().is_high_resolution());
+
+        for system in [&mut original, &mut restored].iter_mut() {
+            system.set_key(0x7, true);
+            system.run_cycles(50).unwrap_or(50);
+        }
+        assert_eq!(save(&original), save(&restored));
+    }
+
+    /** The state hash should match between identical systems and change as soon as one runs. */
+    #[test]
+    fn state_hash() {
+        let mut system = busy_system();
+        let copy = system.clone();
+        assert_eq!(copy.state_hash(), system.state_hash());
+
+        system.tick_timers();
+        assert!(copy.state_hash() != system.state_hash());
+    }
+
+    /** Data that is not a save state, or is cut short, should be rejected without touching the
+      *     system. */
+    #[test]
+    fn load_invalid() {
+        let mut system = System::new();
+        let before = save(&system);
+
+        match system.load_state(&mut &b"not a state"[..]) {
+            Err(StateError::NotAState) => (),
+            result => panic!("unexpected result {:?}", result)
+        }
+
+        let state = save(&busy_system());
+        match system.load_state(&mut &state[..state.len() - 3]) {
+            Err(StateError::Corrupt(_)) => (),
+            result => panic!("unexpected result {:?}", result)
+        }
+        assert_eq!(before, save(&system));
+    }
+
+    /** Chunks and fields added by later versions should be skipped, and chunks missing from
+      *     earlier versions should leave the system's own settings in place. */
+    #[test]
+    fn load_other_versions() {
+        let mut state = save(&busy_system());
+        state[5] = STATE_VERSION as Byte + 1;
+        write_chunk(&mut state, b"NEW!", &[1, 2, 3]);
+
+        let mut system = System::new();
+        system.load_state(&mut &state[..]).unwrap();
+        assert_eq!(Platform::XoChip, system.platform());
+
+        let mut state = STATE_MAGIC.to_vec();
+        push_word(&mut state, STATE_VERSION);
+        let full = save(&busy_system());
+        let mut rest = &full[6..];
+        while !rest.is_empty() {
+            let length = rest[4..8].iter().fold(0, |value, &byte| value << 8 | byte as usize);
+            if &rest[..4] != b"RNG " {
+                state.extend_from_slice(&rest[..8 + length]);
+            }
+            rest = &rest[8 + length..];
+        }
+        let mut system = System::new();
+        system.set_random_source(Box::new(SequenceRng::new(vec![0x42])));
+        system.load_state(&mut &state[..]).unwrap();
+        assert_eq!(0x42, system.rng.next_byte());
+    }
+
+    /** A state from version 1, whose memory chunk had no length, should load as if it had been
+      *     saved by this version. */
+    #[test]
+    fn load_version_1() {
+        let system = busy_system();
+        let current = save(&system);
+
+        let mut state = STATE_MAGIC.to_vec();
+        push_word(&mut state, 1);
+        let mut rest = &current[6..];
+        while !rest.is_empty() {
+            let length = rest[4..8].iter().fold(0, |value, &byte| value << 8 | byte as usize);
+            let (tag, payload) = (&rest[..4], &rest[8..8 + length]);
+            if tag == b"MEM " {
+                let mut memory = vec![payload[0]];
+                memory.extend_from_slice(&payload[5..]);
+                write_chunk(&mut state, b"MEM ", &memory);
+            } else {
+                state.extend_from_slice(&rest[..8 + length]);
+            }
+            rest = &rest[8 + length..];
+        }
+
+        let mut restored = System::new();
+        restored.load_state(&mut &state[..]).unwrap();
+        assert_eq!(current, save(&restored));
+
+        state[5] = 0;
+        match restored.load_state(&mut &state[..]) {
+            Err(StateError::UnsupportedVersion(0)) => (),
+            result => panic!("unexpected result {:?}", result)
+        }
+    }
+}
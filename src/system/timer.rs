@@ -0,0 +1,83 @@
+/// Rate at which the delay and sound timers count down.
+pub const TIMER_FREQUENCY: u32 = 60;
+
+/// Derives 60 Hz timer ticks from a count of executed instructions, for a given CPU speed.
+///
+/// Only whole numbers are used, so the same sequence of cycles always yields the same
+/// sequence of ticks, and no fractional tick is lost between calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerClock {
+    cycles_per_second: u32,
+    pub(super) remainder: u64
+}
+
+impl TimerClock {
+    /// Creates a new TimerClock for a CPU executing `cycles_per_second` instructions.
+    pub fn new(cycles_per_second: u32) -> Self {
+        assert!(cycles_per_second > 0, "CPU speed must be at least one cycle per second!");
+
+        TimerClock {
+            cycles_per_second,
+            remainder: 0
+        }
+    }
+
+    /// The CPU speed this clock was created for.
+    pub fn cycles_per_second(&self) -> u32 {
+        self.cycles_per_second
+    }
+
+    /// Account for `cycles` executed instructions, returning the number of timer ticks that
+    /// have elapsed in that time.
+    pub fn advance(&mut self, cycles: u32) -> u32 {
+        let total = self.remainder + cycles as u64 * TIMER_FREQUENCY as u64;
+        self.remainder = total % self.cycles_per_second as u64;
+        (total / self.cycles_per_second as u64) as u32
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** A CPU running at a multiple of 60 Hz should tick evenly. */
+    #[test]
+    fn advance_even() {
+        let mut clock = TimerClock::new(600);
+
+        assert_eq!(0, clock.advance(9));
+        assert_eq!(1, clock.advance(1));
+        assert_eq!(6, clock.advance(60));
+    }
+
+    /** Any CPU speed should produce exactly 60 ticks per second of cycles, regardless of how
+      *     the cycles are split up. */
+    #[test]
+    fn advance_uneven() {
+        for &speed in &[1, 59, 61, 500, 700, 1000, 3579] {
+            let mut stepped = TimerClock::new(speed);
+            let mut ticks = 0;
+            for _ in 0..speed {
+                ticks += stepped.advance(1);
+            }
+            assert_eq!(TIMER_FREQUENCY, ticks);
+
+            let mut batched = TimerClock::new(speed);
+            assert_eq!(TIMER_FREQUENCY, batched.advance(speed / 3) + batched.advance(speed - speed / 3));
+        }
+    }
+
+    /** Two clocks fed the same cycles should tick at the same instructions. */
+    #[test]
+    fn advance_deterministic() {
+        let mut first = TimerClock::new(700);
+        let mut second = TimerClock::new(700);
+
+        let first_ticks: Vec<u32> = (0..2000).map(|_| first.advance(1)).collect();
+        let second_ticks: Vec<u32> = (0..2000).map(|_| second.advance(1)).collect();
+        assert_eq!(first_ticks, second_ticks);
+        assert_eq!(1, first_ticks[11]);
+        assert_eq!(0, first_ticks[12]);
+    }
+}
@@ -0,0 +1,140 @@
+//! Self-checking test ROMs, in the spirit of the community CHIP-8 test suites, run against the
+//! interpreter under each quirk preset.
+//!
+//! The ROMs are assembled from the sources in `tests/conformance`. Most of them draw a tick or
+//! a cross for every check they make, while the quirks ROM draws a digit for each behaviour it
+//! finds. Each is run until it reaches its final `JP halt` and the framebuffer it leaves is
+//! compared with a golden hash. A mismatch prints the framebuffer, so the failing checks can be
+//! read straight off it.
+
+extern crate chip8_interpreter;
+
+use std::path::Path;
+use chip8_interpreter::asm;
+use chip8_interpreter::system::{rom_hash, Display, OpCode, Quirks, System};
+
+const INSTRUCTIONS_PER_FRAME: usize = 15;
+const FRAME_LIMIT: usize = 1200;
+
+/// A quirk preset, by name.
+type Preset = (&'static str, fn() -> Quirks);
+
+/// The quirk presets every ROM is run under, in the order golden hashes are given.
+const PRESETS: [Preset; 4] = [
+    ("cosmac_vip", Quirks::cosmac_vip),
+    ("chip48", Quirks::chip48),
+    ("super_chip", Quirks::super_chip),
+    ("xo_chip", Quirks::xo_chip)
+];
+
+/// A key pressed or released at the start of a frame: the frame number, the key and whether it
+/// is down.
+type KeyEvent = (usize, usize, bool);
+
+/// Assemble the named ROM and run it until it jumps to itself.
+fn run(name: &str, quirks: Quirks, keys: &[KeyEvent]) -> System {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("conformance")
+        .join(format!("{}.asm", name));
+    let program = asm::assemble_file(&path).unwrap_or_else(|error| panic!("{}", error));
+
+    let mut system = System::new();
+    system.load_rom(&program.rom[..]).unwrap();
+    system.set_quirks(quirks);
+
+    for frame in 0..FRAME_LIMIT {
+        for &(time, key, pressed) in keys {
+            if time == frame {
+                system.set_key(key, pressed);
+            }
+        }
+
+        for _ in 0..INSTRUCTIONS_PER_FRAME {
+            let result = system.step();
+            if let Some(error) = result.error {
+                panic!("{} stopped at frame {}: {}", name, frame, error);
+            }
+            if result.opcode == OpCode::JumpAddress(result.address) {
+                return system;
+            }
+        }
+        system.tick_timers();
+    }
+
+    panic!("{} did not finish within {} frames", name, FRAME_LIMIT);
+}
+
+/// The display as text, one line per row.
+fn render(display: &Display) -> String {
+    let mut text = String::new();
+    for y in 0..display.height() {
+        for x in 0..display.width() {
+            text.push(if display.pixel(x, y) { '#' } else { '.' });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Run the named ROM under every preset and compare its framebuffer with the golden hashes.
+fn check(name: &str, golden: [u64; 4], keys: &[KeyEvent]) {
+    let mut failures = String::new();
+    for (&(preset, quirks), &expected) in PRESETS.iter().zip(golden.iter()) {
+        let system = run(name, quirks(), keys);
+        let hash = rom_hash(system.display().pixels());
+        if hash != expected {
+            failures += &format!("{} under {}: framebuffer hash {:#018x}, expected {:#018x}\n{}\n",
+                                 name, preset, hash, expected, render(system.display()));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures);
+}
+
+/** Arithmetic results and the VF flag after 8XY4 to 8XYE and 7XNN. */
+#[test]
+fn flags() {
+    let hash = 0x6ec2_0ff9_9ab4_e8cd;
+    check("flags", [hash; 4], &[]);
+}
+
+/** The quirks each preset turns on, read off as digits. */
+#[test]
+fn quirks() {
+    check("quirks", [
+        0x0c35_402f_32fa_3a2f,
+        0xceaf_d999_bbe3_33cd,
+        0x47a3_bb8f_c509_d11d,
+        0x1fcb_1c44_14ad_dbeb
+    ], &[]);
+}
+
+/** FX33 on values with one, two and three digits. */
+#[test]
+fn bcd() {
+    let hash = 0xb192_11ef_1c2c_c3d1;
+    check("bcd", [hash; 4], &[]);
+}
+
+/** EX9E, EXA1 and FX0A, with key A pressed for FX0A and key B held for a while after. */
+#[test]
+fn keys() {
+    let hash = 0x1d07_f2c3_47a3_89a2;
+    let script = [(30, 0xA, true), (32, 0xA, false), (60, 0xB, true), (90, 0xB, false)];
+    check("keys", [hash; 4], &script);
+}
+
+/** The delay timer counting down at 60 Hz and stopping at zero. */
+#[test]
+fn timers() {
+    let hash = 0xa16c_fc82_eaa9_b72a;
+    check("timers", [hash; 4], &[]);
+}
+
+/** Skips, jumps, calls, the index register, drawing and CXNN. */
+#[test]
+fn opcodes() {
+    let hash = 0x3841_9e2d_a847_22dd;
+    check("opcodes", [hash; 4], &[]);
+}
@@ -0,0 +1,87 @@
+; FX33 on a range of values, with a tick for each that stores the right hundreds, tens and
+; units digits, followed by the digits of 137 drawn with the built in font. I is set again
+; before each load, so the outcome is the same under every quirk.
+
+            CLS
+            CALL home
+
+            LD V6, 0
+            LD V7, 0
+            LD V8, 0
+            LD V9, 0
+            CALL decimal
+
+            LD V6, 9
+            LD V7, 0
+            LD V8, 0
+            LD V9, 9
+            CALL decimal
+
+            LD V6, 10
+            LD V7, 0
+            LD V8, 1
+            LD V9, 0
+            CALL decimal
+
+            LD V6, 99
+            LD V7, 0
+            LD V8, 9
+            LD V9, 9
+            CALL decimal
+
+            LD V6, 100
+            LD V7, 1
+            LD V8, 0
+            LD V9, 0
+            CALL decimal
+
+            LD V6, 137
+            LD V7, 1
+            LD V8, 3
+            LD V9, 7
+            CALL decimal
+
+            LD V6, 255
+            LD V7, 2
+            LD V8, 5
+            LD V9, 5
+            CALL decimal
+
+; FX33 leaves I where it was.
+            LD V6, 42
+            LD I, digits
+            LD B, V6
+            LD V0, [I]
+            LD V1, 0
+            LD V2, 0
+            LD V3, 0
+            CALL expect
+
+            LD V6, 137
+            LD I, digits
+            LD B, V6
+            LD I, digits
+            LD V2, [I]
+            CALL show
+            LD V0, V1
+            CALL show
+            LD V0, V2
+            CALL show
+
+            JP halt
+
+; Store V6 as decimal digits and check them against the hundreds, tens and units in V7, V8
+; and V9.
+decimal:    LD I, digits
+            LD B, V6
+            LD I, digits
+            LD V2, [I]
+            SE V1, V8
+            LD V0, #FF
+            LD V1, V7
+            LD V3, V9
+            JP expect
+
+digits:     DB 0, 0, 0
+
+            INCLUDE "common.asm"
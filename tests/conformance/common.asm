@@ -0,0 +1,45 @@
+; Routines shared by the conformance ROMs, included at the end of each of them.
+;
+; Results are drawn left to right in a grid of 6x6 pixel cells, ten to a row, starting from
+; the top left corner. VC and VD hold the position of the next cell, and VF and I are changed
+; by every routine here.
+
+; Move the cursor to the first cell.
+home:       LD VC, 1
+            LD VD, 1
+            RET
+
+; Draw a tick in the next cell if V0 = V1 and V2 = V3, or a cross if not.
+expect:     LD I, tick
+            SE V0, V1
+            LD I, cross
+            SE V2, V3
+            LD I, cross
+            JP advance
+
+; Draw the hex digit in V0 in the next cell.
+show:       LD F, V0
+
+; Draw the 5 row sprite at I in the next cell, then move the cursor on.
+advance:    DRW VC, VD, 5
+            ADD VC, 6
+            SE VC, 61
+            RET
+            LD VC, 1
+            ADD VD, 6
+            RET
+
+; The end of every ROM, which the test runner looks out for.
+halt:       JP halt
+
+tick:       DB 0b00001000
+            DB 0b00010000
+            DB 0b10100000
+            DB 0b01000000
+            DB 0b00000000
+
+cross:      DB 0b10001000
+            DB 0b01010000
+            DB 0b00100000
+            DB 0b01010000
+            DB 0b10001000
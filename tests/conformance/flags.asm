@@ -0,0 +1,177 @@
+; The arithmetic instructions and the VF flag they leave behind, with a tick for each case
+; that gives the right result and flag. Shifts use the same register for X and Y and logic
+; instructions do not look at VF, so the outcome is the same under every quirk.
+
+            CLS
+            CALL home
+
+; 8XY4 without a carry, with a carry and wrapping to exactly zero
+            LD V0, #12
+            LD V4, #34
+            ADD V0, V4
+            LD V2, VF
+            LD V1, #46
+            LD V3, 0
+            CALL expect
+
+            LD V0, #FF
+            LD V4, #02
+            ADD V0, V4
+            LD V2, VF
+            LD V1, #01
+            LD V3, 1
+            CALL expect
+
+            LD V0, #80
+            LD V4, #80
+            ADD V0, V4
+            LD V2, VF
+            LD V1, #00
+            LD V3, 1
+            CALL expect
+
+; 8XY5 sets VF when there is no borrow, including when both sides are equal
+            LD V0, #34
+            LD V4, #12
+            SUB V0, V4
+            LD V2, VF
+            LD V1, #22
+            LD V3, 1
+            CALL expect
+
+            LD V0, #12
+            LD V4, #34
+            SUB V0, V4
+            LD V2, VF
+            LD V1, #DE
+            LD V3, 0
+            CALL expect
+
+            LD V0, #20
+            LD V4, #20
+            SUB V0, V4
+            LD V2, VF
+            LD V1, #00
+            LD V3, 1
+            CALL expect
+
+; 8XY7 subtracts VX from VY, storing the result in VX
+            LD V0, #12
+            LD V4, #34
+            SUBN V0, V4
+            LD V2, VF
+            LD V1, #22
+            LD V3, 1
+            CALL expect
+
+            LD V0, #34
+            LD V4, #12
+            SUBN V0, V4
+            LD V2, VF
+            LD V1, #DE
+            LD V3, 0
+            CALL expect
+
+; 8XY6 and 8XYE shift the bit that falls off into VF
+            LD V0, #05
+            SHR V0, V0
+            LD V2, VF
+            LD V1, #02
+            LD V3, 1
+            CALL expect
+
+            LD V0, #04
+            SHR V0, V0
+            LD V2, VF
+            LD V1, #02
+            LD V3, 0
+            CALL expect
+
+            LD V0, #81
+            SHL V0, V0
+            LD V2, VF
+            LD V1, #02
+            LD V3, 1
+            CALL expect
+
+            LD V0, #41
+            SHL V0, V0
+            LD V2, VF
+            LD V1, #82
+            LD V3, 0
+            CALL expect
+
+; With VF as the destination the flag wins over the result
+            LD VF, #FF
+            LD V4, #02
+            ADD VF, V4
+            LD V0, VF
+            LD V1, 1
+            LD V2, 0
+            LD V3, 0
+            CALL expect
+
+            LD VF, #10
+            LD V4, #01
+            SUB VF, V4
+            LD V0, VF
+            LD V1, 1
+            CALL expect
+
+            LD VF, #10
+            LD V4, #01
+            SUBN VF, V4
+            LD V0, VF
+            LD V1, 0
+            CALL expect
+
+            LD VF, #02
+            SHR VF, VF
+            LD V0, VF
+            LD V1, 0
+            CALL expect
+
+            LD VF, #81
+            SHL VF, VF
+            LD V0, VF
+            LD V1, 1
+            CALL expect
+
+; 7XNN never touches VF, even when it overflows
+            LD VF, #55
+            LD V0, #FF
+            ADD V0, 2
+            LD V2, VF
+            LD V1, #01
+            LD V3, #55
+            CALL expect
+
+; 8XY0 to 8XY3
+            LD V0, #F0
+            LD V4, #0F
+            OR V0, V4
+            LD V1, #FF
+            LD V2, 0
+            LD V3, 0
+            CALL expect
+
+            LD V0, #3C
+            LD V4, #0F
+            AND V0, V4
+            LD V1, #0C
+            CALL expect
+
+            LD V0, #FF
+            LD V4, #0F
+            XOR V0, V4
+            LD V1, #F0
+            CALL expect
+
+            LD V4, #A5
+            LD V0, V4
+            LD V1, #A5
+            CALL expect
+
+            JP halt
+
+            INCLUDE "common.asm"
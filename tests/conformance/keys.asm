@@ -0,0 +1,60 @@
+; The keypad, with a tick for each check it passes. The test runner presses key A for FX0A
+; while it waits, then holds key B down for a while.
+
+            CLS
+            CALL home
+            LD V2, 0
+            LD V3, 0
+            LD V4, #5
+            LD V6, #B
+
+; With no keys down EX9E does not skip and EXA1 does.
+            LD V0, 0
+            SKP V4
+            LD V0, 1
+            LD V1, 1
+            CALL expect
+
+            LD V0, 1
+            SKNP V4
+            LD V0, 0
+            CALL expect
+
+; FX0A waits for a key and stores which one it was.
+            LD V5, K
+            LD V0, V5
+            LD V1, #A
+            CALL expect
+
+; Once key B is down EX9E skips and EXA1 does not, while other keys stay up.
+held:       SKP V6
+            JP held
+
+            LD V0, 1
+            SKP V6
+            LD V0, 0
+            LD V1, 1
+            CALL expect
+
+            LD V0, 0
+            SKNP V6
+            LD V0, 1
+            CALL expect
+
+            LD V0, 0
+            SKP V4
+            LD V0, 1
+            CALL expect
+
+; After it is let go EX9E stops skipping again.
+released:   SKNP V6
+            JP released
+
+            LD V0, 0
+            SKP V6
+            LD V0, 1
+            CALL expect
+
+            JP halt
+
+            INCLUDE "common.asm"
@@ -0,0 +1,148 @@
+; The instructions not covered by the other ROMs, with a tick for each check they pass. None
+; of them depend on the quirks.
+
+; 00E0 clears what was drawn before it, which is checked first so as not to wipe the results.
+            LD V4, 10
+            LD V5, 31
+            LD I, dot
+            DRW V4, V5, 1
+            CLS
+            DRW V4, V5, 1
+            LD V6, VF
+            DRW V4, V5, 1
+            CALL home
+            LD V0, V6
+            LD V1, 0
+            LD V2, 0
+            LD V3, 0
+            CALL expect
+
+; 3XNN, 4XNN, 5XY0 and 9XY0, each both skipping and not.
+            LD V4, #12
+            LD V5, #12
+            LD V1, 1
+
+            LD V0, 1
+            SE V4, #12
+            LD V0, 0
+            CALL expect
+
+            LD V0, 0
+            SE V4, #13
+            LD V0, 1
+            CALL expect
+
+            LD V0, 1
+            SNE V4, #13
+            LD V0, 0
+            CALL expect
+
+            LD V0, 0
+            SNE V4, #12
+            LD V0, 1
+            CALL expect
+
+            LD V0, 1
+            SE V4, V5
+            LD V0, 0
+            CALL expect
+
+            LD V0, 0
+            SNE V4, V5
+            LD V0, 1
+            CALL expect
+
+            LD V5, #13
+            LD V0, 0
+            SE V4, V5
+            LD V0, 1
+            CALL expect
+
+            LD V0, 1
+            SNE V4, V5
+            LD V0, 0
+            CALL expect
+
+; 1NNN, 2NNN and 00EE, including a nested call.
+            LD V0, 1
+            JP jumped
+            LD V0, 0
+jumped:     CALL expect
+
+            LD V0, 0
+            CALL outer
+            LD V1, 2
+            CALL expect
+
+; ANNN, FX1E and FX29 point I at the right bytes.
+            LD I, data
+            LD V0, [I]
+            LD V1, #5A
+            CALL expect
+
+            LD I, data
+            LD V4, 1
+            ADD I, V4
+            LD V0, [I]
+            LD V1, #A5
+            CALL expect
+
+            LD V4, 1
+            LD F, V4
+            LD V1, [I]
+            LD V2, V1
+            LD V1, #20
+            LD V3, #60
+            CALL expect
+
+; FX55 and FX65 store and load several registers at once.
+            LD V0, #11
+            LD V1, #22
+            LD V2, #33
+            LD I, scratch
+            LD [I], V2
+            LD V0, 0
+            LD V2, 0
+            LD I, scratch
+            LD V2, [I]
+            LD V1, #11
+            LD V3, #33
+            CALL expect
+
+; DXYN sets VF when it turns a pixel off, and not otherwise.
+            LD V4, 10
+            LD V5, 31
+            LD I, dot
+            DRW V4, V5, 1
+            LD V2, VF
+            DRW V4, V5, 1
+            LD V0, VF
+            LD V1, 1
+            LD V3, 0
+            CALL expect
+
+; CXNN masks the random number.
+            RND V0, #00
+            LD V1, 0
+            LD V2, 0
+            CALL expect
+
+            RND V0, #0F
+            LD V4, #F0
+            AND V0, V4
+            CALL expect
+
+            JP halt
+
+outer:      ADD V0, 1
+            CALL inner
+            RET
+
+inner:      ADD V0, 1
+            RET
+
+data:       DB #5A, #A5
+scratch:    DB 0, 0, 0
+dot:        DB #80
+
+            INCLUDE "common.asm"
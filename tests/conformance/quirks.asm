@@ -0,0 +1,100 @@
+; The behaviours that differ between platforms, each shown as a hex digit:
+;
+;   1-3  VF after 8XY1, 8XY2 and 8XY3, which start with VF = 1: 0 if they reset it
+;   4    SHR V0, V1 with V0 = #10 and V1 = #02: 1 when VY is shifted, 8 when VX is
+;   5    SHL V0, V1 with V0 = #04 and V1 = #02: 4 when VY is shifted, 8 when VX is
+;   6-7  how far FX55 and FX65 with X = 1 move I: 0, 1 or 2
+;   8    BNNN: 0 when it adds V0, 1 when it adds VX
+;   9    1 when sprites wrap around the edges, 0 when they are clipped
+;   10   1 when DXYN waits for the next frame
+
+            CLS
+            CALL home
+
+            LD VF, 1
+            OR V0, V1
+            LD V0, VF
+            CALL show
+
+            LD VF, 1
+            AND V0, V1
+            LD V0, VF
+            CALL show
+
+            LD VF, 1
+            XOR V0, V1
+            LD V0, VF
+            CALL show
+
+            LD V0, #10
+            LD V1, #02
+            SHR V0, V1
+            CALL show
+
+            LD V0, #04
+            LD V1, #02
+            SHL V0, V1
+            CALL show
+
+; The scratch bytes hold their own offsets, and storing or loading V0 = 0 and V1 = 1 leaves
+; them that way, so reading the byte I points to afterwards tells how far it moved.
+            LD V0, 0
+            LD V1, 1
+            LD I, scratch
+            LD [I], V1
+            LD V0, [I]
+            CALL show
+
+            LD I, scratch
+            LD V1, [I]
+            LD V0, [I]
+            CALL show
+
+; The table is at #2XX, so the jump adds either V0 or V2.
+            LD V0, 0
+            LD V2, 4
+            JP V0, table
+jumped:     CALL show
+
+; Draw a line across the right edge of the bottom row and a pixel at its left end, then take
+; them away again.
+            LD V4, 60
+            LD V5, 31
+            LD V6, 0
+            LD I, line
+            DRW V4, V5, 1
+            LD I, dot
+            DRW V6, V5, 1
+            LD V0, VF
+            DRW V6, V5, 1
+            LD I, line
+            DRW V4, V5, 1
+            CALL show
+
+; Waiting for each of four sprites to be drawn takes at least three frames.
+            LD V4, 10
+            LD DT, V4
+            LD I, blank
+            DRW V6, V6, 1
+            DRW V6, V6, 1
+            DRW V6, V6, 1
+            DRW V6, V6, 1
+            LD V4, DT
+            LD V0, 8
+            SUBN V4, V0
+            LD V0, VF
+            CALL show
+
+            JP halt
+
+table:      LD V0, 0
+            JP jumped
+            LD V0, 1
+            JP jumped
+
+scratch:    DB 0, 1, 2, 3
+line:       DB #FF
+dot:        DB #80
+blank:      DB #00
+
+            INCLUDE "common.asm"
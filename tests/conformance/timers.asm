@@ -0,0 +1,74 @@
+; The delay timer, with a tick for each check it passes. Reading the timer can land either side
+; of a 60 Hz tick, so values one lower than the ideal are accepted too.
+
+            CLS
+            CALL home
+            LD V2, 0
+            LD V3, 0
+
+; FX07 reads back what FX15 set.
+            LD V4, #20
+            LD DT, V4
+            LD V5, DT
+            LD V0, 0
+            SNE V5, #20
+            LD V0, 1
+            SNE V5, #1F
+            LD V0, 1
+            LD V1, 1
+            CALL expect
+
+; It counts down to zero...
+            LD V4, 3
+            LD DT, V4
+countdown:  LD V0, DT
+            SE V0, 0
+            JP countdown
+            LD V1, 0
+            CALL expect
+
+; ...and stays there.
+            CALL busy
+            LD V0, DT
+            LD V1, 0
+            CALL expect
+
+; It keeps counting while the program is busy, without running out too soon.
+            LD V4, 60
+            LD DT, V4
+            CALL busy
+            LD V5, DT
+            LD V0, 1
+            SNE V5, 60
+            LD V0, 0
+            SNE V5, 0
+            LD V0, 0
+            LD V1, 1
+            CALL expect
+
+; Setting the sound timer leaves the delay timer alone.
+            LD V4, #40
+            LD DT, V4
+            LD V4, #10
+            LD ST, V4
+            LD V5, DT
+            LD V0, 0
+            SNE V5, #40
+            LD V0, 1
+            SNE V5, #3F
+            LD V0, 1
+            LD V1, 1
+            CALL expect
+
+            LD V4, 0
+            LD ST, V4
+            JP halt
+
+; Spin through a few hundred instructions, which is several frames on any interpreter.
+busy:       LD V4, 0
+spin:       ADD V4, 1
+            SE V4, 100
+            JP spin
+            RET
+
+            INCLUDE "common.asm"